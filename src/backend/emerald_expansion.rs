@@ -0,0 +1,385 @@
+//! Backend for the pokeemerald-expansion decomp project.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use super::{GameBackend, Sprite};
+use crate::{
+    cli::EmeraldExpansionOption,
+    database::{
+        pokedex::{Pokedex, PokemonDatabaseEntry},
+        species::{SPECIES_CONSTANT_PREFIX, SpeciesId, default_aliases, to_constant_name, to_id},
+    },
+    encounters::{self, Encounters},
+    engine::validation::ValidationReport,
+    parties::{self, Parties, Trainer, emerald_expansion::PartySources},
+    pristine::Pristine,
+};
+
+/// Species constant of a species or forme display name, following the
+/// project naming convention.
+fn name_constant(name: &str) -> String {
+    format!("{}{}", SPECIES_CONSTANT_PREFIX, to_constant_name(name))
+}
+
+/// Species constants defined by the project, used to spell the formes whose
+/// constant is not their display name such as `SPECIES_BURMY_SANDY_CLOAK`.
+#[derive(Debug, Clone, Default)]
+pub struct SpeciesConstants {
+    /// Constants keyed by their id, `burmysandycloak` for the above
+    defined: HashMap<String, String>,
+}
+
+impl SpeciesConstants {
+    /// Reads the `#define SPECIES_*` lines of the species constants header.
+    pub fn parse(header: &str) -> Self {
+        let defined = header
+            .lines()
+            .filter_map(|line| line.trim_start().strip_prefix("#define"))
+            .filter_map(|definition| definition.split_whitespace().next())
+            .filter_map(|constant| {
+                let name = constant.strip_prefix(SPECIES_CONSTANT_PREFIX)?;
+                Some((to_id(name), constant.to_owned()))
+            })
+            .collect();
+
+        Self { defined }
+    }
+
+    /// Loads the species constants header, constants following the naming
+    /// convention when the project has no such header.
+    pub fn load(path: &Path) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(header) => Self::parse(&header),
+            Err(error) => {
+                tracing::warn!(
+                    "{}: {error}, species constants are not checked",
+                    path.display()
+                );
+                Self::default()
+            }
+        }
+    }
+
+    /// `SPECIES_` constant of a species, such as `SPECIES_MR_MIME`.
+    pub fn species_constant(&self, entry: &PokemonDatabaseEntry) -> String {
+        self.constant(&entry.name)
+    }
+
+    /// Species constant of a species or forme display name. The constant the
+    /// project defines for the name is preferred over the one it defines for
+    /// an alias of the name.
+    pub fn constant(&self, name: &str) -> String {
+        let id = to_id(name);
+
+        self.defined
+            .get(&id)
+            .or_else(|| default_aliases(&id).find_map(|alias| self.defined.get(alias)))
+            .cloned()
+            .unwrap_or_else(|| name_constant(name))
+    }
+}
+
+/// Species constants header, relative to the project.
+const SPECIES_HEADER_PATH: &str = "include/constants/species.h";
+
+/// Prefix of the item constants, such as `ITEM_LIFE_ORB`.
+const ITEM_CONSTANT_PREFIX: &str = "ITEM_";
+
+pub struct EmeraldExpansion {
+    options: EmeraldExpansionOption,
+    /// Lines the parties were loaded from, written back for the trainers and
+    /// Pokemon left untouched
+    party_sources: PartySources,
+    /// Read from the species constants header on first use
+    species_constants: OnceLock<SpeciesConstants>,
+}
+
+impl EmeraldExpansion {
+    pub fn new(options: EmeraldExpansionOption) -> Self {
+        Self {
+            options,
+            party_sources: PartySources::new(),
+            species_constants: OnceLock::new(),
+        }
+    }
+
+    fn species_constants(&self) -> &SpeciesConstants {
+        self.species_constants.get_or_init(|| {
+            SpeciesConstants::load(&self.options.project_path.join(SPECIES_HEADER_PATH))
+        })
+    }
+}
+
+impl GameBackend for EmeraldExpansion {
+    fn project_path(&self) -> &Path {
+        &self.options.project_path
+    }
+
+    fn managed_files(&self) -> Vec<PathBuf> {
+        vec![
+            self.options.trainers_party_file_path.clone(),
+            self.options.encounters_file_path.clone(),
+        ]
+    }
+
+    fn parties_path(&self) -> PathBuf {
+        self.options
+            .project_path
+            .join(&self.options.trainers_party_file_path)
+    }
+
+    fn encounters_path(&self) -> PathBuf {
+        self.options
+            .project_path
+            .join(&self.options.encounters_file_path)
+    }
+
+    fn trainer_order_path(&self) -> Option<PathBuf> {
+        if self.options.no_trainer_order {
+            return None;
+        }
+
+        Some(
+            self.options
+                .project_path
+                .join(&self.options.trainer_order_path),
+        )
+    }
+
+    fn species_path(&self) -> Option<PathBuf> {
+        Some(self.options.project_path.join(SPECIES_HEADER_PATH))
+    }
+
+    fn load_parties(
+        &mut self,
+        pristine: &Pristine,
+        pokedex: &Pokedex,
+        report: &mut ValidationReport,
+    ) -> eyre::Result<Parties> {
+        let parties_file_path = self.parties_path();
+        let dialect = self.options.party_dialect;
+
+        let content = pristine.read_to_string(&self.options.trainers_party_file_path)?;
+
+        let (parties, sources) = parties::emerald_expansion::from_emerald_expansion_format(
+            &content,
+            dialect,
+            pokedex,
+            &mut report.file(&parties_file_path),
+        )?;
+
+        // Unchanged trainers are written back verbatim, anything else means
+        // some of the file is not understood and will be reformatted.
+        let written = parties::emerald_expansion::to_emerald_expansion_format(
+            &parties, &sources, dialect, pokedex,
+        )?;
+        if written != content {
+            tracing::warn!(
+                "{} does not round-trip, untouched parts of it may be reformatted",
+                parties_file_path.display()
+            );
+        }
+        self.party_sources = sources;
+
+        Ok(parties)
+    }
+
+    fn load_project_parties(
+        &self,
+        pokedex: &Pokedex,
+        report: &mut ValidationReport,
+    ) -> eyre::Result<Parties> {
+        let parties_file_path = self.parties_path();
+        let content = std::fs::read_to_string(&parties_file_path)?;
+
+        let (parties, _) = parties::emerald_expansion::from_emerald_expansion_format(
+            &content,
+            self.options.party_dialect,
+            pokedex,
+            &mut report.file(&parties_file_path),
+        )?;
+
+        Ok(parties)
+    }
+
+    fn save_parties(
+        &self,
+        parties: &Parties,
+        pokedex: &Pokedex,
+        pristine: &mut Pristine,
+    ) -> eyre::Result<()> {
+        let result = parties::emerald_expansion::to_emerald_expansion_format(
+            parties,
+            &self.party_sources,
+            self.options.party_dialect,
+            pokedex,
+        )?;
+
+        pristine.write(&self.options.trainers_party_file_path, result.as_bytes())
+    }
+
+    fn load_encounters(&self, pristine: &Pristine) -> eyre::Result<Box<dyn Encounters>> {
+        let content = pristine.read_to_string(&self.options.encounters_file_path)?;
+
+        let mut encounters: encounters::emerald_expansion::Encounters =
+            serde_json::from_str(&content)?;
+        encounters.species_constants = self.species_constants().clone();
+        Ok(Box::new(encounters))
+    }
+
+    fn save_encounters(
+        &self,
+        encounters: &dyn Encounters,
+        pristine: &mut Pristine,
+    ) -> eyre::Result<()> {
+        let result = encounters.serialize()?;
+
+        pristine.write(&self.options.encounters_file_path, result.as_bytes())
+    }
+
+    /// Reads the `#define SPECIES_*` lines of the species constants header,
+    /// ignoring the constants that are not species such as `SPECIES_EGG`.
+    fn project_species(&self, pokedex: &Pokedex) -> eyre::Result<Option<BTreeSet<SpeciesId>>> {
+        let Some(path) = self.species_path() else {
+            return Ok(None);
+        };
+        let content = std::fs::read_to_string(&path)
+            .map_err(|error| eyre::eyre!("{}: {error}", path.display()))?;
+
+        let species = content
+            .lines()
+            .filter_map(|line| line.trim_start().strip_prefix("#define"))
+            .filter_map(|definition| definition.split_whitespace().next())
+            .filter(|name| name.starts_with(SPECIES_CONSTANT_PREFIX))
+            .filter_map(|name| pokedex.resolve(name).ok())
+            .collect();
+
+        Ok(Some(species))
+    }
+
+    /// Reads the `#define ITEM_*` lines of the item constants header, items
+    /// not being checked when the project has no such header.
+    fn project_items(&self) -> eyre::Result<Option<BTreeSet<String>>> {
+        let path = self.options.project_path.join("include/constants/items.h");
+        if !std::fs::exists(&path)? {
+            tracing::warn!("{} not found, items are not checked", path.display());
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)?;
+
+        let items = content
+            .lines()
+            .filter_map(|line| line.trim_start().strip_prefix("#define"))
+            .filter_map(|definition| definition.split_whitespace().next())
+            .filter_map(|name| name.strip_prefix(ITEM_CONSTANT_PREFIX))
+            .map(to_id)
+            .collect();
+
+        Ok(Some(items))
+    }
+
+    fn species_constant(&self, pokedex: &Pokedex, species: &SpeciesId) -> eyre::Result<String> {
+        Ok(self
+            .species_constants()
+            .species_constant(pokedex.entry(species.as_str())?))
+    }
+
+    fn trainer_sprite(&self, trainer: &Trainer) -> eyre::Result<Sprite> {
+        let name = trainer.pic.to_lowercase().replace(' ', "_");
+        let path = self
+            .options
+            .project_path
+            .join("graphics/trainers/front_pics")
+            .join(format!("{}.png", name));
+
+        Ok(Sprite {
+            path: std::fs::exists(&path)?.then_some(path),
+            name,
+        })
+    }
+
+    fn pokemon_sprite(&self, pokedex: &Pokedex, species: &SpeciesId) -> eyre::Result<Sprite> {
+        let constant = self.species_constant(pokedex, species)?;
+        let name = constant
+            .strip_prefix(SPECIES_CONSTANT_PREFIX)
+            .unwrap_or(&constant)
+            .to_lowercase();
+        let directory = self
+            .options
+            .project_path
+            .join("graphics/pokemon")
+            .join(&name);
+
+        let mut path = None;
+        for candidate in ["anim_front.png", "front.png"] {
+            if std::fs::exists(directory.join(candidate))? {
+                path = Some(directory.join(candidate));
+                break;
+            }
+        }
+
+        Ok(Sprite { name, path })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::database::pokedex::load_pokedex;
+
+    const SPECIES_HEADER: &str = "\
+#define SPECIES_NONE 0
+#define SPECIES_MR_MIME 122
+#define SPECIES_BURMY 412
+#define SPECIES_BURMY_PLANT_CLOAK SPECIES_BURMY
+#define SPECIES_SHELLOS 422
+#define SPECIES_SHELLOS_WEST_SEA SPECIES_SHELLOS
+#define SPECIES_MEOWSTIC_MALE 678
+#define SPECIES_MEOWSTIC SPECIES_MEOWSTIC_MALE
+#define SPECIES_BURMY_SANDY_CLOAK 1200
+#define SPECIES_BURMY_TRASH_CLOAK 1201
+#define SPECIES_SHELLOS_EAST_SEA 1202
+#define SPECIES_MEOWSTIC_FEMALE 1203
+";
+
+    #[test]
+    fn spells_formes_as_the_project_defines_them() {
+        let pokedex = load_pokedex(Path::new("pokedex.json")).unwrap();
+        let constants = SpeciesConstants::parse(SPECIES_HEADER);
+
+        for (name, constant) in [
+            ("Mr. Mime", "SPECIES_MR_MIME"),
+            ("Burmy", "SPECIES_BURMY"),
+            ("Burmy-Sandy", "SPECIES_BURMY_SANDY_CLOAK"),
+            ("Burmy-Trash", "SPECIES_BURMY_TRASH_CLOAK"),
+            ("Shellos", "SPECIES_SHELLOS"),
+            ("Shellos-East", "SPECIES_SHELLOS_EAST_SEA"),
+            ("Meowstic", "SPECIES_MEOWSTIC"),
+            ("Meowstic-F", "SPECIES_MEOWSTIC_FEMALE"),
+        ] {
+            assert_eq!(constants.constant(name), constant, "{}", name);
+            // The constant written back is read as the same species
+            let id = pokedex.resolve(name).unwrap();
+            assert_eq!(pokedex.resolve(constant).unwrap(), id, "{}", constant);
+        }
+
+        let meowstic_f = pokedex.entry("meowsticf").unwrap();
+        assert_eq!(
+            constants.species_constant(meowstic_f),
+            "SPECIES_MEOWSTIC_FEMALE"
+        );
+    }
+
+    #[test]
+    fn follows_the_naming_convention_without_a_header() {
+        let constants = SpeciesConstants::default();
+        assert_eq!(constants.constant("Mr. Mime"), "SPECIES_MR_MIME");
+        assert_eq!(constants.constant("Burmy-Sandy"), "SPECIES_BURMY_SANDY");
+    }
+}
@@ -0,0 +1,107 @@
+//! Everything that depends on the layout of a given decomp project.
+//!
+//! The engine only works on the intermediate representation, and goes through
+//! a [`GameBackend`] to read it from and write it into the project, to find
+//! sprites and to name constants. Supporting a new decomp project means adding
+//! a module implementing it, and a [`ProjectOption`] variant to select it.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cli::ProjectOption,
+    database::{pokedex::Pokedex, species::SpeciesId},
+    encounters::Encounters,
+    engine::{
+        trainer_order::{self, TrainerOrder},
+        validation::ValidationReport,
+    },
+    parties::{Parties, Trainer},
+    pristine::Pristine,
+};
+
+pub mod emerald_expansion;
+
+/// A sprite of the project, and the name it is exported under in the
+/// documentation.
+pub struct Sprite {
+    pub name: String,
+    /// None if the project has no such sprite.
+    pub path: Option<PathBuf>,
+}
+
+pub trait GameBackend {
+    fn project_path(&self) -> &Path;
+    /// Files written by the engine, relative to the project path.
+    fn managed_files(&self) -> Vec<PathBuf>;
+    fn parties_path(&self) -> PathBuf;
+    fn encounters_path(&self) -> PathBuf;
+    /// None if the project comes without a trainer order.
+    fn trainer_order_path(&self) -> Option<PathBuf>;
+    /// File defining the species of the project, None if the backend cannot
+    /// tell which species the project has.
+    fn species_path(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// Loads the parties of the project as they were before the first run,
+    /// keeping whatever the backend needs to write them back.
+    fn load_parties(
+        &mut self,
+        pristine: &Pristine,
+        pokedex: &Pokedex,
+        report: &mut ValidationReport,
+    ) -> eyre::Result<Parties>;
+    /// Parties as the project has them now, generated or not.
+    fn load_project_parties(
+        &self,
+        pokedex: &Pokedex,
+        report: &mut ValidationReport,
+    ) -> eyre::Result<Parties>;
+    fn save_parties(
+        &self,
+        parties: &Parties,
+        pokedex: &Pokedex,
+        pristine: &mut Pristine,
+    ) -> eyre::Result<()>;
+    fn load_encounters(&self, pristine: &Pristine) -> eyre::Result<Box<dyn Encounters>>;
+    fn save_encounters(
+        &self,
+        encounters: &dyn Encounters,
+        pristine: &mut Pristine,
+    ) -> eyre::Result<()>;
+
+    fn load_trainer_order(&self) -> eyre::Result<Option<TrainerOrder>> {
+        self.trainer_order_path()
+            .map(|path| trainer_order::load_trainer_order(&path))
+            .transpose()
+    }
+
+    /// Species the project defines, None if the backend cannot tell.
+    fn project_species(&self, _pokedex: &Pokedex) -> eyre::Result<Option<BTreeSet<SpeciesId>>> {
+        Ok(None)
+    }
+
+    /// Items the project defines, by item ID, None if the backend cannot
+    /// tell.
+    fn project_items(&self) -> eyre::Result<Option<BTreeSet<String>>> {
+        Ok(None)
+    }
+
+    /// Name of the constant the project uses for a species.
+    fn species_constant(&self, pokedex: &Pokedex, species: &SpeciesId) -> eyre::Result<String>;
+    fn trainer_sprite(&self, trainer: &Trainer) -> eyre::Result<Sprite>;
+    fn pokemon_sprite(&self, pokedex: &Pokedex, species: &SpeciesId) -> eyre::Result<Sprite>;
+}
+
+impl ProjectOption {
+    pub fn backend(&self) -> Box<dyn GameBackend> {
+        match self {
+            ProjectOption::EmeraldExpansion(options) => {
+                Box::new(emerald_expansion::EmeraldExpansion::new(options.clone()))
+            }
+        }
+    }
+}
@@ -1,54 +1,310 @@
+//! Set bundles.
+//!
+//! A bundle maps species display names to lists of sets, in the Showdown sets
+//! format. Every field of a set is a list of options, one of which is picked
+//! when the set is generated, and every move slot is such a list too. Options
+//! are plain values, or `{"value": ..., "weight": ...}` objects to make some
+//! options more likely than others, plain values weighing 1. Sets themselves
+//! may have a `weight`, 1 by default, and sets or options weighing 0 are never
+//! picked.
+
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap, HashSet},
     path::{Path, PathBuf},
 };
 
-use rand::Rng;
-use serde::Deserialize;
+use rand::{Rng, RngExt};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    database::pokedex::PokemonDatabaseEntry,
+    database::{
+        moves::Movedex,
+        pokedex::{Pokedex, PokemonDatabaseEntry},
+        species::{SpeciesId, to_id},
+    },
+    engine::{
+        error::EngineError,
+        validation::{FileReport, ValidationReport},
+    },
     parties::party::{PokemonEVs, PokemonGender, PokemonIVs, PokemonSet},
+    profile::{BundleCheckPolicy, CheckLevel},
 };
 
 pub type Species = String;
 
-pub type SetBundle = HashMap<Species, Vec<PokemonBundleSet>>;
+/// Bundle as written on disk, keyed by species display name.
+pub type RawSetBundle = HashMap<Species, Vec<PokemonBundleSet>>;
+
+pub type SetBundle = HashMap<SpeciesId, Vec<PokemonBundleSet>>;
+
+/// Option of a set field, weighted or not.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum BundleOption<T> {
+    // Tried first, as a plain EV spread would take a weighted one too
+    Weighted { value: T, weight: u32 },
+    Plain(T),
+}
+
+impl<T> BundleOption<T> {
+    pub fn value(&self) -> &T {
+        match self {
+            Self::Weighted { value, .. } | Self::Plain(value) => value,
+        }
+    }
+
+    pub fn weight(&self) -> u32 {
+        match self {
+            Self::Weighted { weight, .. } => *weight,
+            Self::Plain(_) => 1,
+        }
+    }
+}
+
+pub type BundleOptions<T> = Vec<BundleOption<T>>;
+
+/// Whether no option of `options` can ever be picked.
+fn weighs_nothing<T>(options: &[BundleOption<T>]) -> bool {
+    options.iter().all(|option| option.weight() == 0)
+}
+
+/// Picks one of `options` uniformly, None when there are none.
+pub fn pick<'a, T, R: Rng + ?Sized>(options: &'a [T], rng: &mut R) -> Option<&'a T> {
+    if options.is_empty() {
+        return None;
+    }
+
+    options.get(rng.random_range(0..options.len()))
+}
+
+/// Picks one of `options` with a probability proportional to its weight, None
+/// when they all weigh 0.
+pub fn pick_weighted<'a, T, R: Rng + ?Sized>(
+    options: &'a [T],
+    weight: impl Fn(&T) -> u32,
+    rng: &mut R,
+) -> Option<&'a T> {
+    let total: u64 = options.iter().map(|option| u64::from(weight(option))).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut target = rng.random_range(0..total);
+    options.iter().find(|option| {
+        let weight = u64::from(weight(option));
+        if target < weight {
+            return true;
+        }
+        target -= weight;
+        false
+    })
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn is_default_weight(weight: &u32) -> bool {
+    *weight == 1
+}
 
 // TODO: Implement tera types, dynamax level
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct PokemonBundleSet {
-    #[expect(dead_code)]
+    /// Showdown format the set comes from, such as "lc", "ou" or "doublesou"
     pub format: String,
-    #[expect(dead_code)]
     pub name: String,
-    pub moves: Vec<Vec<String>>,
-    pub item: Vec<String>,
-    pub nature: Option<Vec<String>>,
-    pub ability: Option<Vec<String>>,
-    #[serde(default)]
-    pub evs: Option<Vec<PokemonEVs>>,
-    #[serde(default)]
-    pub ivs: Option<Vec<PokemonIVs>>,
-    #[serde(rename = "teratypes")]
-    pub tera_types: Option<Vec<String>>,
+    /// How likely the set is to be picked among the sets of its species
+    #[serde(default = "default_weight", skip_serializing_if = "is_default_weight")]
+    pub weight: u32,
+    pub moves: Vec<BundleOptions<String>>,
+    pub item: BundleOptions<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nature: Option<BundleOptions<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ability: Option<BundleOptions<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evs: Option<BundleOptions<PokemonEVs>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ivs: Option<BundleOptions<PokemonIVs>>,
+    #[serde(rename = "teratypes", skip_serializing_if = "Option::is_none")]
+    pub tera_types: Option<BundleOptions<String>>,
+    /// Set of a Gigantamax forme given to its base species
+    #[serde(skip)]
+    pub gigantamax: bool,
+}
+
+/// What bundle sets are checked against when loaded.
+pub struct BundleChecks<'a> {
+    policy: &'a BundleCheckPolicy,
+    pokedex: &'a Pokedex,
+    movedex: Option<&'a Movedex>,
+    /// Item IDs of the project, items are not checked when None
+    items: Option<&'a BTreeSet<String>>,
+    /// IDs of the abilities of every species of the pokedex
+    abilities: HashSet<String>,
+}
+
+impl<'a> BundleChecks<'a> {
+    pub fn new(
+        policy: &'a BundleCheckPolicy,
+        pokedex: &'a Pokedex,
+        movedex: Option<&'a Movedex>,
+        items: Option<&'a BTreeSet<String>>,
+    ) -> Self {
+        let abilities = pokedex
+            .values()
+            .flat_map(|entry| entry.abilities.values())
+            .map(|ability| to_id(ability))
+            .collect();
+
+        Self {
+            policy,
+            pokedex,
+            movedex,
+            items,
+            abilities,
+        }
+    }
 }
 
 impl PokemonBundleSet {
+    /// Reports every problem of the set at the level the policy gives it.
+    /// Moves missing from the move database are always errors.
+    fn validate(&self, species: &str, checks: &BundleChecks, report: &mut FileReport) {
+        let policy = checks.policy;
+        let entity = format!("{} set \"{}\"", species, self.name);
+
+        if let Some(movedex) = checks.movedex {
+            for r#move in self.moves.iter().flatten() {
+                if let Err(error) = movedex.entry(r#move.value()) {
+                    report.push(&entity, error);
+                }
+            }
+        }
+
+        if self.moves.len() > 4 {
+            report.push_at(
+                policy.move_slots,
+                &entity,
+                EngineError::TooManyMoveSlots(self.moves.len()),
+            );
+        }
+
+        let mut empty_fields: Vec<String> = self
+            .moves
+            .iter()
+            .enumerate()
+            .filter(|(_, moves)| weighs_nothing(moves))
+            .map(|(i, _)| format!("move {}", i + 1))
+            .collect();
+        let optional_fields = [
+            ("nature", self.nature.as_deref().map(weighs_nothing)),
+            ("ability", self.ability.as_deref().map(weighs_nothing)),
+            ("evs", self.evs.as_deref().map(weighs_nothing)),
+            ("ivs", self.ivs.as_deref().map(weighs_nothing)),
+            ("teratypes", self.tera_types.as_deref().map(weighs_nothing)),
+        ];
+        empty_fields.extend(
+            optional_fields
+                .into_iter()
+                .filter(|(_, empty)| *empty == Some(true))
+                .map(|(field, _)| field.to_owned()),
+        );
+        for field in empty_fields {
+            report.push_at(
+                policy.empty_options,
+                &entity,
+                EngineError::EmptyBundleOptions { field },
+            );
+        }
+
+        let ev_spreads = self.evs.iter().flatten().map(BundleOption::value);
+        for (i, evs) in ev_spreads.enumerate() {
+            let field = format!("EV spread {}", i + 1);
+            Self::check_stats(evs, &field, 252, policy.evs, &entity, report);
+            let total = evs
+                .stats()
+                .into_iter()
+                .filter_map(|(_, value)| value)
+                .map(u32::from)
+                .sum();
+            if total > 510 {
+                report.push_at(
+                    policy.evs,
+                    &entity,
+                    EngineError::EvTotalTooHigh { field, total },
+                );
+            }
+        }
+        let iv_spreads = self.ivs.iter().flatten().map(BundleOption::value);
+        for (i, ivs) in iv_spreads.enumerate() {
+            let field = format!("IV spread {}", i + 1);
+            Self::check_stats(ivs, &field, 31, policy.ivs, &entity, report);
+        }
+
+        for ability in self.ability.iter().flatten().map(BundleOption::value) {
+            if !checks.abilities.contains(&to_id(ability)) {
+                report.push_at(
+                    policy.unknown_abilities,
+                    &entity,
+                    EngineError::UnknownAbility(ability.clone()),
+                );
+            }
+        }
+        if let Some(items) = checks.items {
+            for item in self.item.iter().map(BundleOption::value) {
+                if !items.contains(&to_id(item)) {
+                    report.push_at(
+                        policy.unknown_items,
+                        &entity,
+                        EngineError::UnknownItem(item.clone()),
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_stats(
+        spread: &PokemonIVs,
+        field: &str,
+        max: u8,
+        level: CheckLevel,
+        entity: &str,
+        report: &mut FileReport,
+    ) {
+        for (stat, value) in spread.stats() {
+            if let Some(value) = value
+                && value > max
+            {
+                report.push_at(
+                    level,
+                    entity,
+                    EngineError::StatTooHigh {
+                        field: field.to_owned(),
+                        stat,
+                        value,
+                        max,
+                    },
+                );
+            }
+        }
+    }
+
+    fn pick_option<R: Rng + ?Sized, T: Clone>(
+        options: &[BundleOption<T>],
+        rng: &mut R,
+    ) -> Option<T> {
+        pick_weighted(options, BundleOption::weight, rng).map(|option| option.value().clone())
+    }
+
     fn pick_one_if_some<R: Rng + ?Sized, T: Clone>(
-        field: &Option<Vec<T>>,
+        field: &Option<BundleOptions<T>>,
         rng: &mut R,
     ) -> Option<T> {
-        if let Some(content) = field {
-            Some(
-                content
-                    .get(rng.next_u32() as usize % content.len())
-                    .expect("modulo len")
-                    .clone(),
-            )
-        } else {
-            None
-        }
+        field
+            .as_ref()
+            .and_then(|options| Self::pick_option(options, rng))
     }
 
     pub fn generate_set<R: Rng + ?Sized>(
@@ -63,24 +319,23 @@ impl PokemonBundleSet {
         let mut move_3 = None;
         let mut move_4 = None;
 
-        for (i, moves) in self.moves.iter().enumerate() {
-            let r#move = moves
-                .get(rng.next_u32() as usize % moves.len())
-                .expect("modulo len");
+        // Empty and extra slots are only there if the bundle checks allow them
+        let slots = self
+            .moves
+            .iter()
+            .filter(|moves| !weighs_nothing(moves))
+            .take(4);
+        for (i, moves) in slots.enumerate() {
+            let r#move = Self::pick_option(moves, rng);
             match i {
-                0 => move_1 = Some(r#move.clone()),
-                1 => move_2 = Some(r#move.clone()),
-                2 => move_3 = Some(r#move.clone()),
-                3 => move_4 = Some(r#move.clone()),
-                _ => panic!("More than four moves in bundle set ???"),
+                0 => move_1 = r#move,
+                1 => move_2 = r#move,
+                2 => move_3 = r#move,
+                _ => move_4 = r#move,
             };
         }
 
-        let held_item = if !self.item.is_empty() {
-            self.item.get(rng.next_u32() as usize % self.item.len())
-        } else {
-            None
-        };
+        let held_item = Self::pick_option(&self.item, rng);
 
         let evs = if !disable_evs {
             Self::pick_one_if_some(&self.evs, rng)
@@ -91,25 +346,27 @@ impl PokemonBundleSet {
 
         let nature = Self::pick_one_if_some(&self.nature, rng);
         let mut ability = Self::pick_one_if_some(&self.ability, rng);
-        if let None = ability {
+        if ability.is_none() {
             ability = Some(db_entry.abilities["0"].clone())
         }
 
         let tera_type = Self::pick_one_if_some(&self.tera_types, rng);
 
+        // A u32 drawn below the male share of 2^32 makes a male
+        let gender = match db_entry.male_ratio() {
+            None => PokemonGender::None,
+            Some(male) if (rng.next_u32() as f64) < male as f64 * (u32::MAX as f64 + 1.0) => {
+                PokemonGender::Male
+            }
+            Some(_) => PokemonGender::Female,
+        };
+
         PokemonSet {
-            species: db_entry.name.clone(),
-            species_normalized: unidecode::unidecode(&db_entry.name)
-                .to_lowercase()
-                .replace('_', "")
-                .replace('\'', "")
-                .replace(". ", "_")
-                .replace('-', "_")
-                .replace(' ', "_")
-                .replace('.', "")
-                .replace(':', ""),
-            gender: PokemonGender::None,
-            held_item: held_item.cloned(),
+            nickname: None,
+            species: db_entry.id.clone(),
+            cosmetic_forme: None,
+            gender,
+            held_item,
             level: Some(level),
             ivs,
             evs,
@@ -119,7 +376,7 @@ impl PokemonBundleSet {
             nature,
             shiny: false,
             dynamax_level: None,
-            gigantamax: false,
+            gigantamax: self.gigantamax,
             tera_type,
             move_1,
             move_2,
@@ -130,37 +387,230 @@ impl PokemonBundleSet {
 }
 
 #[expect(dead_code)]
-pub fn load_bundle(path: &Path) -> eyre::Result<SetBundle> {
+pub fn load_bundle(path: &Path) -> eyre::Result<RawSetBundle> {
     let content = std::fs::read_to_string(path)?;
-    let bundle: SetBundle = serde_json::from_str(&content)?;
+    let bundle: RawSetBundle = serde_json::from_str(&content)?;
 
     Ok(bundle)
 }
 
-pub fn load_bundles(paths: impl AsRef<[PathBuf]>) -> eyre::Result<SetBundle> {
-    let mut bundles = vec![];
+/// Loads and merges bundles, reporting the problems of every set along the
+/// way. Sets of species missing from the pokedex are never used.
+pub fn load_bundles(
+    paths: impl AsRef<[PathBuf]>,
+    checks: &BundleChecks,
+    report: &mut ValidationReport,
+) -> eyre::Result<SetBundle> {
+    let mut merged_bundles: SetBundle = HashMap::new();
 
     for path in paths.as_ref().iter() {
         let content = std::fs::read_to_string(path)?;
-        let bundle: SetBundle = serde_json::from_str(&content)?;
+        let bundle: RawSetBundle = serde_json::from_str(&content)?;
 
-        bundles.push(bundle);
+        merge_bundle(bundle, checks, &mut report.file(path), &mut merged_bundles);
     }
 
-    let mut merged_bundles: SetBundle = HashMap::new();
+    Ok(merged_bundles)
+}
 
-    for bundle in bundles.iter() {
-        for (key, value) in bundle.iter() {
-            if merged_bundles.contains_key(key) {
-                merged_bundles
-                    .get_mut(key)
-                    .expect("Contains checked")
-                    .extend(value.clone());
-            } else {
-                merged_bundles.insert(key.to_owned(), value.clone());
-            }
+/// Reports the problems of every set of `bundle`, adding the sets that can be
+/// picked to `merged_bundles`.
+fn merge_bundle(
+    bundle: RawSetBundle,
+    checks: &BundleChecks,
+    report: &mut FileReport,
+    merged_bundles: &mut SetBundle,
+) {
+    // Species spelled differently may merge, their sets then keep the order
+    // of their spellings
+    let mut species: Vec<_> = bundle.into_iter().collect();
+    species.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut unknown_species = Vec::new();
+    for (name, sets) in species {
+        // Bundles may contain species that are not part of the pokedex, such
+        // as CAP pokemons
+        let Ok(id) = checks.pokedex.resolve(&name) else {
+            unknown_species.push(name);
+            continue;
+        };
+
+        for set in sets.iter() {
+            set.validate(&name, checks, report);
+        }
+        let sets: Vec<_> = sets.into_iter().filter(|set| set.weight > 0).collect();
+        if !sets.is_empty() {
+            merged_bundles.entry(id).or_default().extend(sets);
+        }
+    }
+    if !unknown_species.is_empty() {
+        report.push_at(
+            checks.policy.unknown_species,
+            "Species",
+            EngineError::UnknownBundleSpecies(unknown_species),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    use super::*;
+    use crate::database::{moves::parse_moves, pokedex::load_pokedex};
+
+    fn set(json: serde_json::Value) -> PokemonBundleSet {
+        let mut set = serde_json::json!({
+            "format": "gen9ou",
+            "name": "Test",
+            "moves": [["Tackle"]],
+            "item": ["Leftovers"],
+        });
+        set.as_object_mut()
+            .unwrap()
+            .extend(json.as_object().unwrap().clone());
+        serde_json::from_value(set).unwrap()
+    }
+
+    #[test]
+    fn picks_by_weight() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let options = [("never", 0), ("rare", 1), ("common", 3)];
+        let mut counts = HashMap::new();
+        for _ in 0..4000 {
+            let (name, _) = pick_weighted(&options, |&(_, weight)| weight, &mut rng).unwrap();
+            *counts.entry(*name).or_insert(0) += 1;
         }
+
+        assert_eq!(counts.get("never"), None);
+        assert!((800..1200).contains(&counts["rare"]), "{:?}", counts);
+        assert!((2800..3200).contains(&counts["common"]), "{:?}", counts);
     }
 
-    Ok(merged_bundles)
+    #[test]
+    fn picks_nothing_without_weight() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        assert_eq!(pick_weighted(&[0u32, 0], |&weight| weight, &mut rng), None);
+        assert_eq!(
+            pick_weighted(&[] as &[u32], |&weight| weight, &mut rng),
+            None
+        );
+        // Weights are summed without overflowing
+        assert_eq!(
+            pick_weighted(&[u32::MAX, u32::MAX], |&weight| weight, &mut rng),
+            Some(&u32::MAX)
+        );
+    }
+
+    #[test]
+    fn picks_uniformly() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        assert_eq!(pick(&[] as &[u32], &mut rng), None);
+
+        let mut counts = [0; 3];
+        for _ in 0..3000 {
+            counts[*pick(&[0, 1, 2], &mut rng).unwrap()] += 1;
+        }
+        assert!(
+            counts.iter().all(|count| (800..1200).contains(count)),
+            "{:?}",
+            counts
+        );
+    }
+
+    #[test]
+    fn generates_the_gender_of_the_species() {
+        let pokedex = load_pokedex(Path::new("pokedex.json")).unwrap();
+        let set = set(serde_json::json!({}));
+        let mut rng = SmallRng::seed_from_u64(0);
+        // Male, female and genderless counts
+        let mut genders = |species: &str| {
+            let mut counts = [0; 3];
+            for _ in 0..1000 {
+                let generated = set.generate_set(&pokedex[species], 50, &mut rng, false);
+                counts[match generated.gender {
+                    PokemonGender::Male => 0,
+                    PokemonGender::Female => 1,
+                    PokemonGender::None => 2,
+                }] += 1;
+            }
+            counts
+        };
+
+        assert_eq!(genders("tauros"), [1000, 0, 0]);
+        assert_eq!(genders("chansey"), [0, 1000, 0]);
+        assert_eq!(genders("magnemite"), [0, 0, 1000]);
+        // Bulbasaur is male 87.5% of the time
+        let [male, female, genderless] = genders("bulbasaur");
+        assert!((825..925).contains(&male), "{male} males");
+        assert_eq!((male + female, genderless), (1000, 0));
+    }
+
+    #[test]
+    fn reports_unknown_species_and_moves_and_weightless_options() {
+        let pokedex = load_pokedex(Path::new("pokedex.json")).unwrap();
+        let movedex = parse_moves(
+            r#"{"tackle": {
+                "num": 33, "name": "Tackle", "type": "Normal", "category": "Physical",
+                "basePower": 40, "accuracy": 100, "pp": 35, "target": "normal"
+            }}"#,
+        )
+        .unwrap();
+        let policy = BundleCheckPolicy {
+            unknown_species: CheckLevel::Deny,
+            ..Default::default()
+        };
+        let checks = BundleChecks::new(&policy, &pokedex, Some(&movedex), None);
+        let set = |name: &str, weight: u32, moves: serde_json::Value, nature_weight: u32| {
+            serde_json::json!({
+                "format": "gen9ou",
+                "name": name,
+                "weight": weight,
+                "moves": moves,
+                "item": ["Leftovers"],
+                "nature": [{"value": "Adamant", "weight": nature_weight}],
+            })
+        };
+        let bundle: RawSetBundle = serde_json::from_value(serde_json::json!({
+            "Geodude": [
+                set("Fine", 1, serde_json::json!([["Tackle"]]), 1),
+                set("Typo", 1, serde_json::json!([["Tackle", "Tackel"]]), 1),
+                set("Weightless", 1, serde_json::json!([["Tackle"]]), 0),
+                set("Never", 0, serde_json::json!([["Tackle"]]), 1),
+            ],
+            "Fakemon": [set("Fine", 1, serde_json::json!([["Tackle"]]), 1)],
+            "Missingno": [set("Fine", 1, serde_json::json!([["Tackle"]]), 1)],
+        }))
+        .unwrap();
+
+        let mut report = ValidationReport::default();
+        let mut merged = SetBundle::new();
+        merge_bundle(
+            bundle,
+            &checks,
+            &mut report.file(Path::new("test.json")),
+            &mut merged,
+        );
+
+        let problems: Vec<String> = report
+            .problems()
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                r#"test.json: Geodude set "Typo": Unknown move "Tackel""#,
+                r#"test.json: Geodude set "Weightless": Empty nature option list"#,
+                "test.json: Species: 2 species missing from the pokedex, their sets are never used: \
+                 Fakemon, Missingno",
+            ]
+        );
+        // Sets weighing nothing are left out
+        let names: Vec<_> = merged[&pokedex.resolve("Geodude").unwrap()]
+            .iter()
+            .map(|set| set.name.as_str())
+            .collect();
+        assert_eq!(names, ["Fine", "Typo", "Weightless"]);
+        assert_eq!(merged.len(), 1);
+    }
 }
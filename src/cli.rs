@@ -1,36 +1,133 @@
 use std::path::PathBuf;
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
 
-#[derive(Parser, Debug)]
+use crate::{
+    formats::smogon::SmogonData, parties::emerald_expansion::Dialect, profile::EncounterMode,
+};
+
+#[derive(Parser, Debug, Clone)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
     #[command(subcommand)]
-    pub project: ProjectOption,
+    pub command: Command,
     #[arg(long, value_enum, default_value_t = LogLevel::Debug)]
     pub log_level: LogLevel,
     /// Path to log file.
     #[arg(long, default_value = "log.log")]
     pub log_file: PathBuf,
+    /// Path to a TOML run profile, the flags below override it
+    #[arg(long)]
+    pub profile: Option<PathBuf>,
     /// Path to pokedex file
-    #[arg(long, default_value = "pokedex.json")]
-    pub pokedex: PathBuf,
+    #[arg(long)]
+    pub pokedex: Option<PathBuf>,
+    /// Path to a TOML table of additional species spellings (alias = "species")
+    #[arg(long)]
+    pub species_aliases: Option<PathBuf>,
+    /// Path to a Showdown moves file
+    #[arg(long)]
+    pub moves: Option<PathBuf>,
+    /// Path to a Showdown learnsets file
+    #[arg(long)]
+    pub learnsets: Option<PathBuf>,
     /// List of bundles to use
-    #[arg(short, long, default_values = vec![        
-        clap::builder::OsStr::from("bundles/default/gen6.bundle.json"),
-        clap::builder::OsStr::from("bundles/default/gen7.bundle.json"),
-        clap::builder::OsStr::from("bundles/default/gen8.bundle.json"),
-        clap::builder::OsStr::from("bundles/default/gen9.bundle.json"),
-    ])]
+    #[arg(short, long)]
     pub bundles: Vec<PathBuf>,
     #[arg(long, value_name = "disable-evs", default_value_t = false)]
     pub disable_evs: bool,
+    /// How wild encounters are replaced
+    #[arg(long, value_enum)]
+    pub encounter_mode: Option<EncounterMode>,
     /// Seed used for the random number generator
     #[arg(long, value_name = "seed")]
     pub seed: Option<u64>,
     /// Directory to output documentation to
-    #[arg(long, default_value = "output")]
-    pub output_directory: PathBuf,
+    #[arg(long)]
+    pub output_directory: Option<PathBuf>,
+    /// Overwrite the managed files edited by hand since the last run
+    #[arg(long, default_value_t = false)]
+    pub force: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Randomize a decompilation project
+    #[command(flatten)]
+    Generate(ProjectOption),
+    /// Put back the files of a project as they were before the first run
+    Restore {
+        #[command(subcommand)]
+        project: ProjectOption,
+    },
+    /// Tell whether each file managed by the engine is pristine, generated or
+    /// edited by hand
+    Status {
+        #[command(subcommand)]
+        project: ProjectOption,
+    },
+    /// Run again the generation described by a run manifest
+    Reproduce {
+        /// Path to the manifest written by a previous run
+        manifest: PathBuf,
+    },
+    /// Print the trainers of a project as Showdown pastes
+    Paste {
+        /// Only print the trainers of this trainer order split, in the trainer
+        /// order
+        #[arg(long)]
+        split: Option<String>,
+        /// Path to write the pastes to instead of the standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(subcommand)]
+        project: ProjectOption,
+    },
+    /// Inspect the run profile
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
+    /// Manage set bundles
+    Bundle {
+        #[command(subcommand)]
+        command: BundleCommand,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ProfileCommand {
+    /// Print the profile resolved from the profile file and the flags
+    Show,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum BundleCommand {
+    /// Convert a pkmn/smogon sets or stats file into a bundle
+    Import {
+        /// Path to the pkmn/smogon file
+        input: PathBuf,
+        /// Path to write the bundle to
+        #[arg(short, long)]
+        output: PathBuf,
+        /// What the pkmn/smogon file holds
+        #[arg(long, value_enum, default_value_t = SmogonData::Sets)]
+        data: SmogonData,
+        /// Format of a file holding a single format, such as "ou"
+        #[arg(long)]
+        format: Option<String>,
+        /// Formats to import, `*` standing for any text, every format when none
+        #[arg(long)]
+        include: Vec<String>,
+        /// Formats never to import, `*` standing for any text
+        #[arg(long)]
+        exclude: Vec<String>,
+        /// Path to a pkmn/smogon analyses file, only the sets it has an
+        /// analysis of are imported
+        #[arg(long)]
+        analyses: Option<PathBuf>,
+    },
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -54,32 +151,37 @@ impl From<LogLevel> for tracing::Level {
     }
 }
 
-#[derive(Args, Debug, Clone)]
+#[derive(Args, Debug, Clone, Serialize, Deserialize)]
 pub struct EmeraldExpansionOption {
-        /// Path to the decompilation project
-        #[arg(value_name = "path", default_value = "pokeemerald-expansion")]
-        pub project_path: PathBuf,
-        /// Path to trainers.party file (relative to project path)
-        #[arg(value_name = "trainers", default_value = "src/data/trainers.party")]
-        pub trainers_party_file_path: PathBuf,
-        /// Path to wild_encounters.json file (relative to project path)
-        #[arg(
-            value_name = "encounters",
-            default_value = "src/data/wild_encounters.json"
-        )]
-        pub encounters_file_path: PathBuf,
-        /// Encounters will be replaced globally instead of locally.
-        #[arg(long, value_name = "global-encounter-rng", default_value_t = false)]
-        pub global_encounter_randomization: bool,
-        /// Path to the file specifying trainer order (relative to project path)
-        #[arg(long, value_name = "trainer-order", default_value = "trainer_order.toml")]
-        pub trainer_order_path: PathBuf,
-        /// If no trainer order file available
-        #[arg(long, value_name = "no-trainer-order", default_value_t = false)]
-        pub no_trainer_order: bool,
+    /// Path to the decompilation project
+    #[arg(value_name = "path", default_value = "pokeemerald-expansion")]
+    pub project_path: PathBuf,
+    /// Path to trainers.party file (relative to project path)
+    #[arg(value_name = "trainers", default_value = "src/data/trainers.party")]
+    pub trainers_party_file_path: PathBuf,
+    /// Flavour of the trainers.party format used by the project
+    #[arg(long, value_enum, default_value_t = Dialect::EmeraldExpansion)]
+    #[serde(default)]
+    pub party_dialect: Dialect,
+    /// Path to wild_encounters.json file (relative to project path)
+    #[arg(
+        value_name = "encounters",
+        default_value = "src/data/wild_encounters.json"
+    )]
+    pub encounters_file_path: PathBuf,
+    /// Path to the file specifying trainer order (relative to project path)
+    #[arg(
+        long,
+        value_name = "trainer-order",
+        default_value = "trainer_order.toml"
+    )]
+    pub trainer_order_path: PathBuf,
+    /// If no trainer order file available
+    #[arg(long, value_name = "no-trainer-order", default_value_t = false)]
+    pub no_trainer_order: bool,
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
 pub enum ProjectOption {
     #[command(version, about, long_about = None)]
     EmeraldExpansion(EmeraldExpansionOption),
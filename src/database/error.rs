@@ -0,0 +1,9 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DatabaseError {
+    #[error("Unknown species: {0}")]
+    UnknownSpecies(String),
+    #[error("Unknown move: {0}")]
+    UnknownMove(String),
+}
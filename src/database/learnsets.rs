@@ -0,0 +1,231 @@
+//! Learnset database.
+//!
+//! Learnsets are loaded from a Showdown learnsets file, where every move of a
+//! species lists how it is learned as codes such as "9L15" (level 15 in
+//! generation 9), "8M" (TM) or "7E" (egg move). Formes without a learnset of
+//! their own use the one of the forme they change from, and species also know
+//! the moves of their prevos.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::read_to_string,
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use super::{
+    pokedex::{Pokedex, PokemonDatabaseEntry},
+    species::SpeciesId,
+};
+
+/// Moves of a species, by move ID.
+pub type Learnset = HashMap<String, Vec<MoveSource>>;
+
+pub struct Learnsets {
+    entries: HashMap<SpeciesId, Learnset>,
+}
+
+/// How a move is learned, in a given generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveSource {
+    pub generation: u8,
+    pub method: LearnMethod,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LearnMethod {
+    /// Learned by level up at the given level
+    LevelUp(u8),
+    /// Taught by a TM, TR or HM
+    Machine,
+    /// Taught by a move tutor
+    Tutor,
+    Egg,
+    Event,
+    /// Any other source, such as Virtual Console transfers
+    Other,
+}
+
+#[derive(Deserialize)]
+struct RawLearnsetEntry {
+    #[serde(default)]
+    learnset: HashMap<String, Vec<String>>,
+}
+
+impl MoveSource {
+    /// Parses a Showdown source code such as "9L15", "8M" or "7S0".
+    fn parse(code: &str) -> Option<Self> {
+        let split = code.find(|c: char| !c.is_ascii_digit())?;
+        let (generation, rest) = code.split_at(split);
+        let mut chars = rest.chars();
+        let method = match chars.next()? {
+            'L' => LearnMethod::LevelUp(chars.as_str().parse().ok()?),
+            'M' => LearnMethod::Machine,
+            'T' => LearnMethod::Tutor,
+            'E' => LearnMethod::Egg,
+            'S' => LearnMethod::Event,
+            _ => LearnMethod::Other,
+        };
+
+        Some(Self {
+            generation: generation.parse().ok()?,
+            method,
+        })
+    }
+}
+
+/// ID a move is listed under in learnsets, every Hidden Power type being
+/// listed as Hidden Power.
+pub fn learnset_move_id(id: &str) -> &str {
+    if id.starts_with("hiddenpower") {
+        "hiddenpower"
+    } else {
+        id
+    }
+}
+
+impl Learnsets {
+    /// Learnset of `entry`, or of the forme it changes from when it has none.
+    fn learnset(&self, pokedex: &Pokedex, entry: &PokemonDatabaseEntry) -> Option<&Learnset> {
+        let mut current = entry;
+        loop {
+            if let Some(learnset) = self.entries.get(&current.id) {
+                return Some(learnset);
+            }
+            current = pokedex.forme_base(current)?;
+        }
+    }
+
+    /// Learnsets of `entry` and of its prevos, whose moves it can know.
+    fn lineage(&self, pokedex: &Pokedex, entry: &PokemonDatabaseEntry) -> Vec<&Learnset> {
+        let mut learnsets = Vec::new();
+        let mut current = Some(entry);
+        while let Some(entry) = current {
+            learnsets.extend(self.learnset(pokedex, entry));
+            current = entry
+                .prevo
+                .as_ref()
+                .and_then(|prevo| pokedex.entry(prevo).ok());
+        }
+
+        learnsets
+    }
+
+    /// Every move `entry` can learn one way or another, by move ID.
+    pub fn learnable(&self, pokedex: &Pokedex, entry: &PokemonDatabaseEntry) -> BTreeSet<&str> {
+        self.lineage(pokedex, entry)
+            .into_iter()
+            .flat_map(|learnset| learnset.keys().map(String::as_str))
+            .collect()
+    }
+
+    /// Moves `entry` can know at `level`, learned by level up in the latest
+    /// generation of each learnset or from one of `tms`, by move ID.
+    pub fn learnable_at_level(
+        &self,
+        pokedex: &Pokedex,
+        entry: &PokemonDatabaseEntry,
+        level: u8,
+        tms: &BTreeSet<String>,
+    ) -> BTreeSet<&str> {
+        let mut moves = BTreeSet::new();
+        for learnset in self.lineage(pokedex, entry) {
+            let latest = learnset
+                .values()
+                .flatten()
+                .filter(|source| matches!(source.method, LearnMethod::LevelUp(_)))
+                .map(|source| source.generation)
+                .max();
+
+            for (r#move, sources) in learnset.iter() {
+                let by_level = sources.iter().any(|source| {
+                    Some(source.generation) == latest
+                        && matches!(source.method, LearnMethod::LevelUp(at) if at <= level)
+                });
+                let by_tm = tms.contains(r#move)
+                    && sources
+                        .iter()
+                        .any(|source| source.method == LearnMethod::Machine);
+                if by_level || by_tm {
+                    moves.insert(r#move.as_str());
+                }
+            }
+        }
+
+        moves
+    }
+}
+
+/// Loads a learnsets file, skipping the species the pokedex does not know.
+pub fn load_learnsets(path: &Path, pokedex: &Pokedex) -> eyre::Result<Learnsets> {
+    let content =
+        read_to_string(path).map_err(|error| eyre::eyre!("{}: {error}", path.display()))?;
+    let raw: HashMap<String, RawLearnsetEntry> = serde_json::from_str(&content)?;
+
+    let mut entries = HashMap::new();
+    for (species, entry) in raw.into_iter() {
+        let Ok(id) = pokedex.resolve(&species) else {
+            tracing::debug!("Skipping learnset of unknown species {}", species);
+            continue;
+        };
+        if entry.learnset.is_empty() {
+            continue;
+        }
+
+        let learnset = entry
+            .learnset
+            .into_iter()
+            .map(|(r#move, codes)| {
+                let sources = codes
+                    .iter()
+                    .filter_map(|code| MoveSource::parse(code))
+                    .collect();
+                (r#move, sources)
+            })
+            .collect();
+        entries.insert(id, learnset);
+    }
+
+    Ok(Learnsets { entries })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(generation: u8, method: LearnMethod) -> Option<MoveSource> {
+        Some(MoveSource { generation, method })
+    }
+
+    #[test]
+    fn parses_source_codes() {
+        assert_eq!(
+            MoveSource::parse("9L15"),
+            source(9, LearnMethod::LevelUp(15))
+        );
+        assert_eq!(MoveSource::parse("8L1"), source(8, LearnMethod::LevelUp(1)));
+        assert_eq!(MoveSource::parse("8M"), source(8, LearnMethod::Machine));
+        assert_eq!(MoveSource::parse("7T"), source(7, LearnMethod::Tutor));
+        assert_eq!(MoveSource::parse("7E"), source(7, LearnMethod::Egg));
+        assert_eq!(MoveSource::parse("7S0"), source(7, LearnMethod::Event));
+        assert_eq!(MoveSource::parse("7V"), source(7, LearnMethod::Other));
+    }
+
+    #[test]
+    fn rejects_malformed_source_codes() {
+        assert_eq!(MoveSource::parse(""), None);
+        assert_eq!(MoveSource::parse("9"), None);
+        assert_eq!(MoveSource::parse("L15"), None);
+        assert_eq!(MoveSource::parse("9L"), None);
+        assert_eq!(MoveSource::parse("9Lx"), None);
+        assert_eq!(MoveSource::parse("999L5"), None);
+    }
+
+    #[test]
+    fn lists_every_hidden_power_as_hidden_power() {
+        assert_eq!(learnset_move_id("hiddenpowerfire"), "hiddenpower");
+        assert_eq!(learnset_move_id("hiddenpower"), "hiddenpower");
+        assert_eq!(learnset_move_id("tackle"), "tackle");
+    }
+}
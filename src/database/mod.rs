@@ -0,0 +1,8 @@
+//! This module contains the game data the engine relies on to make decisions,
+//! independently from any decompilation project.
+
+pub mod error;
+pub mod learnsets;
+pub mod moves;
+pub mod pokedex;
+pub mod species;
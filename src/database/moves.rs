@@ -0,0 +1,129 @@
+//! Move database.
+//!
+//! Moves are loaded from a Showdown moves file, keyed by move ID like the
+//! pokedex, and resolved from any spelling of their name through the same
+//! `toID` normalization as species.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    fs::read_to_string,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{error::DatabaseError, species::to_id};
+
+pub struct Movedex {
+    entries: HashMap<String, MoveEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveEntry {
+    /// Key of the entry in the moves file, filled in at load time.
+    #[serde(skip)]
+    pub id: String,
+    pub num: i64,
+    pub name: String,
+    pub r#type: String,
+    pub category: MoveCategory,
+    pub base_power: u16,
+    pub accuracy: Accuracy,
+    pub pp: u8,
+    #[serde(default)]
+    pub priority: i8,
+    /// Properties such as "contact", "sound" or "punch", all set to 1.
+    #[serde(default)]
+    pub flags: BTreeMap<String, u8>,
+    /// Who the move hits, such as "normal", "allAdjacentFoes" or "self".
+    pub target: String,
+    /// Why the move is not in the current games, such as "Past" or "CAP".
+    pub is_nonstandard: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MoveCategory {
+    Physical,
+    Special,
+    Status,
+}
+
+impl Display for MoveCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Physical => f.write_str("Physical"),
+            Self::Special => f.write_str("Special"),
+            Self::Status => f.write_str("Status"),
+        }
+    }
+}
+
+/// Accuracy of a move, `true` in the moves file for moves that never miss.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Accuracy {
+    Percent(u8),
+    NeverMisses(bool),
+}
+
+impl Display for Accuracy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Percent(percent) => write!(f, "{}%", percent),
+            Self::NeverMisses(_) => f.write_str("never misses"),
+        }
+    }
+}
+
+impl MoveEntry {
+    /// Short summary of the move, such as "Fire, Special, 90 power, 100%,
+    /// 15 PP".
+    pub fn summary(&self) -> String {
+        let mut summary = format!("{}, {}", self.r#type, self.category);
+        if self.base_power > 0 {
+            summary.push_str(&format!(", {} power", self.base_power));
+        }
+        summary.push_str(&format!(", {}, {} PP", self.accuracy, self.pp));
+        if self.priority != 0 {
+            summary.push_str(&format!(", priority {:+}", self.priority));
+        }
+
+        summary
+    }
+}
+
+impl std::ops::Deref for Movedex {
+    type Target = HashMap<String, MoveEntry>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entries
+    }
+}
+
+impl Movedex {
+    /// Resolves any spelling of a move to its entry.
+    pub fn entry(&self, name: &str) -> Result<&MoveEntry, DatabaseError> {
+        self.entries
+            .get(&to_id(name))
+            .ok_or_else(|| DatabaseError::UnknownMove(name.to_owned()))
+    }
+}
+
+pub fn load_moves(path: &Path) -> eyre::Result<Movedex> {
+    let content =
+        read_to_string(path).map_err(|error| eyre::eyre!("{}: {error}", path.display()))?;
+
+    parse_moves(&content)
+}
+
+/// Reads the content of a Showdown moves file.
+pub fn parse_moves(content: &str) -> eyre::Result<Movedex> {
+    let mut entries: HashMap<String, MoveEntry> = serde_json::from_str(content)?;
+    for (id, entry) in entries.iter_mut() {
+        entry.id = id.clone();
+    }
+
+    Ok(Movedex { entries })
+}
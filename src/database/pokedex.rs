@@ -0,0 +1,551 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::read_to_string,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::DatabaseError,
+    species::{SpeciesId, SpeciesResolver, to_id},
+};
+
+pub struct Pokedex {
+    entries: HashMap<SpeciesId, PokemonDatabaseEntry>,
+    resolver: SpeciesResolver,
+    /// Species candidate queries are limited to, every species when None
+    pool: Option<BTreeSet<SpeciesId>>,
+    /// First stage of the evolution family of every entry
+    roots: HashMap<SpeciesId, SpeciesId>,
+    /// Member with the highest BST of the family starting at every entry
+    strongest: HashMap<SpeciesId, SpeciesId>,
+    index: CandidateIndex,
+}
+
+/// Species candidate queries return, in the orders they return them. Built
+/// once per pool so that queries neither scan, clone nor sort the entries.
+#[derive(Default)]
+struct CandidateIndex {
+    /// Sorted by name
+    by_name: Vec<SpeciesId>,
+    /// Sorted by BST, then name
+    by_bst: Vec<SpeciesId>,
+    /// Sorted by BST, then name, for every type ID
+    by_type: BTreeMap<String, Vec<SpeciesId>>,
+    /// Name of every type of the candidates, by type ID
+    type_names: BTreeMap<String, String>,
+    /// First stages of the families of the candidates, sorted by the BST of
+    /// their strongest member, then name
+    families: Vec<SpeciesId>,
+}
+
+pub type PokemonName = String;
+
+/// Species entry of a Showdown pokedex, with every field of its schema.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PokemonDatabaseEntry {
+    /// Key of the entry in the pokedex file, filled in at load time.
+    #[serde(skip)]
+    pub id: SpeciesId,
+    pub num: u64,
+    pub name: PokemonName,
+    pub base_species: Option<String>,
+    pub forme: Option<String>,
+    pub base_forme: Option<String>,
+    /// Other formes with an entry of their own.
+    #[serde(default)]
+    pub other_formes: Vec<String>,
+    /// Formes differing only by their looks, which have no entry of their own.
+    #[serde(default)]
+    pub cosmetic_formes: Vec<String>,
+    /// Every forme of the species, in the order of the games.
+    #[serde(default)]
+    pub forme_order: Vec<String>,
+    pub types: Vec<String>,
+    /// Fixed gender of genderless and single-gender species.
+    pub gender: Option<FixedGender>,
+    /// Gender ratio of the species, even when missing and not fixed.
+    pub gender_ratio: Option<GenderRatio>,
+    pub base_stats: PokemonDatabaseEntryBaseStats,
+    pub abilities: HashMap<String, String>,
+    pub heightm: f32,
+    pub weightkg: f32,
+    pub color: String,
+    #[serde(default)]
+    pub egg_groups: Vec<String>,
+    /// Whether a species of the Undiscovered egg group hatches from eggs.
+    #[serde(default)]
+    pub can_hatch: bool,
+    /// Species whose eggs hatch into this one, for species with no prevo.
+    pub mother: Option<String>,
+    pub prevo: Option<String>,
+    pub evo_level: Option<u8>,
+    /// How the species evolves from its prevo when not by level, such as
+    /// "useItem", "trade" or "levelFriendship".
+    pub evo_type: Option<String>,
+    /// Item used or held to evolve.
+    pub evo_item: Option<String>,
+    /// Move known to evolve.
+    pub evo_move: Option<String>,
+    /// Region the species evolves in, for regional evolutions.
+    pub evo_region: Option<String>,
+    /// Free form condition, such as "at night".
+    pub evo_condition: Option<String>,
+    pub evos: Option<Vec<String>>,
+    pub r#gen: Option<u8>,
+    /// Categories such as "Restricted Legendary", "Mythical" or "Paradox".
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Item the forme needs to be held, such as a Mega Stone.
+    pub required_item: Option<String>,
+    /// Items any of which the forme needs to be held, such as a Plate.
+    pub required_items: Option<Vec<String>>,
+    /// Ability the forme needs.
+    pub required_ability: Option<String>,
+    /// Move the forme needs to be known.
+    pub required_move: Option<String>,
+    /// Tera type the forme needs to Terastallize into.
+    pub required_tera_type: Option<String>,
+    /// Forme or formes this battle-only forme changes from.
+    pub battle_only: Option<OneOrMany>,
+    /// Forme this Gigantamax forme changes from.
+    pub changes_from: Option<String>,
+    /// G-Max move of species that can Gigantamax.
+    pub can_gigantamax: Option<String>,
+    #[serde(default)]
+    pub cannot_dynamax: bool,
+    /// Fixed maximum HP, such as Shedinja's.
+    #[serde(rename = "maxHP")]
+    pub max_hp: Option<u16>,
+}
+
+/// Gender of genderless and single-gender species.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FixedGender {
+    #[serde(rename = "M")]
+    Male,
+    #[serde(rename = "F")]
+    Female,
+    #[serde(rename = "N")]
+    Genderless,
+}
+
+/// Share of males and females, summing to 1.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GenderRatio {
+    #[serde(rename = "M")]
+    pub male: f32,
+    #[serde(rename = "F")]
+    pub female: f32,
+}
+
+impl Default for GenderRatio {
+    fn default() -> Self {
+        Self {
+            male: 0.5,
+            female: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    pub fn first(&self) -> Option<&str> {
+        match self {
+            Self::One(value) => Some(value),
+            Self::Many(values) => values.first().map(String::as_str),
+        }
+    }
+}
+
+/// Kind of an alternate forme, which decides how it may appear.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormeClass {
+    /// Not an alternate forme
+    Base,
+    /// Alolan, Galarian, Hisuian and Paldean formes
+    Regional,
+    /// Formes differing only by their looks
+    Cosmetic,
+    /// Formes only reached during a battle, such as Aegislash-Blade
+    BattleOnly,
+    /// Mega Evolutions and Primal Reversions
+    Mega,
+    /// Gigantamax formes
+    Gigantamax,
+    /// Any other forme, such as Rotom-Wash or Arceus-Fire
+    Other,
+}
+
+/// First part of the forme name of regional variants.
+const REGIONS: [&str; 4] = ["Alola", "Galar", "Hisui", "Paldea"];
+
+impl PokemonDatabaseEntry {
+    /// Share of males of the species, None for genderless species.
+    pub fn male_ratio(&self) -> Option<f32> {
+        match self.gender {
+            Some(FixedGender::Male) => Some(1.0),
+            Some(FixedGender::Female) => Some(0.0),
+            Some(FixedGender::Genderless) => None,
+            None => Some(self.gender_ratio.unwrap_or_default().male),
+        }
+    }
+}
+
+impl PartialEq for PokemonDatabaseEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.name.eq(&other.name)
+    }
+}
+
+impl Eq for PokemonDatabaseEntry {}
+
+impl PartialOrd for PokemonDatabaseEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PokemonDatabaseEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.name.cmp(&other.name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PokemonDatabaseEntryBaseStats {
+    pub hp: u8,
+    pub atk: u8,
+    pub def: u8,
+    pub spa: u8,
+    pub spd: u8,
+    pub spe: u8,
+}
+
+impl PokemonDatabaseEntryBaseStats {
+    pub fn total(&self) -> u32 {
+        self.hp as u32
+            + self.atk as u32
+            + self.def as u32
+            + self.spa as u32
+            + self.spd as u32
+            + self.spe as u32
+    }
+}
+
+impl std::ops::Deref for Pokedex {
+    type Target = HashMap<SpeciesId, PokemonDatabaseEntry>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entries
+    }
+}
+
+impl Pokedex {
+    /// Registers additional spellings from a TOML table of alias to species.
+    pub fn load_aliases(&mut self, path: &Path) -> eyre::Result<()> {
+        let content = read_to_string(path)?;
+        let aliases: HashMap<String, String> = toml::from_str(&content)?;
+
+        for (alias, target) in aliases.iter() {
+            self.resolver.add_alias(alias, target)?;
+        }
+
+        Ok(())
+    }
+
+    /// Resolves any spelling of a species to its identifier.
+    pub fn resolve(&self, name: &str) -> Result<SpeciesId, DatabaseError> {
+        self.resolver.resolve(name)
+    }
+
+    /// Resolves any spelling of a species to its pokedex entry.
+    pub fn entry(&self, name: &str) -> Result<&PokemonDatabaseEntry, DatabaseError> {
+        let id = self.resolve(name)?;
+        self.entries
+            .get(&id)
+            .ok_or(DatabaseError::UnknownSpecies(name.to_owned()))
+    }
+
+    /// Limits candidate queries to the species of `pool`.
+    pub fn set_pool(&mut self, pool: BTreeSet<SpeciesId>) {
+        self.pool = Some(pool);
+        self.reindex();
+    }
+
+    /// Whether candidate queries may return `entry`.
+    pub fn in_pool(&self, entry: &PokemonDatabaseEntry) -> bool {
+        self.pool
+            .as_ref()
+            .is_none_or(|pool| pool.contains(&entry.id))
+    }
+
+    /// Class of the forme of `entry`.
+    pub fn forme_class(&self, entry: &PokemonDatabaseEntry) -> FormeClass {
+        let Some(ref forme) = entry.forme else {
+            return FormeClass::Base;
+        };
+        let parts: Vec<_> = forme.split('-').collect();
+
+        if parts
+            .iter()
+            .any(|part| *part == "Mega" || *part == "Primal")
+        {
+            FormeClass::Mega
+        } else if parts.contains(&"Gmax") {
+            FormeClass::Gigantamax
+        } else if entry.battle_only.is_some() {
+            FormeClass::BattleOnly
+        } else if REGIONS.contains(&parts[0]) && !parts.contains(&"Totem") {
+            FormeClass::Regional
+        } else if self
+            .forme_base(entry)
+            .is_some_and(|base| base.cosmetic_formes.contains(&entry.name))
+        {
+            FormeClass::Cosmetic
+        } else {
+            FormeClass::Other
+        }
+    }
+
+    /// Forme an alternate forme changes from, or its base species.
+    pub fn forme_base(&self, entry: &PokemonDatabaseEntry) -> Option<&PokemonDatabaseEntry> {
+        entry
+            .battle_only
+            .as_ref()
+            .and_then(OneOrMany::first)
+            .or(entry.changes_from.as_deref())
+            .or(entry.base_species.as_deref())
+            .and_then(|name| self.entry(name).ok())
+            .filter(|base| base.id != entry.id)
+    }
+
+    fn entries_of<'a>(
+        &'a self,
+        ids: &'a [SpeciesId],
+    ) -> impl Iterator<Item = &'a PokemonDatabaseEntry> + 'a {
+        ids.iter().map(|id| &self.entries[id])
+    }
+
+    /// Builds the family tables, which do not depend on the pool.
+    fn index_families(&mut self) {
+        let mut roots = HashMap::new();
+        let mut strongest = HashMap::new();
+        for entry in self.entries.values() {
+            let mut root = entry;
+            while let Some(ref prevo) = root.prevo
+                && let Ok(prevo) = self.entry(prevo)
+            {
+                root = prevo;
+            }
+            roots.insert(entry.id.clone(), root.id.clone());
+            strongest.insert(entry.id.clone(), self.strongest_member(entry).id.clone());
+        }
+
+        self.roots = roots;
+        self.strongest = strongest;
+    }
+
+    fn strongest_member<'a>(&'a self, root: &'a PokemonDatabaseEntry) -> &'a PokemonDatabaseEntry {
+        self.evolutions(root)
+            .map(|evolution| self.strongest_member(evolution))
+            .fold(root, |strongest, member| {
+                if member.base_stats.total() > strongest.base_stats.total() {
+                    member
+                } else {
+                    strongest
+                }
+            })
+    }
+
+    /// Builds the candidate index of the current pool.
+    fn reindex(&mut self) {
+        let mut candidates: Vec<_> = self
+            .entries
+            .values()
+            .filter(|value| match self.pool {
+                Some(ref pool) => pool.contains(&value.id),
+                None => value.forme.is_none(),
+            })
+            .collect();
+
+        candidates.sort();
+        let by_name = candidates.iter().map(|entry| entry.id.clone()).collect();
+
+        candidates.sort_by(|a, b| bst_order(a, b));
+        let by_bst = candidates.iter().map(|entry| entry.id.clone()).collect();
+
+        let mut by_type: BTreeMap<String, Vec<SpeciesId>> = BTreeMap::new();
+        let mut type_names = BTreeMap::new();
+        for entry in candidates.iter() {
+            for r#type in entry.types.iter() {
+                by_type
+                    .entry(to_id(r#type))
+                    .or_default()
+                    .push(entry.id.clone());
+                type_names
+                    .entry(to_id(r#type))
+                    .or_insert_with(|| r#type.clone());
+            }
+        }
+
+        let mut families: Vec<_> = candidates
+            .iter()
+            .map(|entry| self.family_root(entry))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        families.sort_by(|a, b| {
+            bst_order(self.family_strongest(a), self.family_strongest(b))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        let families = families.iter().map(|root| root.id.clone()).collect();
+
+        self.index = CandidateIndex {
+            by_name,
+            by_bst,
+            by_type,
+            type_names,
+            families,
+        };
+    }
+
+    /// Every species of the pool, sorted by name. Without a pool, every
+    /// species but alternate formes.
+    pub fn species(&self) -> impl Iterator<Item = &PokemonDatabaseEntry> {
+        self.entries_of(&self.index.by_name)
+    }
+
+    /// Species of [`Self::species`], sorted by BST then name.
+    pub fn species_by_bst(&self) -> impl Iterator<Item = &PokemonDatabaseEntry> {
+        self.entries_of(&self.index.by_bst)
+    }
+
+    /// Species of [`Self::species`] having `type`, in any case, sorted by BST
+    /// then name.
+    pub fn species_of_type(&self, r#type: &str) -> impl Iterator<Item = &PokemonDatabaseEntry> {
+        self.entries_of(
+            self.index
+                .by_type
+                .get(&to_id(r#type))
+                .map(Vec::as_slice)
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Types of the species of [`Self::species`], sorted by name.
+    pub fn types(&self) -> impl Iterator<Item = &str> {
+        self.index.type_names.values().map(String::as_str)
+    }
+
+    /// Spelling of `type` in the pokedex, whatever the pool, None if no
+    /// species has it.
+    pub fn type_name(&self, r#type: &str) -> Option<&str> {
+        let id = to_id(r#type);
+        self.entries
+            .values()
+            .flat_map(|entry| entry.types.iter())
+            .find(|name| to_id(name) == id)
+            .map(String::as_str)
+    }
+
+    /// Species `entry` evolves into.
+    pub fn evolutions<'a>(
+        &'a self,
+        entry: &'a PokemonDatabaseEntry,
+    ) -> impl Iterator<Item = &'a PokemonDatabaseEntry> + 'a {
+        entry
+            .evos
+            .iter()
+            .flatten()
+            .filter_map(|name| self.entry(name).ok())
+    }
+
+    /// First stage of the evolution family of `entry`.
+    pub fn family_root<'a>(&'a self, entry: &'a PokemonDatabaseEntry) -> &'a PokemonDatabaseEntry {
+        self.roots
+            .get(&entry.id)
+            .map_or(entry, |root| &self.entries[root])
+    }
+
+    /// Member of the family starting at `root` with the highest BST, usually
+    /// its final stage.
+    pub fn family_strongest<'a>(
+        &'a self,
+        root: &'a PokemonDatabaseEntry,
+    ) -> &'a PokemonDatabaseEntry {
+        self.strongest
+            .get(&root.id)
+            .map_or(root, |strongest| &self.entries[strongest])
+    }
+
+    /// First stages of the families of every species of the pool, sorted by
+    /// the BST of their strongest member, then name. A first stage may be out
+    /// of the pool itself.
+    pub fn family_roots(&self) -> impl Iterator<Item = &PokemonDatabaseEntry> {
+        self.entries_of(&self.index.families)
+    }
+}
+
+/// Order of the BST sorted indexes, by BST then name.
+fn bst_order(a: &PokemonDatabaseEntry, b: &PokemonDatabaseEntry) -> std::cmp::Ordering {
+    a.base_stats
+        .total()
+        .cmp(&b.base_stats.total())
+        .then_with(|| a.name.cmp(&b.name))
+}
+
+pub fn load_pokedex(path: &Path) -> eyre::Result<Pokedex> {
+    let content = read_to_string(path)?;
+    let mut entries: HashMap<SpeciesId, PokemonDatabaseEntry> = serde_json::from_str(&content)?;
+    for (id, entry) in entries.iter_mut() {
+        entry.id = id.clone();
+    }
+    let resolver = SpeciesResolver::new(entries.iter());
+
+    let mut pokedex = Pokedex {
+        entries,
+        resolver,
+        pool: None,
+        roots: HashMap::new(),
+        strongest: HashMap::new(),
+        index: CandidateIndex::default(),
+    };
+    pokedex.index_families();
+    pokedex.reindex();
+
+    Ok(pokedex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_types_up_in_any_case() {
+        let pokedex = load_pokedex(Path::new("pokedex.json")).unwrap();
+        let rock: Vec<_> = pokedex
+            .species_of_type("Rock")
+            .map(|entry| &entry.id)
+            .collect();
+        assert!(!rock.is_empty());
+        assert_eq!(
+            pokedex
+                .species_of_type("rock")
+                .map(|entry| &entry.id)
+                .collect::<Vec<_>>(),
+            rock
+        );
+        assert!(pokedex.types().any(|r#type| r#type == "Rock"));
+        assert_eq!(pokedex.type_name("ROCK"), Some("Rock"));
+        assert_eq!(pokedex.type_name("Rok"), None);
+    }
+}
@@ -0,0 +1,263 @@
+//! Canonical species identifiers.
+//!
+//! Species show up under many spellings depending on where they come from:
+//! Showdown display names ("Mr. Mime"), pokedex keys ("mrmime"), decomp
+//! constants ("SPECIES_MR_MIME") or hand written aliases. Every module goes
+//! through the [`SpeciesResolver`] to turn those into a [`SpeciesId`], which is
+//! the only species representation used by the rest of the engine.
+
+use std::{borrow::Borrow, collections::HashMap, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+
+use super::{error::DatabaseError, pokedex::PokemonDatabaseEntry};
+
+/// Prefix used by decomp projects for species constants.
+pub const SPECIES_CONSTANT_PREFIX: &str = "SPECIES_";
+
+/// Spellings that cannot be derived from the pokedex itself, mostly decomp
+/// constants naming a base forme explicitly.
+static DEFAULT_ALIASES: &[(&str, &str)] = &[
+    ("nidoranfemale", "nidoranf"),
+    ("nidoranmale", "nidoranm"),
+    ("meowsticmale", "meowstic"),
+    ("meowsticfemale", "meowsticf"),
+    ("indeedeemale", "indeedee"),
+    ("indeedeefemale", "indeedeef"),
+    ("basculegionmale", "basculegion"),
+    ("basculegionfemale", "basculegionf"),
+    ("oinkolognemale", "oinkologne"),
+    ("oinkolognefemale", "oinkolognef"),
+    ("burmyplantcloak", "burmy"),
+    ("burmysandycloak", "burmysandy"),
+    ("burmytrashcloak", "burmytrash"),
+    ("wormadamplantcloak", "wormadam"),
+    ("wormadamsandycloak", "wormadamsandy"),
+    ("wormadamtrashcloak", "wormadamtrash"),
+    ("shelloswestsea", "shellos"),
+    ("gastrodonwestsea", "gastrodon"),
+    ("shelloseastsea", "shelloseast"),
+    ("gastrodoneastsea", "gastrodoneast"),
+    ("darmanitanstandardmode", "darmanitan"),
+    ("darmanitanzenmode", "darmanitanzen"),
+    ("darmanitangalarstandardmode", "darmanitangalar"),
+    ("darmanitangalarzenmode", "darmanitangalarzen"),
+    ("zygarde50", "zygarde"),
+    ("ogerpontealmask", "ogerpon"),
+    ("ogerponwellspringmask", "ogerponwellspring"),
+    ("ogerponhearthflamemask", "ogerponhearthflame"),
+    ("ogerponcornerstonemask", "ogerponcornerstone"),
+    ("squawkabillygreenplumage", "squawkabilly"),
+    ("squawkabillyblueplumage", "squawkabillyblue"),
+    ("squawkabillyyellowplumage", "squawkabillyyellow"),
+    ("squawkabillywhiteplumage", "squawkabillywhite"),
+    ("mausholdfamilyoffour", "mausholdfour"),
+    ("mausholdfamilyofthree", "maushold"),
+    ("dudunsparcetwosegment", "dudunsparce"),
+    ("tatsugiricurly", "tatsugiri"),
+    ("gimmighoulchest", "gimmighoul"),
+    ("eiscueiceface", "eiscue"),
+    ("eiscuenoiceface", "eiscuenoice"),
+    ("morpekofullbelly", "morpeko"),
+    ("zacianheroofmanybattles", "zacian"),
+    ("zamazentaheroofmanybattles", "zamazenta"),
+    ("zaciancrownedsword", "zaciancrowned"),
+    ("zamazentacrownedshield", "zamazentacrowned"),
+    ("calyrexicerider", "calyrexice"),
+    ("calyrexshadowrider", "calyrexshadow"),
+    ("taurospaldeacombatbreed", "taurospaldeacombat"),
+    ("taurospaldeablazebreed", "taurospaldeablaze"),
+    ("taurospaldeaaquabreed", "taurospaldeaaqua"),
+];
+
+/// Canonical species identifier, which is the key of the species in the
+/// pokedex (Showdown's `toID` of the species name).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SpeciesId(String);
+
+impl SpeciesId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for SpeciesId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for SpeciesId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Showdown's `toID`: transliterated, lowercased, alphanumeric characters only.
+pub fn to_id(name: &str) -> String {
+    unidecode::unidecode(name)
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Name of the species as a decomp constant, without the `SPECIES_` prefix.
+/// "Mr. Mime" gives `MR_MIME`, "Farfetch’d" gives `FARFETCHD`.
+pub fn to_constant_name(name: &str) -> String {
+    let mut result = String::new();
+
+    for c in unidecode::unidecode(name).chars() {
+        match c {
+            '\'' | '.' | ':' | '%' => (),
+            c if c.is_ascii_alphanumeric() => result.push(c.to_ascii_uppercase()),
+            _ if !result.is_empty() && !result.ends_with('_') => result.push('_'),
+            _ => (),
+        }
+    }
+
+    result.trim_end_matches('_').to_owned()
+}
+
+/// Default aliases of a species or forme id, such as `meowsticfemale` for
+/// `meowsticf`, which is the inverse of the alias table.
+pub fn default_aliases(id: &str) -> impl Iterator<Item = &'static str> {
+    DEFAULT_ALIASES
+        .iter()
+        .filter(move |(_, target)| *target == id)
+        .map(|(alias, _)| *alias)
+}
+
+/// Maps every known spelling of a species to its [`SpeciesId`].
+#[derive(Debug, Clone, Default)]
+pub struct SpeciesResolver {
+    lookup: HashMap<String, SpeciesId>,
+}
+
+impl SpeciesResolver {
+    pub fn new<'a>(
+        entries: impl IntoIterator<Item = (&'a SpeciesId, &'a PokemonDatabaseEntry)>,
+    ) -> Self {
+        let mut resolver = Self::default();
+
+        for (id, entry) in entries {
+            resolver.lookup.insert(id.0.clone(), id.clone());
+            resolver.lookup.insert(to_id(&entry.name), id.clone());
+            // Base formes are sometimes spelled out, as in SPECIES_DEOXYS_NORMAL
+            if let Some(ref base_forme) = entry.base_forme {
+                let spelled_out = format!("{}-{}", entry.name, base_forme);
+                resolver.lookup.insert(to_id(&spelled_out), id.clone());
+            }
+            // Cosmetic formes have no entry and are their base species
+            for forme in entry.cosmetic_formes.iter() {
+                resolver
+                    .lookup
+                    .entry(to_id(forme))
+                    .or_insert_with(|| id.clone());
+            }
+        }
+
+        for (alias, target) in DEFAULT_ALIASES {
+            if let Some(id) = resolver.lookup.get(*target).cloned() {
+                resolver.lookup.entry(alias.to_string()).or_insert(id);
+            }
+        }
+
+        resolver
+    }
+
+    /// Registers an additional spelling for an already known species.
+    pub fn add_alias(&mut self, alias: &str, target: &str) -> Result<(), DatabaseError> {
+        let id = self.resolve(target)?;
+        self.lookup.insert(to_id(alias), id);
+        Ok(())
+    }
+
+    /// Resolves any spelling of a species (display name, pokedex key, decomp
+    /// constant or alias) to its identifier.
+    pub fn resolve(&self, name: &str) -> Result<SpeciesId, DatabaseError> {
+        let trimmed = name.trim();
+        let unprefixed = trimmed
+            .strip_prefix(SPECIES_CONSTANT_PREFIX)
+            .unwrap_or(trimmed);
+
+        self.lookup
+            .get(&to_id(unprefixed))
+            .cloned()
+            .ok_or_else(|| DatabaseError::UnknownSpecies(name.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::database::pokedex::{Pokedex, load_pokedex};
+
+    fn pokedex() -> Pokedex {
+        load_pokedex(Path::new("pokedex.json")).unwrap()
+    }
+
+    fn resolve(pokedex: &Pokedex, name: &str) -> String {
+        pokedex.resolve(name).unwrap().to_string()
+    }
+
+    #[test]
+    fn resolves_every_spelling() {
+        let pokedex = pokedex();
+        for name in ["Mr. Mime", "mrmime", "SPECIES_MR_MIME", "  Mr. Mime "] {
+            assert_eq!(resolve(&pokedex, name), "mrmime", "{}", name);
+        }
+        assert_eq!(resolve(&pokedex, "Farfetch’d"), "farfetchd");
+        assert_eq!(resolve(&pokedex, "SPECIES_FARFETCHD"), "farfetchd");
+        assert_eq!(resolve(&pokedex, "Flabébé"), "flabebe");
+        assert_eq!(resolve(&pokedex, "Nidoran-F"), "nidoranf");
+    }
+
+    #[test]
+    fn resolves_formes() {
+        let pokedex = pokedex();
+        // Spelled out base formes
+        assert_eq!(resolve(&pokedex, "SPECIES_DEOXYS_NORMAL"), "deoxys");
+        assert_eq!(resolve(&pokedex, "Zygarde-50%"), "zygarde");
+        // Cosmetic formes are their base species
+        assert_eq!(resolve(&pokedex, "Burmy-Sandy"), "burmy");
+        assert_eq!(resolve(&pokedex, "SPECIES_FLABEBE_BLUE"), "flabebe");
+        // Formes with an entry of their own
+        assert_eq!(resolve(&pokedex, "SPECIES_WORMADAM_SANDY"), "wormadamsandy");
+        // Default aliases
+        assert_eq!(resolve(&pokedex, "SPECIES_NIDORAN_FEMALE"), "nidoranf");
+        assert_eq!(
+            resolve(&pokedex, "SPECIES_WORMADAM_SANDY_CLOAK"),
+            "wormadamsandy"
+        );
+        assert_eq!(
+            resolve(&pokedex, "SPECIES_MAUSHOLD_FAMILY_OF_FOUR"),
+            "mausholdfour"
+        );
+        assert_eq!(
+            resolve(&pokedex, "SPECIES_DUDUNSPARCE_THREE_SEGMENT"),
+            "dudunsparcethreesegment"
+        );
+    }
+
+    #[test]
+    fn resolves_added_aliases_only_to_known_species() {
+        let mut resolver = SpeciesResolver::default();
+        assert!(matches!(
+            resolver.add_alias("Mimey", "Mr. Mime"),
+            Err(DatabaseError::UnknownSpecies(_))
+        ));
+
+        let pokedex = pokedex();
+        let mut resolver = SpeciesResolver::new(pokedex.species().map(|entry| (&entry.id, entry)));
+        resolver.add_alias("Mimey", "Mr. Mime").unwrap();
+        assert_eq!(resolver.resolve("MIMEY").unwrap().as_str(), "mrmime");
+        assert!(matches!(
+            resolver.resolve("Missingno"),
+            Err(DatabaseError::UnknownSpecies(name)) if name == "Missingno"
+        ));
+    }
+}
@@ -2,7 +2,54 @@ use std::path::PathBuf;
 
 use askama::Template;
 
-use crate::parties::{Trainer, party};
+use crate::{
+    backend::GameBackend,
+    database::{
+        moves::{MoveEntry, Movedex},
+        pokedex::Pokedex,
+    },
+    engine::{levels::TrainerCap, tags::ExcludedSpecies, trainer_order::TrainerOrderEntry},
+    parties::{Trainer, party},
+};
+
+pub struct MoveTemplate {
+    name: String,
+    /// Type, category, power and so on, when a move database is given
+    summary: Option<String>,
+}
+
+pub struct PokemonTemplate {
+    species: String,
+    sprite: String,
+    set: party::PokemonSet,
+    moves: [Option<MoveTemplate>; 4],
+}
+
+impl PokemonTemplate {
+    pub fn new(
+        set: &party::PokemonSet,
+        pokedex: &Pokedex,
+        movedex: Option<&Movedex>,
+        backend: &dyn GameBackend,
+    ) -> eyre::Result<Self> {
+        let set_moves = [&set.move_1, &set.move_2, &set.move_3, &set.move_4];
+        let moves = set_moves.map(|r#move| {
+            r#move.as_ref().map(|name| MoveTemplate {
+                name: name.clone(),
+                summary: movedex
+                    .and_then(|movedex| movedex.entry(name).ok())
+                    .map(MoveEntry::summary),
+            })
+        });
+
+        Ok(Self {
+            species: pokedex.entry(set.species.as_str())?.name.clone(),
+            sprite: backend.pokemon_sprite(pokedex, &set.species)?.name,
+            set: set.clone(),
+            moves,
+        })
+    }
+}
 
 #[derive(Template)]
 #[template(path = "trainer.jinja", escape = "none")]
@@ -11,22 +58,45 @@ pub struct TrainerTemplate {
     pic: PathBuf,
     location: Option<String>,
     split: Option<String>,
-    party: [Option<party::PokemonSet>; 6],
+    level_cap: Option<u8>,
+    party: [Option<PokemonTemplate>; 6],
 }
 
-impl From<Trainer> for TrainerTemplate {
-    fn from(value: Trainer) -> Self {
-        Self {
+impl TrainerTemplate {
+    pub fn new(
+        trainer: &Trainer,
+        pokedex: &Pokedex,
+        movedex: Option<&Movedex>,
+        backend: &dyn GameBackend,
+    ) -> eyre::Result<Self> {
+        let mut party: [Option<PokemonTemplate>; 6] = Default::default();
+        for (slot, set) in party.iter_mut().zip(trainer.party.iter()) {
+            *slot = set
+                .as_ref()
+                .map(|set| PokemonTemplate::new(set, pokedex, movedex, backend))
+                .transpose()?;
+        }
+
+        Ok(Self {
             name: format!(
                 "{} {}",
-                value.class.unwrap_or("Trainer".to_string()),
-                value.name
+                trainer.class.as_deref().unwrap_or("Trainer"),
+                trainer.name
             ),
-            pic: value.pic.to_lowercase().replace(' ', "_").into(),
+            pic: backend.trainer_sprite(trainer)?.name.into(),
             location: None,
             split: None,
-            party: value.party,
-        }
+            level_cap: None,
+            party,
+        })
+    }
+
+    /// Adds where the trainer stands in the trainer order.
+    pub fn with_order_entry(mut self, entry: &TrainerOrderEntry, cap: TrainerCap) -> Self {
+        self.location = entry.location.clone();
+        self.split = Some(entry.split.clone());
+        self.level_cap = Some(cap.cap);
+        self
     }
 }
 
@@ -34,12 +104,17 @@ impl From<Trainer> for TrainerTemplate {
 #[template(path = "trainers.jinja", escape = "none")]
 pub struct TrainerListTemplate {
     trainer_templates: Vec<TrainerTemplate>,
+    excluded_species: Vec<ExcludedSpecies>,
 }
 
-impl From<Vec<TrainerTemplate>> for TrainerListTemplate {
-    fn from(value: Vec<TrainerTemplate>) -> Self {
+impl TrainerListTemplate {
+    pub fn new(
+        trainer_templates: Vec<TrainerTemplate>,
+        excluded_species: Vec<ExcludedSpecies>,
+    ) -> Self {
         Self {
-            trainer_templates: value,
+            trainer_templates,
+            excluded_species,
         }
     }
 }
@@ -2,16 +2,29 @@
 //! file present in the src/data directory in emerald's decomp expansion
 //! project.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-use crate::database::pokedex::Pokedex;
+use crate::{
+    backend::emerald_expansion::SpeciesConstants,
+    bundles::pick,
+    database::{
+        pokedex::{Pokedex, PokemonDatabaseEntry},
+        species::SpeciesId,
+    },
+    encounters::EncounterRules,
+    engine::{error::EngineError, evolution, formes, validation::FileReport},
+    profile::EvolutionMode,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Encounters {
     pub wild_encounter_groups: Vec<WildEncounterGroup>,
+    /// Spelling of the species written back
+    #[serde(skip)]
+    pub species_constants: SpeciesConstants,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -60,80 +73,196 @@ pub struct MapEncounterSet {
     pub mons: Vec<MapEncounterSetMon>,
 }
 
+impl MapEncounters {
+    fn encounter_sets(&self) -> impl Iterator<Item = (&'static str, &MapEncounterSet)> {
+        [
+            ("land_mons", &self.land_mons),
+            ("water_mons", &self.water_mons),
+            ("rock_smash_mons", &self.rock_smash_mons),
+            ("fishing_mons", &self.fishing_mons),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.as_ref().map(|set| (name, set)))
+    }
+}
+
+/// Species constant of `entry`, or of the cosmetic forme it is shown as.
+fn wild_species_constant<R: Rng + ?Sized>(
+    entry: &PokemonDatabaseEntry,
+    constants: &SpeciesConstants,
+    rules: &EncounterRules,
+    rng: &mut R,
+) -> String {
+    match formes::cosmetic_variant(entry, rules.formes, rng) {
+        Some(forme) => constants.constant(&forme),
+        None => constants.species_constant(entry),
+    }
+}
+
 impl MapEncounterSet {
-    // TODO: Do something about having to convert pokemon names from pokedex entry
-    // name to pokedex key because those conversion are too confusing and the code
-    // is fucking ugly
+    /// Replaces every species of the set, `map` being the map constant the
+    /// set belongs to.
     pub fn randomize<R: Rng + ?Sized>(
         &mut self,
         pokedex: &Pokedex,
         rng: &mut R,
-        global_replace_table: &mut Option<HashMap<String, String>>,
-    ) {
-        let mut hash_set = HashSet::new();
+        map: Option<&str>,
+        global_replace_table: &mut Option<HashMap<SpeciesId, SpeciesId>>,
+        constants: &SpeciesConstants,
+        rules: &EncounterRules,
+    ) -> Result<(), EngineError> {
+        if rules.evolutions.mode == EvolutionMode::Stage {
+            return self.randomize_families(
+                pokedex,
+                rng,
+                map,
+                global_replace_table,
+                constants,
+                rules,
+            );
+        }
+
+        let allowed = |entry: &PokemonDatabaseEntry| rules.tags.allows_wild(entry, map);
+
+        let mut species_set = BTreeSet::new();
         for mon in self.mons.iter() {
-            hash_set.insert(mon.species.clone());
+            species_set.insert(pokedex.resolve(&mon.species)?);
         }
 
-        let mut replace_mon = vec![];
-        for species in hash_set.iter() {
-            let species_normalized = species
-                .replace("SPECIES_", "")
-                .to_lowercase()
-                .replace('-', "")
-                .replace(' ', "")
-                .replace('_', "");
+        let pool: Vec<_> = pokedex
+            .species_by_bst()
+            .filter(|entry| allowed(entry))
+            .collect();
 
-            let mon_db_entry = pokedex.get(&species_normalized).unwrap(); // TODO: Error handling
+        let mut replace_map = HashMap::new();
+        for species in species_set.into_iter() {
+            let mon_db_entry = pokedex.entry(species.as_str())?;
 
             let candidates = if let Some(map) = global_replace_table
-                && let Some(mon) = map.get(&species_normalized)
+                && let Some(mon) = map.get(&species)
+                && allowed(pokedex.entry(mon.as_str())?)
             {
-                vec![pokedex.get(mon).unwrap().clone()]
+                vec![pokedex.entry(mon.as_str())?]
             } else {
-                pokedex.get_all_within_bst_range(mon_db_entry.base_stats.total(), 30, 30)
+                rules.similarity.candidates(mon_db_entry, &pool)
+            };
+            // Keep the species when the strategy has nothing to offer, or take
+            // any species when the tags do not allow it here
+            let candidates = if !candidates.is_empty() {
+                candidates
+            } else if allowed(mon_db_entry) {
+                vec![mon_db_entry]
+            } else {
+                pool.clone()
             };
 
-            let chosen = candidates
-                .get(rng.next_u32() as usize % candidates.len())
-                .expect("modulo len");
+            let &chosen =
+                pick(&candidates, rng).ok_or_else(|| EngineError::NoCandidate(species.clone()))?;
 
             if let Some(map) = global_replace_table {
-                let chosen_name_normalized = unidecode::unidecode(&chosen.name)
-                    .to_lowercase()
-                    .replace('\'', "")
-                    .replace(". ", "_")
-                    .replace('-', "_")
-                    .replace(' ', "_")
-                    .replace('.', "")
-                    .replace(':', "")
-                    .replace('_', "");
-                if let None = map.get(&chosen_name_normalized) {
-                    map.insert(species_normalized, chosen_name_normalized);
-                }
+                map.entry(species.clone()).or_insert(chosen.id.clone());
             }
 
-            replace_mon.push(chosen.clone());
+            replace_map.insert(species, chosen.id.clone());
+        }
+
+        for set in self.mons.iter_mut() {
+            let species = pokedex.resolve(&set.species)?;
+            let replacement = pokedex.entry(replace_map[&species].as_str())?;
+            set.species = wild_species_constant(replacement, constants, rules, rng);
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the family of every species, each slot then getting the stage
+    /// of the new family fitting its minimum level. The global replace table
+    /// maps first stages to first stages.
+    fn randomize_families<R: Rng + ?Sized>(
+        &mut self,
+        pokedex: &Pokedex,
+        rng: &mut R,
+        map: Option<&str>,
+        global_replace_table: &mut Option<HashMap<SpeciesId, SpeciesId>>,
+        constants: &SpeciesConstants,
+        rules: &EncounterRules,
+    ) -> Result<(), EngineError> {
+        let allowed = |entry: &PokemonDatabaseEntry| rules.tags.allows_wild(entry, map);
+
+        // Lowest level of every family, which its replacement must fit
+        let mut family_levels = BTreeMap::new();
+        for mon in self.mons.iter() {
+            let entry = pokedex.entry(&mon.species)?;
+            let level = family_levels
+                .entry(pokedex.family_root(entry).id.clone())
+                .or_insert(mon.min_level);
+            *level = mon.min_level.min(*level);
         }
 
         let mut replace_map = HashMap::new();
-        for (to_replace, replacement) in hash_set.iter().zip(replace_mon.iter()) {
-            replace_map.insert(to_replace, replacement.name.clone());
+        for (family, level) in family_levels.into_iter() {
+            let root = pokedex.entry(family.as_str())?;
+            let fits = |root: &PokemonDatabaseEntry| {
+                evolution::has_stage_at_level(pokedex, root, level, rules.evolutions, &allowed)
+            };
+
+            let pool: Vec<_> = pokedex
+                .family_roots()
+                .filter(|root| fits(root))
+                .map(|root| pokedex.family_strongest(root))
+                .collect();
+            let candidates = if let Some(map) = global_replace_table
+                && let Some(mon) = map.get(&family)
+                && fits(pokedex.entry(mon.as_str())?)
+            {
+                vec![pokedex.entry(mon.as_str())?]
+            } else {
+                rules
+                    .similarity
+                    .candidates(pokedex.family_strongest(root), &pool)
+                    .into_iter()
+                    .map(|strongest| pokedex.family_root(strongest))
+                    .collect()
+            };
+            // Keep the family when the strategy has nothing to offer, or take
+            // any family when the tags leave it no stage at this level
+            let candidates = if !candidates.is_empty() {
+                candidates
+            } else if fits(root) {
+                vec![root]
+            } else {
+                pool.iter()
+                    .map(|strongest| pokedex.family_root(strongest))
+                    .collect()
+            };
+
+            let &chosen =
+                pick(&candidates, rng).ok_or_else(|| EngineError::NoCandidate(family.clone()))?;
+
+            if let Some(map) = global_replace_table {
+                map.entry(family.clone()).or_insert(chosen.id.clone());
+            }
+
+            replace_map.insert(family, chosen);
         }
 
         for set in self.mons.iter_mut() {
-            set.species = format!(
-                "SPECIES_{}",
-                unidecode::unidecode(&replace_map[&set.species])
-                    .replace('\'', "")
-                    .replace(". ", "_")
-                    .replace('-', "_")
-                    .replace(' ', "_")
-                    .replace('.', "")
-                    .replace(':', "")
-                    .to_uppercase()
-            );
+            let entry = pokedex.entry(&set.species)?;
+            let root = replace_map[&pokedex.family_root(entry).id];
+            let replacement = evolution::stage_at_level(
+                pokedex,
+                root,
+                set.min_level,
+                rules.evolutions,
+                rng,
+                &allowed,
+            )
+            // Families only fit when they have a stage at their lowest level
+            .ok_or_else(|| EngineError::NoCandidate(entry.id.clone()))?;
+            set.species = wild_species_constant(replacement, constants, rules, rng);
         }
+
+        Ok(())
     }
 }
 
@@ -144,27 +273,71 @@ pub struct MapEncounterSetMon {
     pub species: String,
 }
 
-impl<R: Rng + ?Sized> crate::encounters::Encounters<R> for Encounters {
-    fn randomize(&mut self, pokedex: &Pokedex, rng: &mut R, global_replace_table: bool) {
-        let mut global_replace_table = if global_replace_table {
+impl crate::encounters::Encounters for Encounters {
+    fn randomize(
+        &mut self,
+        pokedex: &Pokedex,
+        rng: &mut dyn Rng,
+        rules: &EncounterRules,
+    ) -> Result<(), EngineError> {
+        let mut global_replace_table = if rules.global_replace_table {
             Some(HashMap::new())
         } else {
             None
         };
 
         for encounter_group in self.wild_encounter_groups.iter_mut() {
-            for map_encouters in encounter_group.encounters.iter_mut() {
-                if let Some(ref mut encounter_set) = map_encouters.land_mons {
-                    encounter_set.randomize(pokedex, rng, &mut global_replace_table);
-                }
-                if let Some(ref mut encounter_set) = map_encouters.water_mons {
-                    encounter_set.randomize(pokedex, rng, &mut global_replace_table);
-                }
-                if let Some(ref mut encounter_set) = map_encouters.fishing_mons {
-                    encounter_set.randomize(pokedex, rng, &mut global_replace_table);
+            for map_encounters in encounter_group.encounters.iter_mut() {
+                let map = map_encounters.map.as_deref();
+                for encounter_set in [
+                    &mut map_encounters.land_mons,
+                    &mut map_encounters.water_mons,
+                    &mut map_encounters.fishing_mons,
+                    &mut map_encounters.rock_smash_mons,
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    encounter_set.randomize(
+                        pokedex,
+                        rng,
+                        map,
+                        &mut global_replace_table,
+                        &self.species_constants,
+                        rules,
+                    )?;
                 }
-                if let Some(ref mut encounter_set) = map_encouters.rock_smash_mons {
-                    encounter_set.randomize(pokedex, rng, &mut global_replace_table);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self, pokedex: &Pokedex, report: &mut FileReport) {
+        for encounter_group in self.wild_encounter_groups.iter() {
+            for map_encounters in encounter_group.encounters.iter() {
+                for (kind, encounter_set) in map_encounters.encounter_sets() {
+                    for (i, mon) in encounter_set.mons.iter().enumerate() {
+                        let entity = format!("{} {} slot {}", map_encounters.base_label, kind, i);
+
+                        if let Err(error) = pokedex.resolve(&mon.species) {
+                            report.push(entity.clone(), error);
+                        }
+                        for level in [mon.min_level, mon.max_level] {
+                            if !(1..=100).contains(&level) {
+                                report.push(entity.clone(), EngineError::InvalidLevel(level));
+                            }
+                        }
+                        if mon.min_level > mon.max_level {
+                            report.push(
+                                entity,
+                                EngineError::InvalidLevelRange {
+                                    min: mon.min_level,
+                                    max: mon.max_level,
+                                },
+                            );
+                        }
+                    }
                 }
             }
         }
@@ -174,3 +347,100 @@ impl<R: Rng + ?Sized> crate::encounters::Encounters<R> for Encounters {
         serde_json::to_string(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    use super::*;
+    use crate::{
+        database::pokedex::load_pokedex,
+        engine::{similarity::SimilarityStrategy, tags::TagRules},
+        profile::{Availability, EvolutionPolicy, FormePolicy, TagRule},
+    };
+
+    /// Strategy never offering a replacement.
+    struct NoCandidates;
+
+    impl SimilarityStrategy for NoCandidates {
+        fn candidates<'a>(
+            &self,
+            _original: &PokemonDatabaseEntry,
+            _pool: &[&'a PokemonDatabaseEntry],
+        ) -> Vec<&'a PokemonDatabaseEntry> {
+            vec![]
+        }
+    }
+
+    fn encounter_set(species: &str) -> MapEncounterSet {
+        MapEncounterSet {
+            encounter_rate: 20,
+            mons: vec![MapEncounterSetMon {
+                min_level: 50,
+                max_level: 50,
+                species: species.to_owned(),
+            }],
+        }
+    }
+
+    #[test]
+    fn falls_back_to_species_the_tags_allow() {
+        let pokedex = load_pokedex(Path::new("pokedex.json")).unwrap();
+        let tags = TagRules::new(
+            &[TagRule {
+                tag: "Restricted Legendary".to_owned(),
+                trainers: Availability::Always,
+                wild: Availability::Never,
+            }],
+            None,
+            &BTreeMap::new(),
+        );
+        let formes = FormePolicy::default();
+
+        for mode in [EvolutionMode::Off, EvolutionMode::Stage] {
+            let evolutions = EvolutionPolicy {
+                mode,
+                ..Default::default()
+            };
+            let rules = EncounterRules {
+                global_replace_table: false,
+                evolutions: &evolutions,
+                similarity: &NoCandidates,
+                tags: &tags,
+                formes: &formes,
+            };
+            let mut rng = SmallRng::seed_from_u64(0);
+
+            for _ in 0..20 {
+                // Kept, or evolved, when the tags allow it
+                let mut set = encounter_set("SPECIES_ZIGZAGOON");
+                set.randomize(
+                    &pokedex,
+                    &mut rng,
+                    None,
+                    &mut None,
+                    &SpeciesConstants::default(),
+                    &rules,
+                )
+                .unwrap();
+                let kept = pokedex.entry(&set.mons[0].species).unwrap();
+                assert_eq!(pokedex.family_root(kept).id.as_str(), "zigzagoon");
+
+                let mut set = encounter_set("SPECIES_MEWTWO");
+                set.randomize(
+                    &pokedex,
+                    &mut rng,
+                    None,
+                    &mut None,
+                    &SpeciesConstants::default(),
+                    &rules,
+                )
+                .unwrap();
+                let replacement = pokedex.entry(&set.mons[0].species).unwrap();
+                assert!(tags.allows_wild(replacement, None), "{}", replacement.name);
+            }
+        }
+    }
+}
@@ -1,50 +1,34 @@
-use std::io::Write;
-
 use rand::Rng;
 
-use crate::{cli::ProjectOption, database::pokedex::Pokedex};
+use crate::{
+    database::pokedex::Pokedex,
+    engine::{
+        error::EngineError, similarity::SimilarityStrategy, tags::TagRules, validation::FileReport,
+    },
+    profile::{EvolutionPolicy, FormePolicy},
+};
 
 pub mod emerald_expansion;
 
-pub trait Encounters<R: Rng + ?Sized> {
-    fn randomize(&mut self, pokedex: &Pokedex, rng: &mut R, global_replace_table: bool);
-    // Mandatory due to serde::Serialize not being dyn-compatible
-    fn serialize(&self) -> Result<String, serde_json::Error>;
-}
-
-pub fn load_encounter<R: Rng + ?Sized>(
-    project_options: &ProjectOption,
-) -> eyre::Result<Box<dyn Encounters<R>>> {
-    match project_options {
-        ProjectOption::EmeraldExpansion(ee_options) => {
-            let encounters_file_path = ee_options
-                .project_path
-                .join(&ee_options.encounters_file_path);
-
-            let content = std::fs::read_to_string(encounters_file_path)?;
-
-            let encounters: emerald_expansion::Encounters = serde_json::from_str(&content)?;
-            Ok(Box::new(encounters))
-        }
-    }
+/// Policies wild encounters are randomized with.
+pub struct EncounterRules<'a> {
+    /// Replace a species by the same species everywhere
+    pub global_replace_table: bool,
+    pub evolutions: &'a EvolutionPolicy,
+    pub similarity: &'a dyn SimilarityStrategy,
+    pub tags: &'a TagRules,
+    pub formes: &'a FormePolicy,
 }
 
-pub fn save_encounter<R: Rng + ?Sized>(
-    project_options: &ProjectOption,
-    encounters: &Box<dyn Encounters<R>>,
-) -> eyre::Result<()> {
-    match project_options {
-        ProjectOption::EmeraldExpansion(ee_options) => {
-            let encounters_file_path = ee_options
-                .project_path
-                .join(&ee_options.encounters_file_path);
-
-            let result = crate::encounters::Encounters::serialize(encounters.as_ref())?;
-
-            let mut file = std::fs::File::create(encounters_file_path)?;
-            file.write_all(result.as_bytes())?;
-
-            Ok(())
-        }
-    }
+pub trait Encounters {
+    fn randomize(
+        &mut self,
+        pokedex: &Pokedex,
+        rng: &mut dyn Rng,
+        rules: &EncounterRules,
+    ) -> Result<(), EngineError>;
+    /// Reports every species and level that the engine cannot work with.
+    fn validate(&self, pokedex: &Pokedex, report: &mut FileReport);
+    // Mandatory due to serde::Serialize not being dyn-compatible
+    fn serialize(&self) -> Result<String, serde_json::Error>;
 }
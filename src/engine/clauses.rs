@@ -0,0 +1,128 @@
+//! Party clauses.
+//!
+//! Slots of a party are generated one after the other, a slot breaking a
+//! clause against the slots before it being rerolled. Single and double
+//! battles have their own set of clauses, picked by `Trainer::double_battle`.
+
+use std::collections::HashMap;
+
+use crate::{
+    database::pokedex::Pokedex,
+    parties::party::PokemonSet,
+    profile::{ClausePolicy, ClauseSet},
+};
+
+impl ClausePolicy {
+    /// Clauses of a single or double battle.
+    pub fn for_battle(&self, double_battle: bool) -> &ClauseSet {
+        if double_battle {
+            &self.doubles
+        } else {
+            &self.singles
+        }
+    }
+}
+
+impl ClauseSet {
+    /// Name of the first clause `set` breaks against the rest of `party`. The
+    /// theme type of the trainer is exempt from the type clause.
+    pub fn broken(
+        &self,
+        pokedex: &Pokedex,
+        party: &[PokemonSet],
+        set: &PokemonSet,
+        theme: Option<&str>,
+    ) -> Option<&'static str> {
+        if self.species && party.iter().any(|other| other.species == set.species) {
+            return Some("species");
+        }
+
+        if self.item
+            && set.held_item.is_some()
+            && party.iter().any(|other| other.held_item == set.held_item)
+        {
+            return Some("item");
+        }
+
+        let entry = pokedex.get(&set.species)?;
+        let others = party.iter().filter_map(|other| pokedex.get(&other.species));
+
+        if self.family {
+            let family = &pokedex.family_root(entry).id;
+            if others
+                .clone()
+                .any(|other| &pokedex.family_root(other).id == family)
+            {
+                return Some("family");
+            }
+        }
+
+        if let Some(max_per_type) = self.max_per_type {
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            for r#type in others.chain([entry]).flat_map(|other| other.types.iter()) {
+                if theme.is_some_and(|theme| theme.eq_ignore_ascii_case(r#type)) {
+                    continue;
+                }
+                *counts.entry(r#type).or_default() += 1;
+            }
+            if counts.values().any(|&count| count > max_per_type) {
+                return Some("type");
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::database::pokedex::load_pokedex;
+
+    fn mon(pokedex: &Pokedex, species: &str, item: Option<&str>) -> PokemonSet {
+        PokemonSet {
+            species: pokedex.resolve(species).unwrap(),
+            held_item: item.map(str::to_owned),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finds_the_broken_clause() {
+        let pokedex = load_pokedex(Path::new("pokedex.json")).unwrap();
+        let party = [
+            mon(&pokedex, "Geodude", Some("Oran Berry")),
+            mon(&pokedex, "Onix", None),
+        ];
+        let clauses = ClauseSet {
+            species: true,
+            item: true,
+            family: true,
+            max_per_type: Some(2),
+        };
+        let broken = |set: PokemonSet, theme| clauses.broken(&pokedex, &party, &set, theme);
+
+        assert_eq!(
+            broken(mon(&pokedex, "Geodude", None), None),
+            Some("species")
+        );
+        assert_eq!(
+            broken(mon(&pokedex, "Zubat", Some("Oran Berry")), None),
+            Some("item")
+        );
+        assert_eq!(broken(mon(&pokedex, "Golem", None), None), Some("family"));
+        // A third Rock type, unless it is the theme type
+        assert_eq!(broken(mon(&pokedex, "Rhyhorn", None), None), Some("type"));
+        assert_eq!(broken(mon(&pokedex, "Sudowoodo", None), None), Some("type"));
+        assert_eq!(broken(mon(&pokedex, "Sudowoodo", None), Some("rock")), None);
+        // Items only clash when held, as Onix holds none
+        assert_eq!(broken(mon(&pokedex, "Zubat", None), None), None);
+
+        // Nothing is broken without clauses
+        let clauses = ClauseSet::default();
+        let set = mon(&pokedex, "Geodude", Some("Oran Berry"));
+        assert_eq!(clauses.broken(&pokedex, &party, &set, None), None);
+    }
+}
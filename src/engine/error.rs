@@ -0,0 +1,58 @@
+use thiserror::Error;
+
+use crate::{
+    database::{error::DatabaseError, species::SpeciesId},
+    parties::error::PartyError,
+};
+
+#[derive(Error, Debug)]
+pub enum EngineError {
+    #[error("Unresolved species \"{0}\"")]
+    UnresolvedSpecies(String),
+    #[error("Trainer {0} does not exist")]
+    MissingTrainer(String),
+    #[error("Empty {field} option list")]
+    EmptyBundleOptions { field: String },
+    #[error("{0} move slots, expected at most 4")]
+    TooManyMoveSlots(usize),
+    #[error("{field} has {stat} {value}, expected at most {max}")]
+    StatTooHigh {
+        field: String,
+        stat: &'static str,
+        value: u8,
+        max: u8,
+    },
+    #[error("{field} totals {total}, expected at most 510")]
+    EvTotalTooHigh { field: String, total: u32 },
+    #[error(
+        "{} species missing from the pokedex, their sets are never used: {}",
+        .0.len(),
+        .0.join(", ")
+    )]
+    UnknownBundleSpecies(Vec<String>),
+    #[error("Unknown ability \"{0}\"")]
+    UnknownAbility(String),
+    #[error("Unknown item \"{0}\"")]
+    UnknownItem(String),
+    #[error("Invalid level {0}, expected a level between 1 and 100")]
+    InvalidLevel(u8),
+    #[error("Invalid level range {min}-{max}")]
+    InvalidLevelRange { min: u8, max: u8 },
+    #[error("Unknown move \"{0}\"")]
+    UnknownMove(String),
+    #[error("Unknown type \"{0}\"")]
+    UnknownType(String),
+    #[error("No replacement candidate for {0}")]
+    NoCandidate(SpeciesId),
+    #[error(transparent)]
+    Party(#[from] PartyError),
+}
+
+impl From<DatabaseError> for EngineError {
+    fn from(value: DatabaseError) -> Self {
+        match value {
+            DatabaseError::UnknownSpecies(name) => Self::UnresolvedSpecies(name),
+            DatabaseError::UnknownMove(name) => Self::UnknownMove(name),
+        }
+    }
+}
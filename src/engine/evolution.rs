@@ -0,0 +1,132 @@
+//! Evolution stage selection.
+//!
+//! Replacements are picked as a family first, compared by the BST of its
+//! strongest member, then as the stage of that family a Pokemon of the slot's
+//! level would have reached. Evolutions without a level (items, trades,
+//! friendship, ...) are considered reached from the thresholds of the
+//! [`EvolutionPolicy`].
+
+use rand::Rng;
+
+use crate::{
+    bundles::pick,
+    database::pokedex::{Pokedex, PokemonDatabaseEntry},
+    profile::EvolutionPolicy,
+};
+
+impl EvolutionPolicy {
+    /// Level from which `evolution` can be met, `evolution` being the evolved
+    /// species.
+    pub fn evolution_level(&self, evolution: &PokemonDatabaseEntry) -> u8 {
+        match evolution.evo_type.as_deref() {
+            Some("useItem") => self.item_level,
+            Some("trade") => self.trade_level,
+            Some("levelFriendship") => self.friendship_level,
+            _ => evolution.evo_level.unwrap_or(self.other_level),
+        }
+    }
+}
+
+/// Evolutions of `entry` that can be met at `level` and lead to an allowed
+/// species of the pool.
+fn reachable_evolutions<'a>(
+    pokedex: &'a Pokedex,
+    entry: &'a PokemonDatabaseEntry,
+    level: u8,
+    policy: &EvolutionPolicy,
+    allowed: &dyn Fn(&PokemonDatabaseEntry) -> bool,
+) -> Vec<&'a PokemonDatabaseEntry> {
+    pokedex
+        .evolutions(entry)
+        .filter(|evolution| policy.evolution_level(evolution) <= level)
+        .filter(|evolution| {
+            (allowed(evolution) && pokedex.in_pool(evolution))
+                || !reachable_evolutions(pokedex, evolution, level, policy, allowed).is_empty()
+        })
+        .collect()
+}
+
+/// Whether the family starting at `root` has an allowed stage of the pool at
+/// `level`.
+pub fn has_stage_at_level(
+    pokedex: &Pokedex,
+    root: &PokemonDatabaseEntry,
+    level: u8,
+    policy: &EvolutionPolicy,
+    allowed: &dyn Fn(&PokemonDatabaseEntry) -> bool,
+) -> bool {
+    (allowed(root) && pokedex.in_pool(root))
+        || !reachable_evolutions(pokedex, root, level, policy, allowed).is_empty()
+}
+
+/// Most evolved allowed stage of the pool of the family starting at `root`
+/// that a Pokemon of `level` would have reached. Branching evolutions are picked at random.
+pub fn stage_at_level<'a, R: Rng + ?Sized>(
+    pokedex: &'a Pokedex,
+    root: &'a PokemonDatabaseEntry,
+    level: u8,
+    policy: &EvolutionPolicy,
+    rng: &mut R,
+    allowed: &dyn Fn(&PokemonDatabaseEntry) -> bool,
+) -> Option<&'a PokemonDatabaseEntry> {
+    let mut current = root;
+    let mut stage = (allowed(root) && pokedex.in_pool(root)).then_some(root);
+
+    loop {
+        let evolutions = reachable_evolutions(pokedex, current, level, policy, allowed);
+        let Some(&next) = pick(&evolutions, rng) else {
+            return stage;
+        };
+
+        current = next;
+        if allowed(current) && pokedex.in_pool(current) {
+            stage = Some(current);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    use super::*;
+    use crate::database::pokedex::load_pokedex;
+
+    #[test]
+    fn meets_evolutions_without_a_level_at_the_policy_thresholds() {
+        let pokedex = load_pokedex(Path::new("pokedex.json")).unwrap();
+        let policy = EvolutionPolicy::default();
+        let level = |name| policy.evolution_level(pokedex.entry(name).unwrap());
+
+        assert_eq!(level("Ivysaur"), 16);
+        assert_eq!(level("Vaporeon"), policy.item_level);
+        assert_eq!(level("Machamp"), policy.trade_level);
+        assert_eq!(level("Crobat"), policy.friendship_level);
+        assert_eq!(level("Mantine"), policy.other_level);
+    }
+
+    #[test]
+    fn picks_the_stage_reached_at_the_level() {
+        let pokedex = load_pokedex(Path::new("pokedex.json")).unwrap();
+        let policy = EvolutionPolicy::default();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let root = pokedex.entry("Bulbasaur").unwrap();
+        let any = |_: &PokemonDatabaseEntry| true;
+        let mut stage = |level, allowed: &dyn Fn(&PokemonDatabaseEntry) -> bool| {
+            stage_at_level(&pokedex, root, level, &policy, &mut rng, allowed)
+                .map(|entry| entry.name.clone())
+        };
+
+        assert_eq!(stage(10, &any).as_deref(), Some("Bulbasaur"));
+        assert_eq!(stage(16, &any).as_deref(), Some("Ivysaur"));
+        assert_eq!(stage(50, &any).as_deref(), Some("Venusaur"));
+        // Stages that are not allowed are skipped
+        let not_ivysaur = |entry: &PokemonDatabaseEntry| entry.name != "Ivysaur";
+        assert_eq!(stage(20, &not_ivysaur).as_deref(), Some("Bulbasaur"));
+        assert_eq!(stage(50, &not_ivysaur).as_deref(), Some("Venusaur"));
+        let none = |_: &PokemonDatabaseEntry| false;
+        assert_eq!(stage(50, &none), None);
+    }
+}
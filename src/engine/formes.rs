@@ -0,0 +1,110 @@
+//! Alternate formes.
+//!
+//! Every alternate forme falls in a [`FormeClass`], and the [`FormePolicy`]
+//! tells how each class may appear. Regional and other formes can be species
+//! of their own, in which case the pool includes them. Battle-only, Mega and
+//! Gigantamax formes cannot start a battle, so their bundle sets are given to
+//! the species they change from instead, holding the item the forme needs.
+//! Cosmetic formes have no entry of their own and are only a different look of
+//! their base species, rolled when the species is picked.
+
+use rand::{Rng, RngExt};
+
+use crate::{
+    bundles::{BundleOption, SetBundle},
+    database::pokedex::{FormeClass, Pokedex, PokemonDatabaseEntry},
+    profile::{BattleFormes, CosmeticFormes, FormePolicy, SpeciesFormes},
+};
+
+impl FormePolicy {
+    /// Whether formes of `class` are species of their own.
+    pub fn is_species(&self, class: FormeClass) -> bool {
+        match class {
+            FormeClass::Base => true,
+            FormeClass::Regional => self.regional == SpeciesFormes::Species,
+            FormeClass::Other => self.other == SpeciesFormes::Species,
+            FormeClass::Cosmetic
+            | FormeClass::BattleOnly
+            | FormeClass::Mega
+            | FormeClass::Gigantamax => false,
+        }
+    }
+
+    fn battle_formes(&self, class: FormeClass) -> Option<BattleFormes> {
+        match class {
+            FormeClass::BattleOnly => Some(self.battle_only),
+            FormeClass::Mega => Some(self.mega),
+            FormeClass::Gigantamax => Some(self.gigantamax),
+            _ => None,
+        }
+    }
+}
+
+/// Moves the sets of battle-only, Mega and Gigantamax formes to the species
+/// they change from, or drops them when the policy never allows the forme.
+/// Mega Stones are also taken away from the sets of the base species then.
+pub fn apply_to_bundle(bundle: &mut SetBundle, pokedex: &Pokedex, policy: &FormePolicy) {
+    // Sorted so that the sets of a species always end up in the same order
+    let mut species: Vec<_> = bundle.keys().cloned().collect();
+    species.sort();
+
+    for id in species {
+        let Some(entry) = pokedex.get(&id) else {
+            continue;
+        };
+        let class = pokedex.forme_class(entry);
+        let Some(battle_formes) = policy.battle_formes(class) else {
+            continue;
+        };
+        let Some(base) = pokedex.forme_base(entry) else {
+            tracing::warn!("{} does not change from any species", entry.name);
+            continue;
+        };
+
+        let sets = bundle.remove(&id).expect("species of the bundle");
+        match battle_formes {
+            BattleFormes::ThroughBase => {
+                let sets = sets.into_iter().map(|mut set| {
+                    match class {
+                        FormeClass::Mega if let Some(ref item) = entry.required_item => {
+                            set.item = vec![BundleOption::Plain(item.clone())]
+                        }
+                        FormeClass::Gigantamax => set.gigantamax = true,
+                        _ => (),
+                    }
+                    set
+                });
+                bundle.entry(base.id.clone()).or_default().extend(sets);
+            }
+            BattleFormes::Never => {
+                if class == FormeClass::Mega
+                    && let Some(ref item) = entry.required_item
+                    && let Some(base_sets) = bundle.get_mut(&base.id)
+                {
+                    base_sets.retain_mut(|set| {
+                        let had_items = !set.item.is_empty();
+                        set.item.retain(|held| held.value() != item);
+                        !had_items || !set.item.is_empty()
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Name of the cosmetic forme `entry` is shown as, None for its own look.
+pub fn cosmetic_variant<R: Rng + ?Sized>(
+    entry: &PokemonDatabaseEntry,
+    policy: &FormePolicy,
+    rng: &mut R,
+) -> Option<String> {
+    if policy.cosmetic == CosmeticFormes::Base || entry.cosmetic_formes.is_empty() {
+        return None;
+    }
+
+    // The base look counts as one of the variants
+    let index = rng.random_range(0..=entry.cosmetic_formes.len());
+    index
+        .checked_sub(1)
+        .map(|index| entry.cosmetic_formes[index].clone())
+}
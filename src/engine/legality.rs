@@ -0,0 +1,148 @@
+//! Move legality.
+//!
+//! Bundle sets are built for competitive play and often know moves a trainer
+//! Pokemon could not have at its level, or at all. Under a legality mode every
+//! illegal move of a generated set is replaced by a legal move of the same
+//! category when possible, or of any category otherwise, so that the set keeps
+//! its attackers and its support moves. Slots are left empty when the species
+//! has no legal move left, as early game Pokemon often do.
+//!
+//! TMs are obtained by split, splits being ordered by their first appearance
+//! in the trainer order. Trainers outside of the trainer order have no TM.
+
+use std::collections::{BTreeSet, HashMap};
+
+use rand::Rng;
+
+use crate::{
+    bundles::pick,
+    database::{
+        learnsets::{Learnsets, learnset_move_id},
+        moves::{MoveEntry, Movedex},
+        pokedex::{Pokedex, PokemonDatabaseEntry},
+        species::to_id,
+    },
+    engine::trainer_order::TrainerOrder,
+    parties::party::PokemonSet,
+    profile::{LegalityMode, LegalityPolicy},
+};
+
+pub struct Legality {
+    mode: LegalityMode,
+    learnsets: Learnsets,
+    /// TMs obtained by every trainer of the trainer order, by trainer id
+    trainer_tms: HashMap<String, BTreeSet<String>>,
+}
+
+impl Legality {
+    pub fn new(
+        policy: &LegalityPolicy,
+        learnsets: Learnsets,
+        trainer_order: Option<&TrainerOrder>,
+    ) -> Self {
+        let splits = trainer_order.map(TrainerOrder::splits).unwrap_or_default();
+        let mut trainer_tms = HashMap::new();
+        for entry in trainer_order.iter().flat_map(|order| order.trainers.iter()) {
+            trainer_tms.entry(entry.id.clone()).or_insert_with(|| {
+                let split = splits.of_trainer(&entry.id);
+                policy
+                    .tms
+                    .iter()
+                    .filter(|(from, _)| splits.reached(from, split))
+                    .flat_map(|(_, tms)| tms.iter().map(|tm| to_id(tm)))
+                    .collect()
+            });
+        }
+
+        Self {
+            mode: policy.mode,
+            learnsets,
+            trainer_tms,
+        }
+    }
+
+    /// Moves `entry` may know at `level` in the party of `trainer_id`, by
+    /// move ID, None when any move is allowed.
+    fn legal_moves(
+        &self,
+        pokedex: &Pokedex,
+        entry: &PokemonDatabaseEntry,
+        level: u8,
+        trainer_id: &str,
+    ) -> Option<BTreeSet<&str>> {
+        match self.mode {
+            LegalityMode::Off => None,
+            LegalityMode::Learnable => Some(self.learnsets.learnable(pokedex, entry)),
+            LegalityMode::Level => {
+                let no_tms = BTreeSet::new();
+                let tms = self.trainer_tms.get(trainer_id).unwrap_or(&no_tms);
+                Some(
+                    self.learnsets
+                        .learnable_at_level(pokedex, entry, level, tms),
+                )
+            }
+        }
+    }
+
+    /// Replaces the moves of `set`, a set of `entry`, that it may not know at
+    /// its level in the party of `trainer_id`.
+    pub fn legalize<R: Rng + ?Sized>(
+        &self,
+        set: &mut PokemonSet,
+        entry: &PokemonDatabaseEntry,
+        trainer_id: &str,
+        pokedex: &Pokedex,
+        movedex: &Movedex,
+        rng: &mut R,
+    ) {
+        let level = set.level.unwrap_or(100);
+        let Some(legal) = self.legal_moves(pokedex, entry, level, trainer_id) else {
+            return;
+        };
+
+        let mut known: BTreeSet<String> = set.moves().map(|name| to_id(name)).collect();
+        let mut moves = Vec::new();
+        for name in set.moves() {
+            let id = to_id(name);
+            if legal.contains(learnset_move_id(&id)) {
+                moves.push(name.clone());
+                continue;
+            }
+
+            let options: Vec<&MoveEntry> = legal
+                .iter()
+                .filter_map(|id| movedex.get(*id))
+                .filter(|option| !known.contains(&option.id))
+                .collect();
+            let category = movedex.entry(name).ok().map(|r#move| r#move.category);
+            let same_category: Vec<&MoveEntry> = options
+                .iter()
+                .copied()
+                .filter(|option| Some(option.category) == category)
+                .collect();
+            let options = if same_category.is_empty() {
+                options
+            } else {
+                same_category
+            };
+
+            let Some(&replacement) = pick(&options, rng) else {
+                tracing::debug!("{} cannot know {name}, dropping it", entry.name);
+                continue;
+            };
+            tracing::debug!(
+                "{} cannot know {name}, replacing it with {}",
+                entry.name,
+                replacement.name
+            );
+            known.insert(replacement.id.clone());
+            moves.push(replacement.name.clone());
+        }
+
+        let mut moves = moves.into_iter();
+        set.move_1 = moves.next();
+        set.move_2 = moves.next();
+        set.move_3 = moves.next();
+        set.move_4 = moves.next();
+    }
+}
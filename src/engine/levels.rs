@@ -0,0 +1,271 @@
+//! Level policy.
+//!
+//! Walking the trainer order gives the level cap in force when each trainer is
+//! fought: the starter cap first, raised each time a trainer setting a new cap
+//! is beaten. Party levels are then rescaled against the cap of their trainer,
+//! cap setting trainers being the bosses of their split.
+
+use std::collections::HashMap;
+
+use crate::{
+    engine::{error::EngineError, trainer_order::TrainerOrder, validation::check_level},
+    parties::party::PokemonSet,
+    profile::{LevelMode, LevelPolicy},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrainerCap {
+    /// Level cap in force when the trainer is fought.
+    pub cap: u8,
+    /// Whether beating the trainer raises the level cap.
+    pub boss: bool,
+}
+
+/// Cap of every entry of the trainer order, in the same order.
+pub fn level_caps(order: &TrainerOrder) -> Vec<TrainerCap> {
+    let mut cap = order.starter_level_cap;
+
+    order
+        .trainers
+        .iter()
+        .map(|entry| {
+            let trainer_cap = TrainerCap {
+                cap,
+                boss: entry.sets_level_cap.is_some(),
+            };
+            if let Some(next_cap) = entry.sets_level_cap {
+                cap = next_cap;
+            }
+            trainer_cap
+        })
+        .collect()
+}
+
+/// Cap of every trainer of the order, by trainer id. A trainer listed more
+/// than once gets the cap of its first fight.
+pub fn trainer_caps(order: &TrainerOrder) -> HashMap<String, TrainerCap> {
+    let mut caps = HashMap::new();
+    for (entry, cap) in order.trainers.iter().zip(level_caps(order)) {
+        caps.entry(entry.id.clone()).or_insert(cap);
+    }
+
+    caps
+}
+
+/// Levels the Pokemon of a party are generated at, None for empty slots.
+pub fn party_levels(
+    party: &[Option<PokemonSet>; 6],
+    trainer_cap: Option<TrainerCap>,
+    policy: &LevelPolicy,
+) -> Result<[Option<u8>; 6], EngineError> {
+    let mut levels = [None; 6];
+    for (level, mon) in levels.iter_mut().zip(party.iter()) {
+        *level = mon.as_ref().map(|mon| check_level(mon.level)).transpose()?;
+    }
+
+    let Some(trainer_cap) = trainer_cap else {
+        return Ok(levels);
+    };
+    if policy.mode == LevelMode::Original {
+        return Ok(levels);
+    }
+
+    if trainer_cap.boss {
+        Ok(scale_boss(levels, trainer_cap.cap, policy))
+    } else {
+        Ok(scale_route(levels, trainer_cap.cap, policy))
+    }
+}
+
+/// The ace, which is the highest level Pokemon, is put at the cap, and the
+/// others between one and `boss_spread` levels below it, keeping their order.
+fn scale_boss(levels: [Option<u8>; 6], cap: u8, policy: &LevelPolicy) -> [Option<u8>; 6] {
+    let mut slots: Vec<usize> = (0..6).filter(|&i| levels[i].is_some()).collect();
+    // Highest level first, the last slot winning ties as aces usually come last
+    slots.sort_by_key(|&i| std::cmp::Reverse((levels[i], i)));
+
+    let mut result = [None; 6];
+    for (rank, slot) in slots.into_iter().enumerate() {
+        let below = (rank as u8).min(policy.boss_spread.max(1));
+        result[slot] = Some(cap.saturating_sub(below).max(1));
+    }
+
+    result
+}
+
+/// The highest level Pokemon is put `route_margin` levels below the cap, the
+/// others keeping their distance to it.
+fn scale_route(levels: [Option<u8>; 6], cap: u8, policy: &LevelPolicy) -> [Option<u8>; 6] {
+    let Some(highest) = levels.iter().flatten().max().copied() else {
+        return levels;
+    };
+    let top = cap.saturating_sub(policy.route_margin).max(1);
+
+    levels.map(|level| level.map(|level| top.saturating_sub(highest - level).max(1)))
+}
+
+/// Party of a pinned team, its sets without a level taking the level of the
+/// slot they replace, or the highest level of the party for the slots the
+/// party did not have.
+pub fn pinned_party(
+    pinned: &[Option<PokemonSet>; 6],
+    levels: [Option<u8>; 6],
+) -> [Option<PokemonSet>; 6] {
+    let highest = levels.iter().flatten().max().copied();
+    let mut party = pinned.clone();
+    for (set, level) in party.iter_mut().zip(levels) {
+        if let Some(set) = set {
+            set.level = set.level.or(level).or(highest);
+        }
+    }
+
+    party
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::trainer_order::TrainerOrderEntry;
+
+    fn order(trainers: &[(&str, Option<u8>)]) -> TrainerOrder {
+        TrainerOrder {
+            starter_level_cap: 15,
+            trainers: trainers
+                .iter()
+                .map(|&(id, sets_level_cap)| TrainerOrderEntry {
+                    id: id.to_owned(),
+                    split: "Roxanne".to_owned(),
+                    sets_level_cap,
+                    optional: None,
+                    location: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn policy(boss_spread: u8, route_margin: u8) -> LevelPolicy {
+        LevelPolicy {
+            mode: LevelMode::LevelCap,
+            boss_spread,
+            route_margin,
+        }
+    }
+
+    #[test]
+    fn raises_the_cap_after_each_boss() {
+        let order = order(&[
+            ("TRAINER_A", None),
+            ("TRAINER_B", Some(20)),
+            ("TRAINER_C", None),
+            ("TRAINER_A", Some(25)),
+        ]);
+        let caps: Vec<_> = level_caps(&order)
+            .iter()
+            .map(|cap| (cap.cap, cap.boss))
+            .collect();
+        assert_eq!(caps, [(15, false), (15, true), (20, false), (20, true)]);
+
+        // Rematches keep the cap of the first fight
+        let caps = trainer_caps(&order);
+        assert_eq!(caps["TRAINER_A"].cap, 15);
+        assert!(!caps["TRAINER_A"].boss);
+        assert_eq!(caps["TRAINER_C"].cap, 20);
+    }
+
+    #[test]
+    fn puts_the_boss_ace_at_the_cap() {
+        let levels = [Some(10), None, Some(12), Some(12), Some(8), None];
+        assert_eq!(
+            scale_boss(levels, 20, &policy(3, 3)),
+            [Some(18), None, Some(19), Some(20), Some(17), None]
+        );
+        // A spread of 0 still keeps the ace above the others
+        assert_eq!(
+            scale_boss(levels, 20, &policy(0, 3)),
+            [Some(19), None, Some(19), Some(20), Some(19), None]
+        );
+        assert_eq!(
+            scale_boss(levels, 1, &policy(3, 3)),
+            [Some(1), None, Some(1), Some(1), Some(1), None]
+        );
+    }
+
+    #[test]
+    fn keeps_route_levels_below_the_cap() {
+        let levels = [Some(10), None, Some(12), Some(8), None, None];
+        assert_eq!(
+            scale_route(levels, 20, &policy(3, 3)),
+            [Some(15), None, Some(17), Some(13), None, None]
+        );
+        assert_eq!(
+            scale_route(levels, 5, &policy(3, 3)),
+            [Some(1), None, Some(2), Some(1), None, None]
+        );
+        assert_eq!(scale_route([None; 6], 20, &policy(3, 3)), [None; 6]);
+    }
+
+    #[test]
+    fn keeps_original_levels_without_a_cap() {
+        let mon = |level| {
+            Some(PokemonSet {
+                level,
+                ..Default::default()
+            })
+        };
+        let party = [mon(Some(10)), mon(None), None, None, None, None];
+        let cap = TrainerCap {
+            cap: 20,
+            boss: true,
+        };
+
+        let original = LevelPolicy::default();
+        assert_eq!(
+            party_levels(&party, Some(cap), &original).unwrap(),
+            [Some(10), Some(100), None, None, None, None]
+        );
+        assert_eq!(
+            party_levels(&party, None, &policy(3, 3)).unwrap(),
+            [Some(10), Some(100), None, None, None, None]
+        );
+        assert_eq!(
+            party_levels(&party, Some(cap), &policy(3, 3)).unwrap(),
+            [Some(19), Some(20), None, None, None, None]
+        );
+        assert!(
+            party_levels(
+                &[mon(Some(0)), None, None, None, None, None],
+                None,
+                &original
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn gives_pinned_sets_the_level_of_their_slot() {
+        let set = |level| {
+            Some(PokemonSet {
+                level,
+                ..Default::default()
+            })
+        };
+        let pinned = [set(Some(50)), set(None), set(None), None, None, None];
+        let levels = [Some(10), Some(12), None, None, None, None];
+
+        let levels: Vec<_> = pinned_party(&pinned, levels)
+            .iter()
+            .map(|set| set.as_ref().map(|set| set.level))
+            .collect();
+        assert_eq!(
+            levels,
+            [
+                Some(Some(50)),
+                Some(Some(12)),
+                Some(Some(12)),
+                None,
+                None,
+                None
+            ]
+        );
+    }
+}
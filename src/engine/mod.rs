@@ -1,18 +1,48 @@
+pub mod clauses;
+pub mod error;
+pub mod evolution;
+pub mod formes;
+pub mod legality;
+pub mod levels;
+pub mod pool;
+pub mod similarity;
+pub mod tags;
+pub mod themes;
+pub mod tiers;
 pub mod trainer_order;
+pub mod validation;
 
-use std::{fs::File, io::Write, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Write,
+    path::Path,
+};
 
 use askama::Template;
 use rand::Rng;
 
 use crate::{
-    bundles::{PokemonBundleSet, SetBundle},
-    cli::EmeraldExpansionOption,
-    database::pokedex::{Pokedex, PokemonDatabaseEntry},
+    backend::GameBackend,
+    bundles::{self, PokemonBundleSet, SetBundle, pick},
+    database::{
+        moves::Movedex,
+        pokedex::{Pokedex, PokemonDatabaseEntry},
+        species::SpeciesId,
+    },
     doc::{TrainerListTemplate, TrainerTemplate},
-    encounters::Encounters,
-    engine::trainer_order::TrainerOrder,
+    encounters::{EncounterRules, Encounters},
+    engine::{
+        error::EngineError,
+        legality::Legality,
+        tags::TagRules,
+        themes::{Theme, Themes},
+        tiers::TierRules,
+        trainer_order::TrainerOrder,
+        validation::{ValidationReport, check_level},
+    },
     parties::{Parties, party::PokemonSet},
+    profile::{Availability, ClauseSet, EncounterMode, EvPolicy, EvolutionMode, Profile},
 };
 
 #[expect(dead_code)]
@@ -26,199 +56,763 @@ where
     R: Rng + ?Sized,
 {
     pub parties: Parties,
-    pub encounters: Box<dyn Encounters<R>>,
+    pub encounters: Box<dyn Encounters>,
     pub pokedex: Pokedex,
+    pub movedex: Option<Movedex>,
+    /// Move legality, None when generated sets keep their bundle moves
+    pub legality: Option<Legality>,
     pub set_bundle: SetBundle,
-    pub cli_options: crate::cli::Cli,
+    /// Teams replacing the party of their trainer, by trainer id
+    pub pinned: Parties,
+    pub profile: Profile,
+    pub backend: Box<dyn GameBackend>,
     pub rng: Box<R>,
     pub trainer_order: Option<TrainerOrder>,
 }
 
+/// Species of the pool having sets the tiers allow, by tier stage and battle
+/// type, built once per generation run for every slot to query.
+struct SetCandidates {
+    /// Sorted by BST
+    species: HashMap<(usize, bool), Vec<SpeciesId>>,
+    lookup: HashMap<(usize, bool), HashSet<SpeciesId>>,
+}
+
+impl SetCandidates {
+    fn new(pokedex: &Pokedex, set_bundle: &SetBundle, tiers: &TierRules) -> Self {
+        let mut species = HashMap::new();
+        for stage in tiers.stages() {
+            for double_battle in [false, true] {
+                let with_sets: Vec<_> = pokedex
+                    .species_by_bst()
+                    .filter(|entry| {
+                        set_bundle.get(&entry.id).is_some_and(|sets| {
+                            !tiers.stage_sets(sets, stage, double_battle).is_empty()
+                        })
+                    })
+                    .map(|entry| entry.id.clone())
+                    .collect();
+                species.insert((stage, double_battle), with_sets);
+            }
+        }
+        let lookup = species
+            .iter()
+            .map(|(key, species)| (*key, species.iter().cloned().collect()))
+            .collect();
+
+        Self { species, lookup }
+    }
+}
+
+/// What the replacement of a party slot must satisfy on top of being similar
+/// to the original species, and which of its sets fit the slot.
+struct SlotFilter<'a> {
+    trainer_id: &'a str,
+    double_battle: bool,
+    theme: Option<&'a str>,
+    tags: &'a TagRules,
+    tiers: &'a TierRules,
+    candidates: &'a SetCandidates,
+}
+
+impl SlotFilter<'_> {
+    fn allows(&self, entry: &PokemonDatabaseEntry) -> bool {
+        self.theme.is_none_or(|theme| Theme::matches(theme, entry))
+            && self.tags.allows_trainer(entry, self.trainer_id)
+    }
+
+    fn key(&self, level: u8) -> (usize, bool) {
+        (self.tiers.stage(self.trainer_id, level), self.double_battle)
+    }
+
+    /// Whether `entry` has sets that the tiers allow at `level`.
+    fn has_sets(&self, entry: &PokemonDatabaseEntry, level: u8) -> bool {
+        self.candidates.lookup[&self.key(level)].contains(&entry.id)
+    }
+
+    /// Species of the pool having sets that the tiers allow at `level` and
+    /// that the filter allows, sorted by BST.
+    fn species<'p>(&self, pokedex: &'p Pokedex, level: u8) -> Vec<&'p PokemonDatabaseEntry> {
+        let key = self.key(level);
+        let species: Box<dyn Iterator<Item = &PokemonDatabaseEntry>> = match self.theme {
+            Some(theme) => Box::new(
+                pokedex
+                    .species_of_type(theme)
+                    .filter(|entry| self.candidates.lookup[&key].contains(&entry.id)),
+            ),
+            None => Box::new(self.candidates.species[&key].iter().map(|id| &pokedex[id])),
+        };
+
+        species.filter(|entry| self.allows(entry)).collect()
+    }
+}
+
 impl<R: Rng + ?Sized> Engine<R> {
-    fn get_random_mon_within_bst_range(&mut self, set: &PokemonSet) -> PokemonDatabaseEntry {
+    /// Picks a random species similar to the one of `set`, among the species
+    /// that have at least one set the tiers allow and that `filter` allows.
+    fn get_random_similar_mon(
+        &mut self,
+        set: &PokemonSet,
+        level: u8,
+        filter: &SlotFilter,
+    ) -> Result<PokemonDatabaseEntry, EngineError> {
+        if self.profile.evolutions.mode == EvolutionMode::Stage {
+            return self.get_random_mon_at_stage(set, level, filter);
+        }
+
         let set_database_entry = self
             .pokedex
-            .get(
-                &set.species
-                    .to_lowercase()
-                    .replace('-', "")
-                    .replace(' ', "")
-                    .replace(':', ""),
-            )
-            .expect("pokemon should exist");
-        let all_within_range =
-            self.pokedex
-                .get_all_within_bst_range(set_database_entry.base_stats.total(), 30, 30);
-        all_within_range
-            .get(self.rng.next_u32() as usize % all_within_range.len())
-            .expect("modulo len")
-            .clone()
+            .get(&set.species)
+            .ok_or(EngineError::UnresolvedSpecies(set.species.to_string()))?;
+
+        let pool = filter.species(&self.pokedex, level);
+        let candidates = self
+            .profile
+            .trainers
+            .similarity
+            .strategy()
+            .candidates(set_database_entry, &pool);
+
+        pick(&candidates, &mut self.rng)
+            .map(|&candidate| candidate.clone())
+            .ok_or_else(|| EngineError::NoCandidate(set.species.clone()))
     }
 
-    fn get_random_bundle_set(
+    /// Picks a random family whose strongest member is similar to the one of
+    /// the family of `set`, then its stage fitting `level`, among the species
+    /// that have at least one set the tiers allow and that `filter` allows.
+    fn get_random_mon_at_stage(
         &mut self,
-        database_entry: &PokemonDatabaseEntry,
-    ) -> Option<PokemonBundleSet> {
-        let Some(mon_sets) = self.set_bundle.get(&database_entry.name) else {
-            return None;
+        set: &PokemonSet,
+        level: u8,
+        filter: &SlotFilter,
+    ) -> Result<PokemonDatabaseEntry, EngineError> {
+        let Self {
+            pokedex,
+            profile,
+            rng,
+            ..
+        } = self;
+        let policy = &profile.evolutions;
+        let allowed = |candidate: &PokemonDatabaseEntry| {
+            filter.has_sets(candidate, level) && filter.allows(candidate)
         };
 
-        Some(
-            mon_sets
-                .get(self.rng.next_u32() as usize % mon_sets.len())
-                .expect("modulo len")
-                .clone(),
-        )
+        let entry = pokedex
+            .get(&set.species)
+            .ok_or(EngineError::UnresolvedSpecies(set.species.to_string()))?;
+
+        let pool: Vec<_> = pokedex
+            .family_roots()
+            .filter(|root| evolution::has_stage_at_level(pokedex, root, level, policy, &allowed))
+            .map(|root| pokedex.family_strongest(root))
+            .collect();
+        let families: Vec<_> = profile
+            .trainers
+            .similarity
+            .strategy()
+            .candidates(pokedex.family_strongest(pokedex.family_root(entry)), &pool)
+            .into_iter()
+            .map(|strongest| pokedex.family_root(strongest))
+            .collect();
+
+        let &root =
+            pick(&families, rng).ok_or_else(|| EngineError::NoCandidate(set.species.clone()))?;
+        // The pool only holds families with a stage at this level
+        let stage = evolution::stage_at_level(pokedex, root, level, policy, rng, &allowed)
+            .ok_or_else(|| EngineError::NoCandidate(set.species.clone()))?;
+
+        Ok(stage.clone())
     }
 
-    fn generate_new_pokemon_set(&mut self, pkmn_set: &PokemonSet) -> PokemonSet {
-        let mut database_entry = self.get_random_mon_within_bst_range(&pkmn_set);
-        let mut random_bundle_set = self.get_random_bundle_set(&database_entry);
+    fn get_random_bundle_set(
+        &mut self,
+        database_entry: &PokemonDatabaseEntry,
+        level: u8,
+        filter: &SlotFilter,
+    ) -> Result<PokemonBundleSet, EngineError> {
+        let mon_sets = self
+            .set_bundle
+            .get(&database_entry.id)
+            .filter(|sets| !sets.is_empty())
+            .ok_or(EngineError::NoCandidate(database_entry.id.clone()))?;
+        let mon_sets = filter
+            .tiers
+            .sets(mon_sets, filter.trainer_id, level, filter.double_battle);
+
+        let set = bundles::pick_weighted(&mon_sets, |set| set.weight, &mut self.rng)
+            .ok_or(EngineError::NoCandidate(database_entry.id.clone()))?;
+        tracing::debug!(
+            "{} set \"{}\" from {}",
+            database_entry.name,
+            set.name,
+            set.format
+        );
 
-        while let None = random_bundle_set {
-            tracing::debug!("Rerolling pokemon species");
-            database_entry = self.get_random_mon_within_bst_range(&pkmn_set);
-            random_bundle_set = self.get_random_bundle_set(&database_entry);
-        }
+        Ok((*set).clone())
+    }
 
-        let random_bundle_set = random_bundle_set.expect("Cannot be None");
+    fn generate_new_pokemon_set(
+        &mut self,
+        pkmn_set: &PokemonSet,
+        level: u8,
+        filter: &SlotFilter,
+    ) -> Result<PokemonSet, EngineError> {
+        let database_entry = match self.get_random_similar_mon(pkmn_set, level, filter) {
+            Err(EngineError::NoCandidate(species)) if let Some(theme) = filter.theme => {
+                tracing::warn!(
+                    "No {theme} replacement candidate for {species}, ignoring the theme"
+                );
+                let filter = SlotFilter {
+                    theme: None,
+                    ..*filter
+                };
+                self.get_random_similar_mon(pkmn_set, level, &filter)?
+            }
+            result => result?,
+        };
+        let random_bundle_set = self.get_random_bundle_set(&database_entry, level, filter)?;
 
-        random_bundle_set.generate_set(
+        let mut set = random_bundle_set.generate_set(
             &database_entry,
-            pkmn_set.level.unwrap(),
+            level,
             &mut self.rng,
-            self.cli_options.disable_evs,
-        )
+            self.profile.trainers.evs == EvPolicy::None,
+        );
+        set.cosmetic_forme =
+            formes::cosmetic_variant(&database_entry, &self.profile.formes, &mut self.rng);
+        if let Some(ref legality) = self.legality
+            && let Some(ref movedex) = self.movedex
+        {
+            legality.legalize(
+                &mut set,
+                &database_entry,
+                filter.trainer_id,
+                &self.pokedex,
+                movedex,
+                &mut self.rng,
+            );
+        }
+
+        Ok(set)
     }
 
-    pub fn randomize_parties(&mut self) {
+    /// Generates the set of a slot, rerolling it while it breaks one of the
+    /// `clauses` against the `party` generated so far, as many times as the
+    /// profile allows. Returns the clause the set still breaks, if any.
+    fn generate_slot(
+        &mut self,
+        pkmn_set: &PokemonSet,
+        level: u8,
+        filter: &SlotFilter,
+        clauses: &ClauseSet,
+        party: &[PokemonSet],
+        theme: Option<&str>,
+    ) -> Result<(PokemonSet, Option<&'static str>), EngineError> {
+        let mut set = self.generate_new_pokemon_set(pkmn_set, level, filter)?;
+        for _ in 0..self.profile.trainers.clauses.rerolls {
+            if clauses.broken(&self.pokedex, party, &set, theme).is_none() {
+                return Ok((set, None));
+            }
+            set = self.generate_new_pokemon_set(pkmn_set, level, filter)?;
+        }
+
+        let broken = clauses.broken(&self.pokedex, party, &set, theme);
+        Ok((set, broken))
+    }
+
+    pub fn randomize_parties(&mut self) -> Result<(), EngineError> {
+        let trainer_caps = self
+            .trainer_order
+            .as_ref()
+            .map(levels::trainer_caps)
+            .unwrap_or_default();
+        let tags = TagRules::new(
+            &self.profile.tags,
+            self.trainer_order.as_ref(),
+            &self.profile.encounters.map_splits,
+        );
+        let mut themes = Themes::new(
+            &self.profile.trainers.themes,
+            self.trainer_order.as_ref(),
+            &self.pokedex,
+        );
+        let tiers = TierRules::new(&self.profile.trainers.tiers, self.trainer_order.as_ref());
+        let candidates = SetCandidates::new(&self.pokedex, &self.set_bundle, &tiers);
+
         let mut new_parties = std::mem::take(&mut self.parties);
         for party in new_parties.iter_mut() {
-            for maybe_mon in party.party.iter_mut() {
-                if let Some(mon) = maybe_mon {
-                    *mon = self.generate_new_pokemon_set(mon);
-                    if self.cli_options.disable_evs {
-                        mon.evs = None;
-                    }
-                    tracing::debug!(?mon);
+            let levels = levels::party_levels(
+                &party.party,
+                trainer_caps.get(&party.id).copied(),
+                &self.profile.trainers.levels,
+            )?;
+            if let Some(pinned) = self.pinned.get(&party.id) {
+                party.party = levels::pinned_party(&pinned.party, levels);
+                tracing::debug!(trainer = party.id, "pinned party");
+                continue;
+            }
+
+            let theme = themes.theme(party, &mut self.rng);
+            tracing::debug!(trainer = party.id, ?theme);
+            let clauses = self
+                .profile
+                .trainers
+                .clauses
+                .for_battle(party.double_battle)
+                .clone();
+            let party_theme = theme.as_ref().map(|theme| theme.r#type.as_str());
+            let mut generated: Vec<PokemonSet> = Vec::new();
+            for (slot, (mon, level)) in party.party.iter_mut().zip(levels).enumerate() {
+                let (Some(mon), Some(level)) = (mon, level) else {
+                    continue;
+                };
+                let filter = SlotFilter {
+                    trainer_id: &party.id,
+                    double_battle: party.double_battle,
+                    theme: theme
+                        .as_ref()
+                        .filter(|theme| theme.slots[slot])
+                        .map(|theme| theme.r#type.as_str()),
+                    tags: &tags,
+                    tiers: &tiers,
+                    candidates: &candidates,
+                };
+                let (new_mon, broken) =
+                    self.generate_slot(mon, level, &filter, &clauses, &generated, party_theme)?;
+                if let Some(clause) = broken {
+                    tracing::warn!(
+                        "{} slot {} still breaks the {clause} clause after {} rerolls",
+                        party.id,
+                        slot + 1,
+                        self.profile.trainers.clauses.rerolls
+                    );
                 }
+                generated.push(new_mon.clone());
+                *mon = new_mon;
+                if self.profile.trainers.evs == EvPolicy::None {
+                    mon.evs = None;
+                }
+                tracing::debug!(?mon);
             }
         }
 
         self.parties = new_parties;
+
+        Ok(())
+    }
+
+    /// Runs the checks that need every input to be loaded, on top of the
+    /// problems already reported while loading them.
+    pub fn validate(&self, report: &mut ValidationReport) {
+        let parties_file = self.backend.parties_path();
+        let mut parties_report = report.file(&parties_file);
+        for trainer in self.parties.iter() {
+            for (i, mon) in trainer.party.iter().enumerate() {
+                if let Some(mon) = mon
+                    && let Err(error) = check_level(mon.level)
+                {
+                    parties_report.push(format!("{} slot {}", trainer.id, i + 1), error);
+                }
+            }
+        }
+
+        if let Some(ref trainer_order) = self.trainer_order
+            && let Some(trainer_order_file) = self.backend.trainer_order_path()
+        {
+            let mut trainer_order_report = report.file(&trainer_order_file);
+            for (i, entry) in trainer_order.trainers.iter().enumerate() {
+                if self.parties.get(&entry.id).is_none() {
+                    trainer_order_report.push(
+                        format!("trainers[{}]", i),
+                        EngineError::MissingTrainer(entry.id.clone()),
+                    );
+                }
+            }
+        }
+
+        let splits = self
+            .trainer_order
+            .as_ref()
+            .map(TrainerOrder::splits)
+            .unwrap_or_default();
+        for rule in self.profile.tags.iter() {
+            for availability in [&rule.trainers, &rule.wild] {
+                if let Availability::From(split) = availability
+                    && splits.position(split).is_none()
+                {
+                    tracing::warn!(
+                        "{} rule: split {split} is not in the trainer order, the tag is never allowed",
+                        rule.tag
+                    );
+                }
+            }
+        }
+        for stage in self.profile.trainers.tiers.stages.iter() {
+            if let Some(ref split) = stage.split
+                && splits.position(split).is_none()
+            {
+                tracing::warn!(
+                    "Tier stage: split {split} is not in the trainer order, the stage is never reached"
+                );
+            }
+        }
+        for team in self.pinned.iter() {
+            if self.parties.get(&team.id).is_none() {
+                tracing::warn!(
+                    "Pinned team {}: no such trainer, the team is never used",
+                    team.id
+                );
+            }
+        }
+        for split in self.profile.trainers.legality.tms.keys() {
+            if splits.position(split).is_none() {
+                tracing::warn!(
+                    "TMs of split {split}: the split is not in the trainer order, its TMs are never obtained"
+                );
+            }
+        }
+
+        let mut profile_report = report.file(Path::new("profile"));
+        for (i, rule) in self.profile.trainers.themes.iter().enumerate() {
+            for r#type in rule.types.iter() {
+                if self.pokedex.type_name(r#type).is_none() {
+                    profile_report.push(
+                        format!("trainers.themes[{}]", i),
+                        EngineError::UnknownType(r#type.clone()),
+                    );
+                }
+            }
+        }
+
+        let encounters_file = self.backend.encounters_path();
+        self.encounters
+            .validate(&self.pokedex, &mut report.file(&encounters_file));
     }
 
-    pub fn randomize_encounters(&mut self) {
-        let options = match self.cli_options.project {
-            crate::cli::ProjectOption::EmeraldExpansion(ref options) => options,
+    /// Reports every party move missing from the move database, if any.
+    pub fn validate_moves(&self, report: &mut ValidationReport) {
+        let Some(ref movedex) = self.movedex else {
+            return;
         };
 
-        self.encounters.randomize(
-            &self.pokedex,
-            &mut self.rng,
-            options.global_encounter_randomization,
+        let parties_file = self.backend.parties_path();
+        let mut parties_report = report.file(&parties_file);
+        for trainer in self.parties.iter() {
+            for (i, mon) in trainer.party.iter().enumerate() {
+                for r#move in mon.iter().flat_map(PokemonSet::moves) {
+                    if let Err(error) = movedex.entry(r#move) {
+                        parties_report.push(format!("{} slot {}", trainer.id, i + 1), error);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn randomize_encounters(&mut self) -> eyre::Result<()> {
+        let tags = TagRules::new(
+            &self.profile.tags,
+            self.trainer_order.as_ref(),
+            &self.profile.encounters.map_splits,
         );
+        let similarity = self.profile.encounters.similarity.strategy();
+        let rules = EncounterRules {
+            global_replace_table: self.profile.encounters.mode == EncounterMode::Global,
+            evolutions: &self.profile.evolutions,
+            similarity: similarity.as_ref(),
+            tags: &tags,
+            formes: &self.profile.formes,
+        };
+        self.encounters
+            .randomize(&self.pokedex, &mut self.rng, &rules)?;
+
+        Ok(())
     }
 
-    fn generate_pokeemerald_documentation(
-        &mut self,
-        option: EmeraldExpansionOption,
-    ) -> eyre::Result<()> {
-        let html_assets_dir = self.cli_options.output_directory.join("assets");
+    pub fn generate_documentation(&mut self) -> eyre::Result<()> {
+        let html_assets_dir = self.profile.output_directory.join("assets");
         let html_assets_pkmn_dir = html_assets_dir.join("pkmn");
         let html_assets_trainer_dir = html_assets_dir.join("trainer");
 
         std::fs::create_dir_all(&html_assets_pkmn_dir)?;
         std::fs::create_dir_all(&html_assets_trainer_dir)?;
 
-        // Copy trainer and mons sprites
-        let trainer_pics_dir = option.project_path.join("graphics/trainers/front_pics");
-        let mons_pics_dir = option.project_path.join("graphics/pokemon");
-
         let parties = if let Some(ref trainer_order) = self.trainer_order {
             let mut res = Vec::new();
             for trainer in trainer_order.trainers.iter() {
-                res.push(self.parties.get(&trainer.id).unwrap().clone())
+                let party = self
+                    .parties
+                    .get(&trainer.id)
+                    .ok_or(EngineError::MissingTrainer(trainer.id.clone()))?;
+                res.push(party.clone())
             }
             Parties::new(res)
         } else {
             self.parties.clone()
         };
 
+        // Copy trainer and mons sprites
         for trainer in parties.iter() {
-            let mut trainer_pic_filename =
-                PathBuf::from(trainer.pic.to_lowercase().replace(' ', "_"));
-            trainer_pic_filename.add_extension("png");
+            let trainer_sprite = self.backend.trainer_sprite(trainer)?;
+            let mut trainer_pic_path = html_assets_trainer_dir.join(&trainer_sprite.name);
+            trainer_pic_path.add_extension("png");
 
             // Ideally handle properly all the possible sprites.
             // Right now this is a wrokaround for may and brendan sprites needing an edge case
-            if !std::fs::exists(trainer_pics_dir.join(&trainer_pic_filename))? {
-                tracing::warn!("{} not found", trainer_pic_filename.display());
+            let Some(trainer_pic) = trainer_sprite.path else {
+                tracing::warn!("{}.png not found", trainer_sprite.name);
                 continue;
-            }
+            };
 
-            if !std::fs::exists(html_assets_trainer_dir.join(&trainer_pic_filename))? {
-                std::fs::copy(
-                    trainer_pics_dir.join(&trainer_pic_filename),
-                    html_assets_trainer_dir.join(&trainer_pic_filename),
-                )?;
+            if !std::fs::exists(&trainer_pic_path)? {
+                std::fs::copy(trainer_pic, trainer_pic_path)?;
             }
 
             // Do the same for each mon in the party
-            for maybe_mon in trainer.party.iter() {
-                if let Some(mon) = maybe_mon {
-                    let mut save_path = html_assets_pkmn_dir.join(&mon.species_normalized);
-                    save_path.add_extension("png");
+            for mon in trainer.party.iter().flatten() {
+                let sprite = self.backend.pokemon_sprite(&self.pokedex, &mon.species)?;
+                let mut save_path = html_assets_pkmn_dir.join(&sprite.name);
+                save_path.add_extension("png");
 
-                    if std::fs::exists(&save_path)? {
-                        continue;
-                    }
-
-                    let mon_anim_front_pic_path = mons_pics_dir
-                        .join(&mon.species_normalized)
-                        .join("anim_front.png");
-                    let mon_front_pic_path = mons_pics_dir
-                        .join(&mon.species_normalized)
-                        .join("front.png");
-
-                    let mon_pic = if std::fs::exists(&mon_anim_front_pic_path)? {
-                        mon_anim_front_pic_path
-                    } else if std::fs::exists(&mon_front_pic_path)? {
-                        mon_front_pic_path
-                    } else {
-                        tracing::warn!("cannot find front pic for {}", mon.species_normalized);
-                        continue;
-                    };
-
-                    let image = image::ImageReader::open(mon_pic)?
-                        .decode()?
-                        .crop_imm(0, 0, 64, 64);
-
-                    image.save(save_path)?;
+                if std::fs::exists(&save_path)? {
+                    continue;
                 }
+
+                let Some(mon_pic) = sprite.path else {
+                    tracing::warn!("cannot find front pic for {}", sprite.name);
+                    continue;
+                };
+
+                let image = image::ImageReader::open(mon_pic)?
+                    .decode()?
+                    .crop_imm(0, 0, 64, 64);
+
+                image.save(save_path)?;
             }
         }
 
-        let trainer_templates: Vec<TrainerTemplate> =
-            parties.iter().map(Clone::clone).map(Into::into).collect();
-        let trainer_list_template: TrainerListTemplate = trainer_templates.into();
+        let order_entries: Vec<_> = match self.trainer_order {
+            Some(ref trainer_order) => trainer_order
+                .trainers
+                .iter()
+                .zip(levels::level_caps(trainer_order))
+                .map(Some)
+                .collect(),
+            None => vec![None; parties.len()],
+        };
+        let trainer_templates = parties
+            .iter()
+            .zip(order_entries)
+            .map(|(trainer, order_entry)| {
+                let template = TrainerTemplate::new(
+                    trainer,
+                    &self.pokedex,
+                    self.movedex.as_ref(),
+                    self.backend.as_ref(),
+                )?;
+                Ok(match order_entry {
+                    Some((entry, cap)) => template.with_order_entry(entry, cap),
+                    None => template,
+                })
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+        let tags = TagRules::new(
+            &self.profile.tags,
+            self.trainer_order.as_ref(),
+            &self.profile.encounters.map_splits,
+        );
+        let trainer_list_template =
+            TrainerListTemplate::new(trainer_templates, tags.excluded_species(&self.pokedex));
 
         let res = trainer_list_template.render()?;
-        let mut file = File::create(self.cli_options.output_directory.join("trainers.html"))?;
+        let mut file = File::create(self.profile.output_directory.join("trainers.html"))?;
         file.write_all(res.as_bytes())?;
 
+        std::fs::copy(
+            "templates/styles.css",
+            self.profile.output_directory.join("styles.css"),
+        )?;
+
         Ok(())
     }
+}
 
-    pub fn generate_documentation(&mut self) -> eyre::Result<()> {
-        match &self.cli_options.project {
-            crate::cli::ProjectOption::EmeraldExpansion(option) => {
-                self.generate_pokeemerald_documentation(option.clone())?;
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    use super::*;
+    use crate::{
+        backend::emerald_expansion::EmeraldExpansion,
+        cli::EmeraldExpansionOption,
+        database::pokedex::load_pokedex,
+        encounters,
+        engine::similarity::{AsymmetricBand, SimilarityStrategy},
+        parties::party::PokemonSet,
+    };
+
+    fn set(format: &str) -> PokemonBundleSet {
+        serde_json::from_value(serde_json::json!({
+            "format": format,
+            "name": format,
+            "moves": [],
+            "item": [],
+        }))
+        .unwrap()
+    }
 
-        std::fs::copy("templates/styles.css", "output/styles.css")?;
+    #[test]
+    fn queries_candidates_with_sets_in_the_bst_window() {
+        let pokedex = load_pokedex(Path::new("pokedex.json")).unwrap();
+        let set_bundle: SetBundle = [
+            ("zubat", "gen9ou"),
+            ("geodude", "gen9lc"),
+            ("onix", "gen9ou"),
+            ("graveler", "gen9ou"),
+            ("golem", "gen9ou"),
+        ]
+        .into_iter()
+        .map(|(species, format)| (pokedex.resolve(species).unwrap(), vec![set(format)]))
+        .collect();
+        let tiers = TierRules::new(
+            &toml::from_str(
+                r#"
+                [[stages]]
+                level = 30
+                formats = ["gen9ou"]
+                allowed = ["gen9ou"]
+                "#,
+            )
+            .unwrap(),
+            None,
+        );
+        let tags = TagRules::default();
+        let candidates = SetCandidates::new(&pokedex, &set_bundle, &tiers);
+        let filter = SlotFilter {
+            trainer_id: "TRAINER_X",
+            double_battle: false,
+            theme: None,
+            tags: &tags,
+            tiers: &tiers,
+            candidates: &candidates,
+        };
+        let names = |species: &[&PokemonDatabaseEntry]| -> Vec<String> {
+            species.iter().map(|entry| entry.id.to_string()).collect()
+        };
 
-        Ok(())
+        // Only species with sets, sorted by BST
+        let pool = filter.species(&pokedex, 20);
+        assert_eq!(
+            names(&pool),
+            ["zubat", "geodude", "onix", "graveler", "golem"]
+        );
+        assert!(!filter.has_sets(&pokedex["pikachu"], 20));
+
+        // Graveler has a BST of 390, Onix 385 and Golem 495
+        let band = AsymmetricBand {
+            plus: 100,
+            minus: 10,
+        };
+        let graveler = &pokedex["graveler"];
+        assert_eq!(
+            names(&band.candidates(graveler, &pool)),
+            ["onix", "graveler"]
+        );
+
+        // From level 30 on, Geodude has no set the tiers allow
+        let pool = filter.species(&pokedex, 30);
+        assert_eq!(names(&pool), ["zubat", "onix", "graveler", "golem"]);
+        assert!(!filter.has_sets(&pokedex["geodude"], 30));
+        let band = AsymmetricBand {
+            plus: 100,
+            minus: 100,
+        };
+        assert_eq!(
+            names(&band.candidates(&pokedex["geodude"], &pool)),
+            ["zubat", "onix", "graveler"]
+        );
+    }
+
+    /// Engine whose only sets are one of each of `species`.
+    pub(super) fn engine(species: &[&str], rerolls: u32) -> Engine<SmallRng> {
+        let pokedex = load_pokedex(Path::new("pokedex.json")).unwrap();
+        let set_bundle = species
+            .iter()
+            .map(|species| (pokedex.resolve(species).unwrap(), vec![set("gen9ou")]))
+            .collect();
+        let mut profile = Profile::default();
+        profile.trainers.clauses.rerolls = rerolls;
+
+        Engine {
+            parties: Parties::new(vec![]),
+            encounters: Box::new(encounters::emerald_expansion::Encounters {
+                wild_encounter_groups: vec![],
+                species_constants: Default::default(),
+            }),
+            pokedex,
+            movedex: None,
+            legality: None,
+            set_bundle,
+            pinned: Parties::new(vec![]),
+            profile,
+            backend: Box::new(EmeraldExpansion::new(EmeraldExpansionOption {
+                project_path: "pokeemerald-expansion".into(),
+                trainers_party_file_path: "src/data/trainers.party".into(),
+                party_dialect: Default::default(),
+                encounters_file_path: "src/data/wild_encounters.json".into(),
+                trainer_order_path: "trainer_order.toml".into(),
+                no_trainer_order: true,
+            })),
+            rng: Box::new(SmallRng::seed_from_u64(0)),
+            trainer_order: None,
+        }
+    }
+
+    #[test]
+    fn rerolls_slots_breaking_a_clause_up_to_the_limit() {
+        let clauses = ClauseSet {
+            species: true,
+            ..Default::default()
+        };
+        let tags = TagRules::default();
+        let tiers = TierRules::default();
+
+        // Zubat has a BST of 245, Pidgey 251
+        for (species, rerolls, broken) in [
+            (&["zubat", "pidgey"][..], 20, None),
+            (&["zubat"][..], 3, Some("species")),
+            (&["zubat"][..], 0, Some("species")),
+        ] {
+            let mut engine = engine(species, rerolls);
+            let candidates = SetCandidates::new(&engine.pokedex, &engine.set_bundle, &tiers);
+            let filter = SlotFilter {
+                trainer_id: "TRAINER_X",
+                double_battle: false,
+                theme: None,
+                tags: &tags,
+                tiers: &tiers,
+                candidates: &candidates,
+            };
+            let zubat = PokemonSet {
+                species: engine.pokedex.resolve("zubat").unwrap(),
+                ..Default::default()
+            };
+
+            let (set, clause) = engine
+                .generate_slot(
+                    &zubat,
+                    20,
+                    &filter,
+                    &clauses,
+                    std::slice::from_ref(&zubat),
+                    None,
+                )
+                .unwrap();
+            assert_eq!(clause, broken, "{:?}", species);
+            let expected = if broken.is_some() { "zubat" } else { "pidgey" };
+            assert_eq!(set.species.as_str(), expected, "{:?}", species);
+        }
     }
 }
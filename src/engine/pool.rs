@@ -0,0 +1,194 @@
+//! Species pool.
+//!
+//! The pool is every species and the alternate formes that the
+//! [`FormePolicy`] makes species of their own, narrowed down by generation,
+//! regional dex and the species the project defines, then adjusted by the
+//! include and exclude lists of the [`PoolPolicy`]. Candidate queries of the
+//! [`Pokedex`] are limited to it.
+
+use std::{collections::BTreeSet, fs::read_to_string, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    backend::GameBackend,
+    database::{
+        pokedex::{Pokedex, PokemonDatabaseEntry},
+        species::SpeciesId,
+    },
+    profile::{FormePolicy, PoolPolicy},
+};
+
+/// Last national dex number of every generation.
+const GENERATION_ENDS: [u64; 9] = [151, 251, 386, 493, 649, 721, 809, 905, 1025];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegionalDex {
+    species: Vec<String>,
+}
+
+/// Generation regional formes were introduced in, by region.
+const REGION_GENERATIONS: [(&str, u8); 4] =
+    [("Alola", 7), ("Galar", 8), ("Hisui", 8), ("Paldea", 9)];
+
+/// Generation of a species, from its region for regional formes or from its
+/// national dex number when the pokedex does not tell.
+fn generation(entry: &PokemonDatabaseEntry) -> Option<u8> {
+    let region = entry
+        .forme
+        .as_ref()
+        .and_then(|forme| forme.split('-').next())
+        .and_then(|region| {
+            REGION_GENERATIONS
+                .iter()
+                .find(|(name, _)| *name == region)
+                .map(|(_, r#gen)| *r#gen)
+        });
+
+    entry.r#gen.or(region).or_else(|| {
+        GENERATION_ENDS
+            .iter()
+            .position(|&end| entry.num >= 1 && entry.num <= end)
+            .map(|index| index as u8 + 1)
+    })
+}
+
+fn load_regional_dex(path: &Path, pokedex: &Pokedex) -> eyre::Result<BTreeSet<SpeciesId>> {
+    let content =
+        read_to_string(path).map_err(|error| eyre::eyre!("{}: {error}", path.display()))?;
+    let dex: RegionalDex = toml::from_str(&content)?;
+
+    dex.species
+        .iter()
+        .map(|species| {
+            pokedex
+                .resolve(species)
+                .map_err(|error| eyre::eyre!("{}: {error}", path.display()))
+        })
+        .collect()
+}
+
+/// Resolves the species pool of `policy`.
+pub fn resolve_pool(
+    policy: &PoolPolicy,
+    formes: &FormePolicy,
+    pokedex: &Pokedex,
+    backend: &dyn GameBackend,
+) -> eyre::Result<BTreeSet<SpeciesId>> {
+    let regional_dex = policy
+        .regional_dex
+        .as_ref()
+        .map(|path| load_regional_dex(path, pokedex))
+        .transpose()?;
+    let project_species = if policy.project_species_only {
+        let species = backend.project_species(pokedex)?.ok_or(eyre::eyre!(
+            "the project backend cannot tell which species the project has"
+        ))?;
+        Some(species)
+    } else {
+        None
+    };
+
+    let mut pool: BTreeSet<SpeciesId> = pokedex
+        .values()
+        .filter(|entry| formes.is_species(pokedex.forme_class(entry)))
+        .filter(|entry| {
+            policy.generations.is_empty()
+                || generation(entry).is_some_and(|r#gen| policy.generations.contains(&r#gen))
+        })
+        .filter(|entry| {
+            regional_dex
+                .as_ref()
+                .is_none_or(|dex| dex.contains(&entry.id))
+        })
+        .filter(|entry| {
+            project_species
+                .as_ref()
+                .is_none_or(|species| species.contains(&entry.id))
+        })
+        .map(|entry| entry.id.clone())
+        .collect();
+
+    for species in policy.include.iter() {
+        pool.insert(pokedex.resolve(species)?);
+    }
+    for species in policy.exclude.iter() {
+        pool.remove(&pokedex.resolve(species)?);
+    }
+
+    Ok(pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::emerald_expansion::EmeraldExpansion, cli::EmeraldExpansionOption,
+        database::pokedex::load_pokedex,
+    };
+
+    fn resolve(policy: &str) -> eyre::Result<BTreeSet<SpeciesId>> {
+        let pokedex = load_pokedex(Path::new("pokedex.json")).unwrap();
+        let backend = EmeraldExpansion::new(EmeraldExpansionOption {
+            project_path: "pokeemerald-expansion".into(),
+            trainers_party_file_path: "src/data/trainers.party".into(),
+            party_dialect: Default::default(),
+            encounters_file_path: "src/data/wild_encounters.json".into(),
+            trainer_order_path: "trainer_order.toml".into(),
+            no_trainer_order: true,
+        });
+
+        resolve_pool(
+            &toml::from_str(policy).unwrap(),
+            &FormePolicy::default(),
+            &pokedex,
+            &backend,
+        )
+    }
+
+    fn contains(pool: &BTreeSet<SpeciesId>, species: &str) -> bool {
+        pool.iter().any(|id| id.as_str() == species)
+    }
+
+    #[test]
+    fn narrows_the_pool_to_the_regional_dex() {
+        let pool = resolve(r#"regional_dex = "dexes/hoenn.toml""#).unwrap();
+        assert_eq!(pool.len(), 202);
+        assert!(contains(&pool, "treecko"));
+        assert!(contains(&pool, "zubat"));
+        assert!(!contains(&pool, "bulbasaur"));
+        // Only the formes the dex lists
+        assert!(contains(&pool, "deoxys"));
+        assert!(!contains(&pool, "deoxysattack"));
+
+        let pool = resolve(
+            r#"
+            regional_dex = "dexes/hoenn.toml"
+            generations = [3]
+            "#,
+        )
+        .unwrap();
+        assert!(contains(&pool, "treecko"));
+        assert!(!contains(&pool, "zubat"));
+    }
+
+    #[test]
+    fn applies_inclusions_then_exclusions() {
+        let pool = resolve(
+            r#"
+            regional_dex = "dexes/hoenn.toml"
+            include = ["Bulbasaur", "Mew"]
+            exclude = ["Rayquaza", "SPECIES_ZUBAT", "mew"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(pool.len(), 202 + 1 - 2);
+        assert!(contains(&pool, "bulbasaur"));
+        assert!(!contains(&pool, "rayquaza"));
+        assert!(!contains(&pool, "zubat"));
+        assert!(!contains(&pool, "mew"));
+
+        assert!(resolve(r#"exclude = ["Missingno"]"#).is_err());
+    }
+}
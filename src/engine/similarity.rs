@@ -0,0 +1,346 @@
+//! Species similarity.
+//!
+//! A [`SimilarityStrategy`] narrows a pool of species down to the ones that
+//! can replace a given species. Strategies are built from a
+//! [`SimilarityPolicy`] of the profile, separately for trainers and wild
+//! encounters, as a BST band optionally wrapped by stat distribution and
+//! shared type filters, and by a widening fallback.
+
+use crate::{
+    database::pokedex::PokemonDatabaseEntry,
+    profile::{BandKind, SimilarityPolicy},
+};
+
+/// Band wide enough to contain every BST difference.
+const MAX_BAND: u32 = 1200;
+
+pub trait SimilarityStrategy {
+    /// Species of `pool` that can replace `original`, in the order of `pool`.
+    /// `pool` is sorted by BST, as the species queries of the pokedex are.
+    fn candidates<'a>(
+        &self,
+        original: &PokemonDatabaseEntry,
+        pool: &[&'a PokemonDatabaseEntry],
+    ) -> Vec<&'a PokemonDatabaseEntry>;
+}
+
+/// Species whose BST is within a percentage of the original one.
+pub struct PercentBand {
+    pub percent: u32,
+}
+
+impl SimilarityStrategy for PercentBand {
+    fn candidates<'a>(
+        &self,
+        original: &PokemonDatabaseEntry,
+        pool: &[&'a PokemonDatabaseEntry],
+    ) -> Vec<&'a PokemonDatabaseEntry> {
+        let bst = original.base_stats.total();
+        let band = bst * self.percent / 100;
+
+        AsymmetricBand {
+            plus: band,
+            minus: band,
+        }
+        .candidates(original, pool)
+    }
+}
+
+/// Species whose BST is at most `plus` above and `minus` below the original
+/// one.
+pub struct AsymmetricBand {
+    pub plus: u32,
+    pub minus: u32,
+}
+
+impl SimilarityStrategy for AsymmetricBand {
+    fn candidates<'a>(
+        &self,
+        original: &PokemonDatabaseEntry,
+        pool: &[&'a PokemonDatabaseEntry],
+    ) -> Vec<&'a PokemonDatabaseEntry> {
+        let bst = original.base_stats.total();
+
+        // The pool being sorted by BST, the band is a slice of it
+        let start =
+            pool.partition_point(|candidate| candidate.base_stats.total() + self.minus < bst);
+        let end = pool.partition_point(|candidate| candidate.base_stats.total() <= bst + self.plus);
+
+        pool[start..end].to_vec()
+    }
+}
+
+#[derive(PartialEq, Eq)]
+enum Lean {
+    Physical,
+    Special,
+    Mixed,
+}
+
+/// Keeps the candidates of `inner` attacking on the same side and in the same
+/// speed tier as the original.
+pub struct StatDistribution {
+    pub inner: Box<dyn SimilarityStrategy>,
+}
+
+impl StatDistribution {
+    /// Attack stats closer than this are considered mixed.
+    const MIXED_MARGIN: u8 = 15;
+
+    fn lean(entry: &PokemonDatabaseEntry) -> Lean {
+        let stats = &entry.base_stats;
+        if stats.atk > stats.spa.saturating_add(Self::MIXED_MARGIN) {
+            Lean::Physical
+        } else if stats.spa > stats.atk.saturating_add(Self::MIXED_MARGIN) {
+            Lean::Special
+        } else {
+            Lean::Mixed
+        }
+    }
+
+    fn speed_tier(entry: &PokemonDatabaseEntry) -> u8 {
+        match entry.base_stats.spe {
+            0..50 => 0,
+            50..80 => 1,
+            80..100 => 2,
+            _ => 3,
+        }
+    }
+}
+
+impl SimilarityStrategy for StatDistribution {
+    fn candidates<'a>(
+        &self,
+        original: &PokemonDatabaseEntry,
+        pool: &[&'a PokemonDatabaseEntry],
+    ) -> Vec<&'a PokemonDatabaseEntry> {
+        let lean = Self::lean(original);
+        let speed_tier = Self::speed_tier(original);
+
+        self.inner
+            .candidates(original, pool)
+            .into_iter()
+            .filter(|candidate| {
+                Self::lean(candidate) == lean && Self::speed_tier(candidate) == speed_tier
+            })
+            .collect()
+    }
+}
+
+/// Prefers the candidates of `inner` sharing a type with the original, falling
+/// back to all of them when none does.
+pub struct SharedType {
+    pub inner: Box<dyn SimilarityStrategy>,
+}
+
+impl SimilarityStrategy for SharedType {
+    fn candidates<'a>(
+        &self,
+        original: &PokemonDatabaseEntry,
+        pool: &[&'a PokemonDatabaseEntry],
+    ) -> Vec<&'a PokemonDatabaseEntry> {
+        let candidates = self.inner.candidates(original, pool);
+        let sharing: Vec<_> = candidates
+            .iter()
+            .filter(|candidate| {
+                candidate
+                    .types
+                    .iter()
+                    .any(|r#type| original.types.contains(r#type))
+            })
+            .copied()
+            .collect();
+
+        if sharing.is_empty() {
+            candidates
+        } else {
+            sharing
+        }
+    }
+}
+
+/// Widens the band of the policy by `step` until it gives at least
+/// `min_candidates` candidates.
+pub struct Widening {
+    pub policy: SimilarityPolicy,
+    pub step: u32,
+    pub min_candidates: usize,
+}
+
+impl SimilarityStrategy for Widening {
+    fn candidates<'a>(
+        &self,
+        original: &PokemonDatabaseEntry,
+        pool: &[&'a PokemonDatabaseEntry],
+    ) -> Vec<&'a PokemonDatabaseEntry> {
+        let mut policy = self.policy.clone();
+        loop {
+            let candidates = policy.band_strategy().candidates(original, pool);
+            let widest = match policy.band {
+                BandKind::Percent => {
+                    policy.percent >= 100 * MAX_BAND / original.base_stats.total().max(1)
+                }
+                BandKind::Asymmetric => policy.plus >= MAX_BAND && policy.minus >= MAX_BAND,
+            };
+            if candidates.len() >= self.min_candidates || widest || self.step == 0 {
+                return candidates;
+            }
+
+            policy.plus += self.step;
+            policy.minus += self.step;
+            policy.percent += self.step;
+        }
+    }
+}
+
+impl SimilarityPolicy {
+    /// Strategy described by the policy.
+    pub fn strategy(&self) -> Box<dyn SimilarityStrategy> {
+        if self.min_candidates > 0 {
+            Box::new(Widening {
+                policy: self.clone(),
+                step: self.widen_step,
+                min_candidates: self.min_candidates,
+            })
+        } else {
+            self.band_strategy()
+        }
+    }
+
+    /// Band of the policy with its filters, without the widening fallback.
+    fn band_strategy(&self) -> Box<dyn SimilarityStrategy> {
+        let mut strategy: Box<dyn SimilarityStrategy> = match self.band {
+            BandKind::Percent => Box::new(PercentBand {
+                percent: self.percent,
+            }),
+            BandKind::Asymmetric => Box::new(AsymmetricBand {
+                plus: self.plus,
+                minus: self.minus,
+            }),
+        };
+        if self.stat_distribution {
+            strategy = Box::new(StatDistribution { inner: strategy });
+        }
+        if self.prefer_shared_type {
+            strategy = Box::new(SharedType { inner: strategy });
+        }
+
+        strategy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::database::pokedex::{Pokedex, load_pokedex};
+
+    /// Species of the tests sorted by BST: Zubat 245, Geodude 300, Onix 385,
+    /// Graveler 390, Machoke 405 and Golem 495.
+    const POOL: [&str; 6] = ["zubat", "geodude", "onix", "graveler", "machoke", "golem"];
+
+    fn pokedex() -> Pokedex {
+        load_pokedex(Path::new("pokedex.json")).unwrap()
+    }
+
+    fn candidates(
+        strategy: &dyn SimilarityStrategy,
+        pokedex: &Pokedex,
+        original: &str,
+    ) -> Vec<String> {
+        let pool: Vec<_> = POOL.iter().map(|id| &pokedex[*id]).collect();
+        strategy
+            .candidates(&pokedex[original], &pool)
+            .into_iter()
+            .map(|entry| entry.id.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_bst_window() {
+        let pokedex = pokedex();
+        let asymmetric = AsymmetricBand { plus: 0, minus: 5 };
+        assert_eq!(
+            candidates(&asymmetric, &pokedex, "graveler"),
+            ["onix", "graveler"]
+        );
+        let asymmetric = AsymmetricBand {
+            plus: 105,
+            minus: 0,
+        };
+        assert_eq!(
+            candidates(&asymmetric, &pokedex, "graveler"),
+            ["graveler", "machoke", "golem"]
+        );
+
+        // 10% of 390 is 39, from 351 to 429
+        let percent = PercentBand { percent: 10 };
+        assert_eq!(
+            candidates(&percent, &pokedex, "graveler"),
+            ["onix", "graveler", "machoke"]
+        );
+    }
+
+    #[test]
+    fn matches_types_and_stat_distribution() {
+        let pokedex = pokedex();
+        let band = || {
+            Box::new(AsymmetricBand {
+                plus: 20,
+                minus: 10,
+            })
+        };
+
+        let shared_type = SharedType { inner: band() };
+        assert_eq!(
+            candidates(&shared_type, &pokedex, "graveler"),
+            ["onix", "graveler"]
+        );
+        // Onix is as strong on both sides and faster
+        let stat_distribution = StatDistribution { inner: band() };
+        assert_eq!(
+            candidates(&stat_distribution, &pokedex, "graveler"),
+            ["graveler", "machoke"]
+        );
+        // No Electric candidate, the band is kept as it is
+        let shared_type = SharedType {
+            inner: Box::new(AsymmetricBand {
+                plus: 25,
+                minus: 25,
+            }),
+        };
+        assert_eq!(candidates(&shared_type, &pokedex, "pikachu"), ["geodude"]);
+    }
+
+    #[test]
+    fn widens_the_band_until_it_has_candidates() {
+        let pokedex = pokedex();
+        let policy = SimilarityPolicy {
+            plus: 0,
+            minus: 0,
+            min_candidates: 0,
+            ..Default::default()
+        };
+        assert!(candidates(&*policy.strategy(), &pokedex, "pikachu").is_empty());
+
+        // Pikachu has a BST of 320, Geodude is 20 below
+        let policy = SimilarityPolicy {
+            min_candidates: 1,
+            widen_step: 10,
+            ..policy
+        };
+        assert_eq!(
+            candidates(&*policy.strategy(), &pokedex, "pikachu"),
+            ["geodude"]
+        );
+
+        // Gives up at the widest band
+        let widening = Widening {
+            policy,
+            step: 10,
+            min_candidates: POOL.len() + 1,
+        };
+        assert_eq!(candidates(&widening, &pokedex, "pikachu"), POOL);
+    }
+}
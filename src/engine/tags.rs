@@ -0,0 +1,99 @@
+//! Pokedex tag rules.
+//!
+//! Species tagged "Restricted Legendary", "Mythical", "Sub-Legendary", ... can
+//! be kept out of trainer parties and wild encounters, or only let in from a
+//! given split of the trainer order onwards. Splits are ordered by their first
+//! appearance in the trainer order, wild maps getting theirs from the
+//! `map_splits` table of the profile.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    database::pokedex::{Pokedex, PokemonDatabaseEntry},
+    engine::trainer_order::{Splits, TrainerOrder},
+    profile::{Availability, TagRule},
+};
+
+/// Species a tag rule keeps out somewhere, for the spoiler log.
+pub struct ExcludedSpecies {
+    pub species: String,
+    pub tag: String,
+    pub trainers: Availability,
+    pub wild: Availability,
+}
+
+#[derive(Default)]
+pub struct TagRules {
+    rules: Vec<TagRule>,
+    splits: Splits,
+    /// Split of the wild maps, by map constant
+    map_splits: BTreeMap<String, String>,
+}
+
+impl TagRules {
+    pub fn new(
+        rules: &[TagRule],
+        trainer_order: Option<&TrainerOrder>,
+        map_splits: &BTreeMap<String, String>,
+    ) -> Self {
+        Self {
+            rules: rules.to_vec(),
+            splits: trainer_order.map(TrainerOrder::splits).unwrap_or_default(),
+            map_splits: map_splits.clone(),
+        }
+    }
+
+    fn is_available(&self, availability: &Availability, split: Option<&str>) -> bool {
+        match availability {
+            Availability::Always => true,
+            Availability::Never => false,
+            Availability::From(from) => self.splits.reached(from, split),
+        }
+    }
+
+    fn rules_of<'a>(
+        &'a self,
+        entry: &'a PokemonDatabaseEntry,
+    ) -> impl Iterator<Item = &'a TagRule> {
+        self.rules
+            .iter()
+            .filter(|rule| entry.tags.contains(&rule.tag))
+    }
+
+    /// Whether the party of the trainer `trainer_id` may contain `entry`.
+    pub fn allows_trainer(&self, entry: &PokemonDatabaseEntry, trainer_id: &str) -> bool {
+        let split = self.splits.of_trainer(trainer_id);
+        self.rules_of(entry)
+            .all(|rule| self.is_available(&rule.trainers, split))
+    }
+
+    /// Whether the map `map` may have `entry` as a wild encounter.
+    pub fn allows_wild(&self, entry: &PokemonDatabaseEntry, map: Option<&str>) -> bool {
+        let split = map
+            .and_then(|map| self.map_splits.get(map))
+            .map(String::as_str);
+        self.rules_of(entry)
+            .all(|rule| self.is_available(&rule.wild, split))
+    }
+
+    /// Species kept out of trainers or wild encounters somewhere, sorted by
+    /// name.
+    pub fn excluded_species(&self, pokedex: &Pokedex) -> Vec<ExcludedSpecies> {
+        let mut excluded = Vec::new();
+        for entry in pokedex.species() {
+            for rule in self.rules_of(entry) {
+                if rule.trainers == Availability::Always && rule.wild == Availability::Always {
+                    continue;
+                }
+                excluded.push(ExcludedSpecies {
+                    species: entry.name.clone(),
+                    tag: rule.tag.clone(),
+                    trainers: rule.trainers.clone(),
+                    wild: rule.wild.clone(),
+                });
+            }
+        }
+
+        excluded
+    }
+}
@@ -0,0 +1,168 @@
+//! Trainer type themes.
+//!
+//! Theme rules of the profile match trainers by class, id pattern and trainer
+//! order split. The first matching rule gives the type of the trainer, picked
+//! once per rule and trainer name so that rematches keep it, and how many of
+//! its Pokemon have that type.
+
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::{
+    bundles::pick,
+    database::pokedex::{Pokedex, PokemonDatabaseEntry},
+    engine::trainer_order::{Splits, TrainerOrder},
+    parties::Trainer,
+    profile::ThemeRule,
+};
+
+/// Type a trainer is themed around, and which slots of its party follow it.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub r#type: String,
+    pub slots: [bool; 6],
+}
+
+impl Theme {
+    pub fn matches(r#type: &str, entry: &PokemonDatabaseEntry) -> bool {
+        entry
+            .types
+            .iter()
+            .any(|entry_type| entry_type.eq_ignore_ascii_case(r#type))
+    }
+}
+
+pub struct Themes {
+    rules: Vec<ThemeRule>,
+    splits: Splits,
+    /// Types of the pokedex, for rules without types
+    types: Vec<String>,
+    /// Type picked for a rule and a trainer name
+    picked: HashMap<(usize, String), String>,
+}
+
+impl Themes {
+    pub fn new(
+        rules: &[ThemeRule],
+        trainer_order: Option<&TrainerOrder>,
+        pokedex: &Pokedex,
+    ) -> Self {
+        Self {
+            rules: rules.to_vec(),
+            splits: trainer_order.map(TrainerOrder::splits).unwrap_or_default(),
+            types: pokedex.types().map(str::to_owned).collect(),
+            picked: HashMap::new(),
+        }
+    }
+
+    fn rule_matches(&self, rule: &ThemeRule, trainer: &Trainer) -> bool {
+        rule.class.as_ref().is_none_or(|class| {
+            trainer
+                .class
+                .as_ref()
+                .is_some_and(|trainer_class| trainer_class.eq_ignore_ascii_case(class))
+        }) && rule
+            .id
+            .as_ref()
+            .is_none_or(|pattern| matches_pattern(pattern, &trainer.id))
+            && rule
+                .split
+                .as_ref()
+                .is_none_or(|split| self.splits.of_trainer(&trainer.id) == Some(split.as_str()))
+    }
+
+    /// Theme of `trainer`, from the first rule matching it.
+    pub fn theme<R: Rng + ?Sized>(&mut self, trainer: &Trainer, rng: &mut R) -> Option<Theme> {
+        let (index, rule) = self
+            .rules
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| self.rule_matches(rule, trainer))?;
+
+        let types = if rule.types.is_empty() {
+            &self.types
+        } else {
+            &rule.types
+        };
+        if types.is_empty() {
+            return None;
+        }
+        let r#type = self
+            .picked
+            .entry((index, trainer.name.clone()))
+            .or_insert_with(|| pick(types, rng).expect("types is not empty").clone())
+            .clone();
+
+        // Themed slots are taken from the end of the party, where aces are
+        let mut remaining = rule.at_least.unwrap_or(6);
+        let mut slots = [false; 6];
+        for (slot, mon) in slots.iter_mut().zip(trainer.party.iter()).rev() {
+            if mon.is_some() && remaining > 0 {
+                *slot = true;
+                remaining -= 1;
+            }
+        }
+
+        Some(Theme { r#type, slots })
+    }
+}
+
+/// Matches `text` against `pattern`, in which `*` stands for any text.
+pub fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<_> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_patterns_without_wildcards_exactly() {
+        assert!(matches_pattern("TRAINER_ROXANNE_1", "TRAINER_ROXANNE_1"));
+        assert!(!matches_pattern("TRAINER_ROXANNE", "TRAINER_ROXANNE_1"));
+        assert!(!matches_pattern("TRAINER_ROXANNE_1", "TRAINER_ROXANNE"));
+        assert!(matches_pattern("", ""));
+        assert!(!matches_pattern("", "ou"));
+    }
+
+    #[test]
+    fn matches_any_text_with_wildcards() {
+        assert!(matches_pattern("TRAINER_ROXANNE_*", "TRAINER_ROXANNE_1"));
+        assert!(matches_pattern("TRAINER_ROXANNE_*", "TRAINER_ROXANNE_"));
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("*ou", "doublesou"));
+        assert!(matches_pattern("vgc*", "vgc2024regg"));
+        assert!(matches_pattern("*_LEADER_*", "TRAINER_LEADER_BRAWLY"));
+        assert!(matches_pattern("a*b*c", "abc"));
+        assert!(matches_pattern("a*b*c", "aXbYbZc"));
+        assert!(!matches_pattern("a*b*c", "acb"));
+        assert!(!matches_pattern("*ou", "ubers"));
+        // Parts do not overlap
+        assert!(!matches_pattern("ab*b", "ab"));
+        assert!(!matches_pattern("a*a", "a"));
+    }
+
+    #[test]
+    fn matches_case_sensitively() {
+        assert!(!matches_pattern("ou", "OU"));
+        assert!(!matches_pattern("trainer_*", "TRAINER_CALVIN_1"));
+    }
+}
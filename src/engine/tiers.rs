@@ -0,0 +1,198 @@
+//! Tier-aware set selection.
+//!
+//! Bundle sets come from Showdown formats, from Little Cup to Ubers. Tier
+//! stages of the profile tell which formats fit each part of the game, a stage
+//! starting at a trainer order split, at a level, or both. The last stage a
+//! Pokemon reached applies, splits being ordered by their first appearance in
+//! the trainer order.
+//!
+//! Sets are taken from the first format of preference that has sets for the
+//! species: the formats of the stage in order, then the doubles formats for
+//! the Pokemon of double battles, then the formats of the earlier stages, the
+//! latest first. Species with none of those get their sets of the formats the
+//! stage allows, or any of their sets when it allows no format in particular.
+
+use crate::{
+    bundles::PokemonBundleSet,
+    engine::{
+        themes::matches_pattern,
+        trainer_order::{Splits, TrainerOrder},
+    },
+    profile::{TierPolicy, TierStage},
+};
+
+#[derive(Default)]
+pub struct TierRules {
+    policy: TierPolicy,
+    splits: Splits,
+}
+
+impl TierRules {
+    pub fn new(policy: &TierPolicy, trainer_order: Option<&TrainerOrder>) -> Self {
+        Self {
+            policy: policy.clone(),
+            splits: trainer_order.map(TrainerOrder::splits).unwrap_or_default(),
+        }
+    }
+
+    fn is_reached(&self, stage: &TierStage, trainer_id: &str, level: u8) -> bool {
+        let split_reached = stage.split.as_ref().is_none_or(|split| {
+            self.splits
+                .reached(split, self.splits.of_trainer(trainer_id))
+        });
+
+        split_reached && stage.level.is_none_or(|from| level >= from)
+    }
+
+    /// Number of stages up to the last one a Pokemon at `level` in the party
+    /// of `trainer_id` reached, 0 if it reached none.
+    pub fn stage(&self, trainer_id: &str, level: u8) -> usize {
+        self.policy
+            .stages
+            .iter()
+            .rposition(|stage| self.is_reached(stage, trainer_id, level))
+            .map_or(0, |reached| reached + 1)
+    }
+
+    /// Every value [`Self::stage`] can take.
+    pub fn stages(&self) -> std::ops::RangeInclusive<usize> {
+        0..=self.policy.stages.len()
+    }
+
+    /// Format patterns in order of preference given the `reached` stages.
+    fn preferences<'a>(&'a self, reached: &'a [TierStage], double_battle: bool) -> Vec<&'a str> {
+        let mut preferences: Vec<&str> = Vec::new();
+        let mut stages = reached.iter().rev();
+        if let Some(stage) = stages.next() {
+            preferences.extend(stage.formats.iter().map(String::as_str));
+        }
+        if double_battle {
+            preferences.extend(self.policy.doubles.iter().map(String::as_str));
+        }
+        for stage in stages {
+            preferences.extend(stage.formats.iter().map(String::as_str));
+        }
+
+        preferences
+    }
+
+    /// Sets of the first format of preference that has some among `sets`,
+    /// the sets of the formats the stage allows if none has. Empty when the
+    /// species has no set the stage allows.
+    pub fn sets<'s>(
+        &self,
+        sets: &'s [PokemonBundleSet],
+        trainer_id: &str,
+        level: u8,
+        double_battle: bool,
+    ) -> Vec<&'s PokemonBundleSet> {
+        self.stage_sets(sets, self.stage(trainer_id, level), double_battle)
+    }
+
+    /// [`Self::sets`] of a Pokemon having reached `stage`.
+    pub fn stage_sets<'s>(
+        &self,
+        sets: &'s [PokemonBundleSet],
+        stage: usize,
+        double_battle: bool,
+    ) -> Vec<&'s PokemonBundleSet> {
+        let reached = &self.policy.stages[..stage];
+        for pattern in self.preferences(reached, double_battle) {
+            let matching: Vec<_> = sets
+                .iter()
+                .filter(|set| set.weight > 0 && matches_pattern(pattern, &set.format))
+                .collect();
+            if !matching.is_empty() {
+                return matching;
+            }
+        }
+
+        let allowed = reached
+            .last()
+            .map(|stage| stage.allowed.as_slice())
+            .unwrap_or_default();
+        sets.iter()
+            .filter(|set| {
+                allowed.is_empty()
+                    || allowed
+                        .iter()
+                        .any(|pattern| matches_pattern(pattern, &set.format))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(format: &str) -> PokemonBundleSet {
+        serde_json::from_value(serde_json::json!({
+            "format": format,
+            "name": format,
+            "moves": [],
+            "item": [],
+        }))
+        .unwrap()
+    }
+
+    fn formats(
+        policy: &str,
+        sets: &[PokemonBundleSet],
+        level: u8,
+        double_battle: bool,
+    ) -> Vec<String> {
+        let rules = TierRules::new(&toml::from_str(policy).unwrap(), None);
+        rules
+            .sets(sets, "TRAINER_X", level, double_battle)
+            .into_iter()
+            .map(|set| set.format.clone())
+            .collect()
+    }
+
+    const STAGES: &str = r#"
+        doubles = ["doublesou"]
+
+        [[stages]]
+        formats = ["lc"]
+
+        [[stages]]
+        level = 30
+        formats = ["ou"]
+        allowed = ["uu", "nu"]
+    "#;
+
+    #[test]
+    fn prefers_the_latest_stage_formats() {
+        let sets = [set("lc"), set("ou"), set("uu")];
+        assert_eq!(formats(STAGES, &sets, 10, false), ["lc"]);
+        assert_eq!(formats(STAGES, &sets, 30, false), ["ou"]);
+        assert_eq!(formats(STAGES, &[set("lc"), set("uu")], 30, false), ["lc"]);
+    }
+
+    #[test]
+    fn tries_doubles_formats_after_the_stage_ones() {
+        let sets = [set("lc"), set("ou"), set("doublesou")];
+        assert_eq!(formats(STAGES, &sets, 30, true), ["ou"]);
+        assert_eq!(formats(STAGES, &sets[..1], 30, true), ["lc"]);
+        assert_eq!(formats(STAGES, &sets[2..], 30, false), Vec::<String>::new());
+        assert_eq!(
+            formats(STAGES, &[set("lc"), set("doublesou")], 30, true),
+            ["doublesou"]
+        );
+        assert_eq!(
+            formats("doubles = [\"doublesou\"]", &sets, 50, true),
+            ["doublesou"]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_allowed_formats() {
+        let sets = [set("ubers"), set("uu"), set("nu")];
+        assert_eq!(formats(STAGES, &sets, 30, false), ["uu", "nu"]);
+        assert_eq!(formats(STAGES, &sets[..1], 30, false), Vec::<String>::new());
+        // Stages allowing no format in particular fall back to any set
+        assert_eq!(formats(STAGES, &sets[..1], 10, false), ["ubers"]);
+        assert_eq!(formats("", &sets[..1], 10, false), ["ubers"]);
+    }
+}
@@ -1,6 +1,6 @@
-use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path};
 
-use crate::cli::ProjectOption;
+use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TrainerOrderEntry {
@@ -17,19 +17,93 @@ pub struct TrainerOrder {
     pub trainers: Vec<TrainerOrderEntry>,
 }
 
-pub fn load_trainer_order(project_options: &ProjectOption) -> eyre::Result<Option<TrainerOrder>> {
-    match project_options {
-        ProjectOption::EmeraldExpansion(ee_options) => {
-            if ee_options.no_trainer_order {
-                return Ok(None);
-            }
+impl TrainerOrder {
+    pub fn splits(&self) -> Splits {
+        let mut splits = Splits::default();
+        for entry in self.trainers.iter() {
+            let position = splits.positions.len();
+            splits
+                .positions
+                .entry(entry.split.clone())
+                .or_insert(position);
+            splits
+                .trainer_splits
+                .entry(entry.id.clone())
+                .or_insert(entry.split.clone());
+        }
+        splits
+    }
+}
+
+/// Splits of a trainer order, ordered by their first appearance in it.
+#[derive(Clone, Debug, Default)]
+pub struct Splits {
+    /// Position of every split
+    positions: HashMap<String, usize>,
+    /// Split of every trainer, by trainer id
+    trainer_splits: HashMap<String, String>,
+}
 
-            let content = std::fs::read_to_string(
-                ee_options.project_path.join(&ee_options.trainer_order_path),
-            )?;
-            let trainer_order: TrainerOrder = toml::from_str(&content)?;
+impl Splits {
+    pub fn position(&self, split: &str) -> Option<usize> {
+        self.positions.get(split).copied()
+    }
+
+    /// Split of the trainer `trainer_id`, from its first entry.
+    pub fn of_trainer(&self, trainer_id: &str) -> Option<&str> {
+        self.trainer_splits.get(trainer_id).map(String::as_str)
+    }
 
-            Ok(Some(trainer_order))
+    /// Whether `split` comes at or after `from`, false if either is not in
+    /// the trainer order.
+    pub fn reached(&self, from: &str, split: Option<&str>) -> bool {
+        let from = self.position(from);
+        let split = split.and_then(|split| self.position(split));
+        matches!((from, split), (Some(from), Some(split)) if split >= from)
+    }
+}
+
+pub fn load_trainer_order(path: &Path) -> eyre::Result<TrainerOrder> {
+    let content = std::fs::read_to_string(path)?;
+    Ok(toml::from_str(&content)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, split: &str) -> TrainerOrderEntry {
+        TrainerOrderEntry {
+            id: id.to_owned(),
+            split: split.to_owned(),
+            sets_level_cap: None,
+            optional: None,
+            location: None,
         }
     }
+
+    #[test]
+    fn orders_splits_by_first_appearance() {
+        let order = TrainerOrder {
+            starter_level_cap: 5,
+            trainers: vec![
+                entry("TRAINER_A", "Roxanne"),
+                entry("TRAINER_B", "Brawly"),
+                entry("TRAINER_C", "Roxanne"),
+                entry("TRAINER_B", "Wattson"),
+            ],
+        };
+        let splits = order.splits();
+
+        assert_eq!(splits.position("Roxanne"), Some(0));
+        assert_eq!(splits.position("Brawly"), Some(1));
+        assert_eq!(splits.position("Wattson"), Some(2));
+        assert_eq!(splits.of_trainer("TRAINER_C"), Some("Roxanne"));
+        assert_eq!(splits.of_trainer("TRAINER_B"), Some("Brawly"));
+        assert!(splits.reached("Roxanne", Some("Brawly")));
+        assert!(splits.reached("Brawly", Some("Brawly")));
+        assert!(!splits.reached("Wattson", Some("Brawly")));
+        assert!(!splits.reached("Norman", Some("Brawly")));
+        assert!(!splits.reached("Roxanne", None));
+    }
 }
@@ -0,0 +1,291 @@
+//! Pre-flight validation of the engine inputs.
+//!
+//! Problems are collected into a [`ValidationReport`] instead of failing on the
+//! first one, so that every issue across parties, encounters, bundles and
+//! trainer order can be reported at once, before anything is written to the
+//! decomp project. Problems a profile only warns about are collected too, and
+//! logged without failing the run.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use super::error::EngineError;
+use crate::profile::CheckLevel;
+
+#[derive(Debug)]
+pub struct Problem {
+    pub file: PathBuf,
+    pub entity: String,
+    pub error: EngineError,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}: {}",
+            self.file.display(),
+            self.entity,
+            self.error
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    problems: Vec<Problem>,
+    warnings: Vec<Problem>,
+}
+
+/// View of a [`ValidationReport`] attaching every problem to a single file.
+pub struct FileReport<'a> {
+    file: &'a Path,
+    report: &'a mut ValidationReport,
+}
+
+impl FileReport<'_> {
+    pub fn path(&self) -> &Path {
+        self.file
+    }
+
+    fn problem(&self, entity: impl Into<String>, error: impl Into<EngineError>) -> Problem {
+        Problem {
+            file: self.file.to_owned(),
+            entity: entity.into(),
+            error: error.into(),
+        }
+    }
+
+    pub fn push(&mut self, entity: impl Into<String>, error: impl Into<EngineError>) {
+        let problem = self.problem(entity, error);
+        self.report.problems.push(problem);
+    }
+
+    pub fn warn(&mut self, entity: impl Into<String>, error: impl Into<EngineError>) {
+        let problem = self.problem(entity, error);
+        self.report.warnings.push(problem);
+    }
+
+    /// Reports a problem as a warning or an error depending on `level`.
+    pub fn push_at(
+        &mut self,
+        level: CheckLevel,
+        entity: impl Into<String>,
+        error: impl Into<EngineError>,
+    ) {
+        match level {
+            CheckLevel::Allow => (),
+            CheckLevel::Warn => self.warn(entity, error),
+            CheckLevel::Deny => self.push(entity, error),
+        }
+    }
+}
+
+impl ValidationReport {
+    pub fn file<'a>(&'a mut self, file: &'a Path) -> FileReport<'a> {
+        FileReport { file, report: self }
+    }
+
+    #[cfg(test)]
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+
+    /// Logs every collected warning and problem, and fails if there is at
+    /// least one problem.
+    pub fn check(&self) -> eyre::Result<()> {
+        for warning in self.warnings.iter() {
+            tracing::warn!("{}", warning);
+        }
+
+        if self.problems.is_empty() {
+            return Ok(());
+        }
+
+        for problem in self.problems.iter() {
+            tracing::error!("{}", problem);
+        }
+
+        eyre::bail!(
+            "Validation failed with {} problem(s), nothing was written",
+            self.problems.len()
+        )
+    }
+}
+
+/// Checks that a level is usable by the game, a missing level meaning 100.
+pub fn check_level(level: Option<u8>) -> Result<u8, EngineError> {
+    match level {
+        None => Ok(100),
+        Some(level @ 1..=100) => Ok(level),
+        Some(level) => Err(EngineError::InvalidLevel(level)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::emerald_expansion::EmeraldExpansion,
+        cli::EmeraldExpansionOption,
+        database::moves::parse_moves,
+        encounters::emerald_expansion::Encounters,
+        engine::{
+            tests::engine,
+            trainer_order::{TrainerOrder, TrainerOrderEntry},
+        },
+        parties::{Parties, Trainer, party::PokemonSet},
+        profile::ThemeRule,
+    };
+
+    fn messages(report: &ValidationReport) -> Vec<String> {
+        report.problems().iter().map(Problem::to_string).collect()
+    }
+
+    fn trainer_order_entry(id: &str) -> TrainerOrderEntry {
+        TrainerOrderEntry {
+            id: id.to_owned(),
+            split: "Roxanne".to_owned(),
+            sets_level_cap: None,
+            optional: None,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn checks_levels() {
+        assert_eq!(check_level(None).unwrap(), 100);
+        assert_eq!(check_level(Some(1)).unwrap(), 1);
+        assert_eq!(check_level(Some(100)).unwrap(), 100);
+        for level in [0, 101] {
+            assert!(matches!(
+                check_level(Some(level)),
+                Err(EngineError::InvalidLevel(invalid)) if invalid == level
+            ));
+        }
+    }
+
+    #[test]
+    fn fails_only_on_denied_problems() {
+        let mut report = ValidationReport::default();
+        let mut file_report = report.file(Path::new("profile"));
+        file_report.push_at(CheckLevel::Allow, "allowed", EngineError::InvalidLevel(0));
+        file_report.push_at(CheckLevel::Warn, "warned", EngineError::InvalidLevel(0));
+        file_report.warn("warned", EngineError::InvalidLevel(0));
+        assert!(report.problems().is_empty());
+        assert_eq!(report.warnings.len(), 2);
+        assert!(report.check().is_ok());
+
+        report.file(Path::new("profile")).push_at(
+            CheckLevel::Deny,
+            "denied",
+            EngineError::InvalidLevel(0),
+        );
+        assert_eq!(
+            messages(&report),
+            ["profile: denied: Invalid level 0, expected a level between 1 and 100"]
+        );
+        assert!(report.check().is_err());
+    }
+
+    #[test]
+    fn reports_every_invalid_input() {
+        let mut engine = engine(&["zubat"], 0);
+        engine.backend = Box::new(EmeraldExpansion::new(EmeraldExpansionOption {
+            project_path: "project".into(),
+            trainers_party_file_path: "trainers.party".into(),
+            party_dialect: Default::default(),
+            encounters_file_path: "wild_encounters.json".into(),
+            trainer_order_path: "trainer_order.toml".into(),
+            no_trainer_order: false,
+        }));
+        let zubat = |level| {
+            Some(PokemonSet {
+                species: engine.pokedex.resolve("zubat").unwrap(),
+                level: Some(level),
+                move_1: Some("Splash".to_owned()),
+                ..Default::default()
+            })
+        };
+        engine.parties = Parties::new(vec![Trainer {
+            id: "TRAINER_ROXANNE_1".to_owned(),
+            party: [zubat(12), zubat(0), None, None, None, None],
+            ..Default::default()
+        }]);
+        engine.trainer_order = Some(TrainerOrder {
+            starter_level_cap: 5,
+            trainers: vec![
+                trainer_order_entry("TRAINER_ROXANNE_1"),
+                trainer_order_entry("TRAINER_BRAWLY_1"),
+            ],
+        });
+        engine.profile.trainers.themes = vec![ThemeRule {
+            class: Some("Leader".to_owned()),
+            id: None,
+            split: None,
+            types: vec!["Rock".to_owned(), "Plastic".to_owned()],
+            at_least: None,
+        }];
+        engine.encounters = Box::new(
+            serde_json::from_value::<Encounters>(serde_json::json!({
+                "wild_encounter_groups": [{
+                    "label": "gWildMonHeaders",
+                    "for_maps": true,
+                    "fields": [],
+                    "encounters": [{
+                        "map": "MAP_ROUTE101",
+                        "base_label": "gRoute101",
+                        "land_mons": {
+                            "encounter_rate": 20,
+                            "mons": [
+                                {"min_level": 2, "max_level": 3, "species": "SPECIES_ZUBAT"},
+                                {"min_level": 2, "max_level": 3, "species": "SPECIES_MISSINGNO"},
+                                {"min_level": 0, "max_level": 3, "species": "SPECIES_ZUBAT"},
+                                {"min_level": 3, "max_level": 2, "species": "SPECIES_ZUBAT"},
+                            ],
+                        },
+                    }],
+                }],
+            }))
+            .unwrap(),
+        );
+
+        let mut report = ValidationReport::default();
+        engine.validate(&mut report);
+        assert_eq!(
+            messages(&report),
+            [
+                "project/trainers.party: TRAINER_ROXANNE_1 slot 2: Invalid level 0, expected a level between 1 and 100",
+                "project/trainer_order.toml: trainers[1]: Trainer TRAINER_BRAWLY_1 does not exist",
+                "profile: trainers.themes[0]: Unknown type \"Plastic\"",
+                "project/wild_encounters.json: gRoute101 land_mons slot 1: Unresolved species \"SPECIES_MISSINGNO\"",
+                "project/wild_encounters.json: gRoute101 land_mons slot 2: Invalid level 0, expected a level between 1 and 100",
+                "project/wild_encounters.json: gRoute101 land_mons slot 3: Invalid level range 3-2",
+            ]
+        );
+
+        // Moves are only checked against a move database
+        let mut report = ValidationReport::default();
+        engine.validate_moves(&mut report);
+        assert!(report.problems().is_empty());
+        engine.movedex = Some(
+            parse_moves(
+                r#"{"tackle": {
+                    "num": 33, "name": "Tackle", "type": "Normal", "category": "Physical",
+                    "basePower": 40, "accuracy": 100, "pp": 35, "target": "normal"
+                }}"#,
+            )
+            .unwrap(),
+        );
+        engine.validate_moves(&mut report);
+        assert_eq!(
+            messages(&report),
+            [
+                "project/trainers.party: TRAINER_ROXANNE_1 slot 1: Unknown move \"Splash\"",
+                "project/trainers.party: TRAINER_ROXANNE_1 slot 2: Unknown move \"Splash\"",
+            ]
+        );
+    }
+}
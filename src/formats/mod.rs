@@ -0,0 +1,110 @@
+//! Data formats of third party tools, converted to and from the formats of the
+//! engine.
+
+use crate::parties::party::PokemonIVs;
+
+pub mod showdown;
+pub mod smogon;
+
+/// IVs giving each type to Hidden Power, every other IV being 31.
+static HIDDEN_POWER_IVS: &[(&str, &[&str])] = &[
+    ("Bug", &["atk", "def", "spd"]),
+    ("Dark", &[]),
+    ("Dragon", &["atk"]),
+    ("Electric", &["spa"]),
+    ("Fighting", &["def", "spa", "spd", "spe"]),
+    ("Fire", &["atk", "spa", "spe"]),
+    ("Flying", &["hp", "atk", "def", "spa", "spd"]),
+    ("Ghost", &["def", "spd"]),
+    ("Grass", &["atk", "spa"]),
+    ("Ground", &["spa", "spd"]),
+    ("Ice", &["atk", "def"]),
+    ("Poison", &["def", "spa", "spd"]),
+    ("Psychic", &["atk", "spe"]),
+    ("Rock", &["def", "spd", "spe"]),
+    ("Steel", &["spd"]),
+    ("Water", &["atk", "def", "spa"]),
+];
+
+pub fn hidden_power_ivs(r#type: &str) -> Option<PokemonIVs> {
+    let (_, thirties) = HIDDEN_POWER_IVS
+        .iter()
+        .find(|(hp_type, _)| hp_type.eq_ignore_ascii_case(r#type))?;
+    let iv = |stat| Some(if thirties.contains(&stat) { 30 } else { 31 });
+
+    Some(PokemonIVs {
+        health: iv("hp"),
+        attack: iv("atk"),
+        defense: iv("def"),
+        sp_attack: iv("spa"),
+        sp_defense: iv("spd"),
+        speed: iv("spe"),
+    })
+}
+
+/// Renames a typed Hidden Power, such as "Hidden Power [Fire]", to the single
+/// Hidden Power the games know, returning its type.
+pub fn strip_hidden_power(r#move: &mut String) -> Option<String> {
+    let r#type = r#move
+        .strip_prefix("Hidden Power ")?
+        .trim_matches(['[', ']'])
+        .to_owned();
+    *r#move = "Hidden Power".to_owned();
+
+    Some(r#type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hidden Power type the IVs give, by the formula of the games.
+    fn hidden_power_type(ivs: &PokemonIVs) -> &'static str {
+        const TYPES: [&str; 16] = [
+            "Fighting", "Flying", "Poison", "Ground", "Rock", "Bug", "Ghost", "Steel", "Fire",
+            "Water", "Grass", "Electric", "Psychic", "Ice", "Dragon", "Dark",
+        ];
+        let bits = [
+            ivs.health,
+            ivs.attack,
+            ivs.defense,
+            ivs.speed,
+            ivs.sp_attack,
+            ivs.sp_defense,
+        ];
+        let sum: usize = bits
+            .iter()
+            .enumerate()
+            .map(|(i, iv)| usize::from(iv.unwrap() & 1) << i)
+            .sum();
+        TYPES[sum * 15 / 63]
+    }
+
+    #[test]
+    fn gives_hidden_power_its_type() {
+        for (r#type, _) in HIDDEN_POWER_IVS {
+            let ivs = hidden_power_ivs(r#type).unwrap();
+            assert_eq!(hidden_power_type(&ivs), *r#type);
+        }
+        assert_eq!(hidden_power_ivs("fire"), hidden_power_ivs("Fire"));
+        assert_eq!(hidden_power_ivs("Fairy"), None);
+        assert_eq!(hidden_power_ivs("Normal"), None);
+    }
+
+    #[test]
+    fn strips_the_type_of_hidden_power() {
+        let mut r#move = "Hidden Power [Fire]".to_owned();
+        assert_eq!(strip_hidden_power(&mut r#move).as_deref(), Some("Fire"));
+        assert_eq!(r#move, "Hidden Power");
+
+        let mut r#move = "Hidden Power Ice".to_owned();
+        assert_eq!(strip_hidden_power(&mut r#move).as_deref(), Some("Ice"));
+        assert_eq!(r#move, "Hidden Power");
+
+        for untyped in ["Hidden Power", "Tackle"] {
+            let mut r#move = untyped.to_owned();
+            assert_eq!(strip_hidden_power(&mut r#move), None);
+            assert_eq!(r#move, untyped);
+        }
+    }
+}
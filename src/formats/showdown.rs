@@ -0,0 +1,469 @@
+//! Showdown team pastes.
+//!
+//! Sets are written the way the Showdown teambuilder exports them. Trainers
+//! become teams headed by `=== [format] TRAINER_ID ===`, so that a dump of the
+//! generated trainers imports into the teambuilder at once, and hand-written
+//! pastes in the same shape can pin the party of a trainer.
+
+use std::path::{Path, PathBuf};
+
+use super::{hidden_power_ivs, strip_hidden_power};
+use crate::{
+    database::{pokedex::Pokedex, species::to_id},
+    parties::{
+        Parties, Trainer,
+        emerald_expansion::parse_species_line,
+        error::{PartyError, Span},
+        party::{PokemonGender, PokemonIVs, PokemonSet},
+    },
+};
+
+/// Formats of the teams, custom games allowing the levels of the game.
+const SINGLES_FORMAT: &str = "gen9customgame";
+const DOUBLES_FORMAT: &str = "gen9doublescustomgame";
+
+fn syntax_error(line: usize, message: impl Into<String>) -> PartyError {
+    PartyError::Syntax {
+        span: Span { line, column: 1 },
+        message: message.into(),
+    }
+}
+
+/// Writes an `EVs: 252 Atk / 4 SpD / 252 Spe` line, None when no stat is
+/// given.
+fn write_stats(label: &str, stats: &PokemonIVs) -> Option<String> {
+    let stats: Vec<_> = stats
+        .stats()
+        .into_iter()
+        .filter_map(|(stat, value)| value.map(|value| format!("{value} {stat}")))
+        .collect();
+
+    (!stats.is_empty()).then(|| format!("{label}: {}", stats.join(" / ")))
+}
+
+pub fn write_set(set: &PokemonSet, pokedex: &Pokedex) -> Result<String, PartyError> {
+    let entry = pokedex.entry(set.species.as_str())?;
+    let species = set.cosmetic_forme.as_ref().unwrap_or(&entry.name);
+
+    let mut first_line = match set.nickname {
+        Some(ref nickname) if nickname != species => format!("{nickname} ({species})"),
+        _ => species.clone(),
+    };
+    if set.gender != PokemonGender::None {
+        first_line.push_str(&format!(" ({})", String::from(set.gender)));
+    }
+    if let Some(ref item) = set.held_item {
+        first_line.push_str(&format!(" @ {item}"));
+    }
+
+    let mut lines = vec![first_line];
+    lines.extend(
+        set.ability
+            .as_ref()
+            .map(|ability| format!("Ability: {ability}")),
+    );
+    // Written even at 100, as sets without a level are pinned at the level of
+    // their slot
+    lines.extend(set.level.map(|level| format!("Level: {level}")));
+    if set.shiny {
+        lines.push("Shiny: Yes".to_owned());
+    }
+    lines.extend(
+        set.happiness
+            .map(|happiness| format!("Happiness: {happiness}")),
+    );
+    lines.extend(set.ball.as_ref().map(|ball| format!("Pokeball: {ball}")));
+    lines.extend(
+        set.dynamax_level
+            .map(|level| format!("Dynamax Level: {level}")),
+    );
+    if set.gigantamax {
+        lines.push("Gigantamax: Yes".to_owned());
+    }
+    lines.extend(
+        set.tera_type
+            .as_ref()
+            .map(|r#type| format!("Tera Type: {type}")),
+    );
+    lines.extend(set.evs.as_ref().and_then(|evs| write_stats("EVs", evs)));
+    lines.extend(set.nature.as_ref().map(|nature| format!("{nature} Nature")));
+    lines.extend(set.ivs.as_ref().and_then(|ivs| write_stats("IVs", ivs)));
+    lines.extend(set.moves().map(|r#move| format!("- {move}")));
+
+    let mut paste = lines.join("\n");
+    paste.push('\n');
+
+    Ok(paste)
+}
+
+/// Writes the party of `trainer` as a team named after the trainer id.
+pub fn write_trainer(trainer: &Trainer, pokedex: &Pokedex) -> Result<String, PartyError> {
+    let format = if trainer.double_battle {
+        DOUBLES_FORMAT
+    } else {
+        SINGLES_FORMAT
+    };
+
+    let mut paste = format!("=== [{format}] {} ===\n", trainer.id);
+    for set in trainer.party.iter().flatten() {
+        paste.push('\n');
+        paste.push_str(&write_set(set, pokedex)?);
+    }
+
+    Ok(paste)
+}
+
+/// Parses an `EVs` or `IVs` value, such as `252 Atk / 4 SpD / 252 Spe`.
+fn parse_stats(number: usize, value: &str) -> Result<PokemonIVs, PartyError> {
+    let mut stats = PokemonIVs::default();
+    for part in value.split('/') {
+        let Some((amount, label)) = part.trim().split_once(' ') else {
+            return Err(syntax_error(
+                number,
+                format!("expected `<value> <stat>`, found `{}`", part.trim()),
+            ));
+        };
+        let amount = amount
+            .parse()
+            .map_err(|_| syntax_error(number, format!("invalid stat value `{amount}`")))?;
+        let Some(stat) = stats.stat_mut(label.trim()) else {
+            return Err(syntax_error(number, format!("unknown stat `{label}`")));
+        };
+        *stat = Some(amount);
+    }
+
+    Ok(stats)
+}
+
+fn parse_number<T: std::str::FromStr>(
+    number: usize,
+    value: &str,
+    what: &str,
+) -> Result<T, PartyError> {
+    value
+        .parse()
+        .map_err(|_| syntax_error(number, format!("invalid {what} `{value}`")))
+}
+
+/// Parses a set from its numbered lines.
+fn parse_set(lines: &[(usize, &str)], pokedex: &Pokedex) -> Result<PokemonSet, PartyError> {
+    let (number, first_line) = lines[0];
+    let (nickname, name, gender, held_item) = parse_species_line(first_line)?;
+    let species = pokedex
+        .resolve(&name)
+        .map_err(|error| syntax_error(number, error.to_string()))?;
+    // Cosmetic formes resolve to their base species, the name keeps the look
+    let cosmetic_forme = pokedex.get(&species).and_then(|entry| {
+        entry
+            .cosmetic_formes
+            .iter()
+            .find(|forme| to_id(forme) == to_id(&name))
+            .cloned()
+    });
+
+    let mut set = PokemonSet {
+        nickname,
+        species,
+        cosmetic_forme,
+        gender,
+        held_item,
+        ..Default::default()
+    };
+    let mut moves = Vec::new();
+    let mut hidden_power_type = None;
+
+    for &(number, line) in &lines[1..] {
+        if let Some(r#move) = line.strip_prefix('-') {
+            if moves.len() == 4 {
+                return Err(syntax_error(number, "more than 4 moves"));
+            }
+            let mut r#move = r#move.trim().to_owned();
+            if let Some(r#type) = strip_hidden_power(&mut r#move) {
+                hidden_power_type = Some(r#type);
+            }
+            moves.push(r#move);
+            continue;
+        }
+        if let Some(nature) = line.strip_suffix(" Nature") {
+            set.nature = Some(nature.trim().to_owned());
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            return Err(syntax_error(number, format!("unexpected line `{line}`")));
+        };
+        let value = value.trim();
+        let yes = value == "Yes";
+        match key.trim() {
+            "Ability" => set.ability = Some(value.to_owned()),
+            "Level" => set.level = Some(parse_number(number, value, "level")?),
+            "Shiny" => set.shiny = yes,
+            "Happiness" => set.happiness = Some(parse_number(number, value, "happiness")?),
+            "Pokeball" | "Ball" => set.ball = Some(value.to_owned()),
+            "Dynamax Level" => {
+                set.dynamax_level = Some(parse_number(number, value, "dynamax level")?)
+            }
+            "Gigantamax" => set.gigantamax = yes,
+            "Tera Type" => set.tera_type = Some(value.to_owned()),
+            "EVs" => set.evs = Some(parse_stats(number, value)?),
+            "IVs" => set.ivs = Some(parse_stats(number, value)?),
+            "Hidden Power" => hidden_power_type = Some(value.to_owned()),
+            key => tracing::warn!("line {number}: unknown Showdown key `{key}`, ignored"),
+        }
+    }
+
+    // The games only know a single Hidden Power, its type comes from the IVs
+    if set.ivs.is_none() {
+        set.ivs = hidden_power_type.as_deref().and_then(hidden_power_ivs);
+    }
+
+    let mut moves = moves.into_iter();
+    set.move_1 = moves.next();
+    set.move_2 = moves.next();
+    set.move_3 = moves.next();
+    set.move_4 = moves.next();
+
+    Ok(set)
+}
+
+/// Parses a `=== [format] TRAINER_ID ===` header into a trainer without a
+/// party, None for lines that are not headers.
+fn parse_header(line: &str) -> Option<Trainer> {
+    let header = line.strip_prefix("===")?.strip_suffix("===")?.trim();
+    let (format, id) = match header
+        .strip_prefix('[')
+        .and_then(|header| header.split_once(']'))
+    {
+        Some((format, id)) => (format, id.trim()),
+        None => ("", header),
+    };
+
+    Some(Trainer {
+        id: id.to_owned(),
+        double_battle: format.contains("doubles"),
+        ..Default::default()
+    })
+}
+
+/// Parses the teams of a paste into trainers named after the team. A paste
+/// without headers is a single team with an empty name.
+pub fn from_showdown_paste(paste: &str, pokedex: &Pokedex) -> Result<Vec<Trainer>, PartyError> {
+    let mut trainers = Vec::new();
+    let mut trainer = Trainer::default();
+    let mut sets: Vec<PokemonSet> = Vec::new();
+    let mut set_lines: Vec<(usize, &str)> = Vec::new();
+
+    let mut end_team = |trainer: Trainer, sets: &mut Vec<PokemonSet>, number: usize| {
+        if sets.len() > trainer.party.len() {
+            return Err(syntax_error(
+                number,
+                format!("more than 6 Pokemon in team `{}`", trainer.id),
+            ));
+        }
+        if !trainer.id.is_empty() || !sets.is_empty() {
+            let mut trainer = trainer;
+            for (slot, set) in trainer.party.iter_mut().zip(sets.drain(..)) {
+                *slot = Some(set);
+            }
+            trainers.push(trainer);
+        }
+        Ok(())
+    };
+
+    for (number, line) in paste.lines().enumerate().map(|(i, line)| (i + 1, line)) {
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with("===") {
+            set_lines.push((number, line));
+            continue;
+        }
+
+        if !set_lines.is_empty() {
+            sets.push(parse_set(&set_lines, pokedex)?);
+            set_lines.clear();
+        }
+        if line.starts_with("===") {
+            let Some(header) = parse_header(line) else {
+                return Err(syntax_error(
+                    number,
+                    "unterminated team header, expected `===`",
+                ));
+            };
+            end_team(std::mem::replace(&mut trainer, header), &mut sets, number)?;
+        }
+    }
+    if !set_lines.is_empty() {
+        sets.push(parse_set(&set_lines, pokedex)?);
+    }
+    let last_line = paste.lines().count();
+    end_team(trainer, &mut sets, last_line)?;
+
+    Ok(trainers)
+}
+
+/// Loads the teams pinned to trainers, each team being named after the id of
+/// its trainer.
+pub fn load_pinned_teams(paths: &[PathBuf], pokedex: &Pokedex) -> eyre::Result<Parties> {
+    let mut teams = Vec::new();
+    for path in paths {
+        for team in load_paste(path, pokedex)? {
+            if team.id.is_empty() {
+                eyre::bail!(
+                    "{}: pinned teams need a `=== TRAINER_ID ===` header",
+                    path.display()
+                );
+            }
+            teams.push(team);
+        }
+    }
+
+    Ok(Parties::new(teams))
+}
+
+fn load_paste(path: &Path, pokedex: &Pokedex) -> eyre::Result<Vec<Trainer>> {
+    let content = std::fs::read_to_string(path)?;
+    from_showdown_paste(&content, pokedex)
+        .map_err(|error| eyre::eyre!("{}: {error}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::pokedex::load_pokedex;
+
+    fn pokedex() -> Pokedex {
+        load_pokedex(Path::new("pokedex.json")).unwrap()
+    }
+
+    fn trainers(pokedex: &Pokedex) -> Vec<Trainer> {
+        let charizard = PokemonSet {
+            nickname: Some("Blaze".to_owned()),
+            species: pokedex.resolve("Charizard").unwrap(),
+            gender: PokemonGender::Female,
+            held_item: Some("Heavy-Duty Boots".to_owned()),
+            ability: Some("Solar Power".to_owned()),
+            level: Some(50),
+            shiny: true,
+            happiness: Some(0),
+            ball: Some("Ultra Ball".to_owned()),
+            dynamax_level: Some(10),
+            gigantamax: true,
+            tera_type: Some("Dragon".to_owned()),
+            evs: Some(PokemonIVs {
+                sp_attack: Some(252),
+                sp_defense: Some(4),
+                speed: Some(252),
+                ..Default::default()
+            }),
+            nature: Some("Timid".to_owned()),
+            ivs: Some(PokemonIVs {
+                attack: Some(0),
+                ..Default::default()
+            }),
+            move_1: Some("Flamethrower".to_owned()),
+            move_2: Some("Air Slash".to_owned()),
+            move_3: Some("Hidden Power".to_owned()),
+            move_4: Some("Roost".to_owned()),
+            ..Default::default()
+        };
+        let burmy = PokemonSet {
+            species: pokedex.resolve("Burmy").unwrap(),
+            cosmetic_forme: Some("Burmy-Sandy".to_owned()),
+            move_1: Some("Protect".to_owned()),
+            ..Default::default()
+        };
+
+        vec![
+            Trainer {
+                id: "TRAINER_RED".to_owned(),
+                party: [Some(charizard), Some(burmy), None, None, None, None],
+                ..Default::default()
+            },
+            Trainer {
+                id: "TRAINER_TATE_AND_LIZA_1".to_owned(),
+                double_battle: true,
+                party: [
+                    Some(PokemonSet {
+                        species: pokedex.resolve("Lunatone").unwrap(),
+                        level: Some(42),
+                        ..Default::default()
+                    }),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                ],
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn reads_back_written_teams() {
+        let pokedex = pokedex();
+        let trainers = trainers(&pokedex);
+        let paste: String = trainers
+            .iter()
+            .map(|trainer| write_trainer(trainer, &pokedex).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let parsed = from_showdown_paste(&paste, &pokedex).unwrap();
+        assert_eq!(parsed.len(), trainers.len());
+        for (written, read) in trainers.iter().zip(parsed.iter()) {
+            assert_eq!(written.id, read.id);
+            assert_eq!(written.double_battle, read.double_battle);
+            assert_eq!(written.party, read.party);
+        }
+    }
+
+    #[test]
+    fn writes_sets_as_the_teambuilder_does() {
+        let pokedex = pokedex();
+        let trainers = trainers(&pokedex);
+        assert_eq!(
+            write_trainer(&trainers[1], &pokedex).unwrap(),
+            "=== [gen9doublescustomgame] TRAINER_TATE_AND_LIZA_1 ===\n\nLunatone\nLevel: 42\n"
+        );
+        let charizard = write_set(trainers[0].party[0].as_ref().unwrap(), &pokedex).unwrap();
+        assert!(charizard.starts_with("Blaze (Charizard) (F) @ Heavy-Duty Boots\n"));
+        assert!(charizard.contains("\nEVs: 252 SpA / 4 SpD / 252 Spe\nTimid Nature\nIVs: 0 Atk\n"));
+    }
+
+    #[test]
+    fn keeps_the_hidden_power_type_as_ivs() {
+        let pokedex = pokedex();
+        let paste = "Raichu @ Light Ball\nLevel: 30\n- Hidden Power [Ice]\n- Thunderbolt\n";
+        let [team] = from_showdown_paste(paste, &pokedex)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(team.id, "");
+        let raichu = team.party[0].as_ref().unwrap();
+        assert_eq!(raichu.move_1.as_deref(), Some("Hidden Power"));
+        assert_eq!(raichu.ivs, hidden_power_ivs("Ice"));
+    }
+
+    #[test]
+    fn reports_malformed_pastes_at_their_line() {
+        let pokedex = pokedex();
+        let error = |paste: &str| {
+            from_showdown_paste(paste, &pokedex)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            error("=== TRAINER_X ===\n\nPikachu\nLevel: high\n"),
+            "line 4, column 1: invalid level `high`"
+        );
+        assert_eq!(
+            error("=== TRAINER_X\n"),
+            "line 1, column 1: unterminated team header, expected `===`"
+        );
+        assert_eq!(
+            error(&"Pikachu\n\n".repeat(7)),
+            "line 14, column 1: more than 6 Pokemon in team ``"
+        );
+    }
+}
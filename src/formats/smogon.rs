@@ -0,0 +1,557 @@
+//! Importer of the pkmn/smogon set data.
+//!
+//! Sets files are keyed by species then format (`sets/gen9.json`), or by
+//! species only when they hold a single format (`sets/gen9ou.json`). Usage
+//! stats files (`stats/gen9ou.json`) become one set per species, every option
+//! weighing its usage. Analyses files, shaped like sets files, can restrict the
+//! import to the sets that have a written analysis.
+//!
+//! Species are renamed to their pokedex name, and the type of Hidden Power is
+//! kept as the IVs giving it, the games only knowing a single Hidden Power.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::Path,
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, de::DeserializeOwned};
+
+use super::{hidden_power_ivs, strip_hidden_power};
+use crate::{
+    bundles::{BundleOption, BundleOptions, PokemonBundleSet},
+    database::pokedex::Pokedex,
+    engine::themes::matches_pattern,
+    parties::party::{PokemonEVs, PokemonIVs},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SmogonData {
+    /// Sets written by Smogon analysts
+    Sets,
+    /// Usage statistics of a single format
+    Stats,
+}
+
+/// What to import from a pkmn/smogon file.
+pub struct ImportOptions {
+    pub data: SmogonData,
+    /// Format of the file when it holds a single format
+    pub format: Option<String>,
+    /// Formats to import, every format when empty, `*` standing for any text
+    pub include: Vec<String>,
+    /// Formats never imported
+    pub exclude: Vec<String>,
+    /// Analyses file the sets must have an analysis in
+    pub analyses: Option<std::path::PathBuf>,
+}
+
+/// What an import kept and dropped.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub species: usize,
+    pub sets: usize,
+    /// Species missing from the pokedex
+    pub unknown_species: BTreeSet<String>,
+    /// Sets of the formats left out by the filters, by format
+    pub excluded_formats: BTreeMap<String, usize>,
+    /// Sets without an analysis
+    pub without_analysis: usize,
+    /// Species of a stats file without any move usage
+    pub without_moves: Vec<String>,
+    /// Sets whose Hidden Power type was turned into IVs
+    pub hidden_power_ivs: usize,
+    /// Sets whose Hidden Power type was lost, for having several or an
+    /// unknown one
+    pub hidden_power_lost: Vec<String>,
+}
+
+impl Display for ImportReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Imported {} sets of {} species", self.sets, self.species)?;
+        if !self.unknown_species.is_empty() {
+            let species: Vec<_> = self.unknown_species.iter().map(String::as_str).collect();
+            writeln!(
+                f,
+                "Dropped {} species missing from the pokedex: {}",
+                species.len(),
+                species.join(", ")
+            )?;
+        }
+        if !self.excluded_formats.is_empty() {
+            let formats: Vec<_> = self
+                .excluded_formats
+                .iter()
+                .map(|(format, sets)| format!("{format} ({sets})"))
+                .collect();
+            writeln!(
+                f,
+                "Dropped the sets of {} excluded formats: {}",
+                formats.len(),
+                formats.join(", ")
+            )?;
+        }
+        if self.without_analysis > 0 {
+            writeln!(
+                f,
+                "Dropped {} sets without an analysis",
+                self.without_analysis
+            )?;
+        }
+        if !self.without_moves.is_empty() {
+            writeln!(
+                f,
+                "Dropped {} species without move usage: {}",
+                self.without_moves.len(),
+                self.without_moves.join(", ")
+            )?;
+        }
+        if self.hidden_power_ivs > 0 {
+            writeln!(
+                f,
+                "Kept the Hidden Power type of {} sets as IVs",
+                self.hidden_power_ivs
+            )?;
+        }
+        if !self.hidden_power_lost.is_empty() {
+            writeln!(
+                f,
+                "Lost the Hidden Power type of {} sets: {}",
+                self.hidden_power_lost.len(),
+                self.hidden_power_lost.join(", ")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    fn into_vec(self) -> Vec<T> {
+        match self {
+            Self::One(value) => vec![value],
+            Self::Many(values) => values,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SmogonSet {
+    moves: Vec<OneOrMany<String>>,
+    ability: Option<OneOrMany<String>>,
+    item: Option<OneOrMany<String>>,
+    nature: Option<OneOrMany<String>>,
+    evs: Option<OneOrMany<PokemonEVs>>,
+    ivs: Option<OneOrMany<PokemonIVs>>,
+    teratypes: Option<OneOrMany<String>>,
+}
+
+/// Sets of a species in a format, by set name.
+type FormatSets<T> = BTreeMap<String, T>;
+
+/// Usage stats of a format, in the pkmn/smogon shape or in the chaos shape of
+/// Smogon itself.
+#[derive(Deserialize)]
+struct UsageStats {
+    #[serde(alias = "data")]
+    pokemon: BTreeMap<String, SpeciesUsage>,
+}
+
+#[derive(Deserialize)]
+struct SpeciesUsage {
+    #[serde(default, alias = "Abilities")]
+    abilities: BTreeMap<String, f64>,
+    #[serde(default, alias = "Items")]
+    items: BTreeMap<String, f64>,
+    #[serde(default, alias = "Moves")]
+    moves: BTreeMap<String, f64>,
+    /// Usage of "Nature:HP/Atk/Def/SpA/SpD/Spe" spreads
+    #[serde(default, alias = "stats", alias = "Spreads")]
+    spreads: BTreeMap<String, f64>,
+    #[serde(default, rename = "teraTypes", alias = "Tera Types")]
+    tera_types: BTreeMap<String, f64>,
+}
+
+/// Moves of a usage set, spread round robin over the four slots.
+const USAGE_MOVES: usize = 8;
+/// EV spreads of a usage set.
+const USAGE_SPREADS: usize = 6;
+
+/// Gives `set` the IVs of its Hidden Power type when it has a single one. Sets
+/// with IVs of their own are left as they are, their IVs already giving the
+/// type in the older generations.
+fn apply_hidden_power(
+    set: &mut PokemonBundleSet,
+    types: BTreeSet<String>,
+    entity: String,
+    report: &mut ImportReport,
+) {
+    if types.is_empty() || set.ivs.is_some() {
+        return;
+    }
+
+    let types: Vec<_> = types.into_iter().collect();
+    match types.as_slice() {
+        [r#type] => match hidden_power_ivs(r#type) {
+            Some(ivs) => {
+                set.ivs = Some(vec![BundleOption::Plain(ivs)]);
+                report.hidden_power_ivs += 1;
+            }
+            None => report.hidden_power_lost.push(entity),
+        },
+        _ => report.hidden_power_lost.push(entity),
+    }
+}
+
+fn plain<T>(options: Option<OneOrMany<T>>) -> Option<BundleOptions<T>> {
+    options.map(|options| {
+        options
+            .into_vec()
+            .into_iter()
+            .map(BundleOption::Plain)
+            .collect()
+    })
+}
+
+fn non_empty<T>(options: BundleOptions<T>) -> Option<BundleOptions<T>> {
+    (!options.is_empty()).then_some(options)
+}
+
+fn is_no_item(item: &str) -> bool {
+    item.is_empty() || item.eq_ignore_ascii_case("No Item") || item.eq_ignore_ascii_case("Nothing")
+}
+
+fn convert_set(
+    species: &str,
+    format: &str,
+    name: String,
+    set: SmogonSet,
+    report: &mut ImportReport,
+) -> PokemonBundleSet {
+    let mut hidden_power_types = BTreeSet::new();
+    let moves = set
+        .moves
+        .into_iter()
+        .map(|slot| {
+            let mut moves = Vec::new();
+            for mut r#move in slot.into_vec() {
+                hidden_power_types.extend(strip_hidden_power(&mut r#move));
+                if !moves.contains(&r#move) {
+                    moves.push(r#move);
+                }
+            }
+            moves.into_iter().map(BundleOption::Plain).collect()
+        })
+        .collect();
+    let item = set
+        .item
+        .map(OneOrMany::into_vec)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|item| !is_no_item(item))
+        .map(BundleOption::Plain)
+        .collect();
+
+    let entity = format!("{species} \"{name}\"");
+    let mut bundle_set = PokemonBundleSet {
+        format: format.to_owned(),
+        name,
+        weight: 1,
+        moves,
+        item,
+        nature: plain(set.nature),
+        ability: plain(set.ability),
+        evs: plain(set.evs),
+        ivs: plain(set.ivs),
+        tera_types: plain(set.teratypes),
+        gigantamax: false,
+    };
+    apply_hidden_power(&mut bundle_set, hidden_power_types, entity, report);
+
+    bundle_set
+}
+
+/// Options weighing their share of `usage`, in thousandths, the options too
+/// rare to weigh anything being left out.
+fn weighted<T>(usage: impl IntoIterator<Item = (T, f64)>) -> BundleOptions<T> {
+    let usage: Vec<_> = usage.into_iter().collect();
+    let total: f64 = usage.iter().map(|(_, share)| share).sum();
+    usage
+        .into_iter()
+        .map(|(value, share)| BundleOption::Weighted {
+            value,
+            weight: (share / total * 1000.0).round() as u32,
+        })
+        .filter(|option| option.weight() > 0)
+        .collect()
+}
+
+/// Entries of `usage` from the most to the least used.
+fn by_usage(usage: BTreeMap<String, f64>) -> Vec<(String, f64)> {
+    let mut usage: Vec<_> = usage.into_iter().collect();
+    usage.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    usage
+}
+
+/// Parses a "Nature:HP/Atk/Def/SpA/SpD/Spe" spread.
+fn parse_spread(spread: &str) -> Option<(String, PokemonEVs)> {
+    let (nature, evs) = spread.split_once(':')?;
+    let evs: Vec<u8> = evs
+        .split('/')
+        .map(|ev| ev.parse().ok())
+        .collect::<Option<_>>()?;
+    let [hp, atk, def, spa, spd, spe] = evs[..] else {
+        return None;
+    };
+    let ev = |value| (value > 0).then_some(value);
+
+    Some((
+        nature.to_owned(),
+        PokemonEVs {
+            health: ev(hp),
+            attack: ev(atk),
+            defense: ev(def),
+            sp_attack: ev(spa),
+            sp_defense: ev(spd),
+            speed: ev(spe),
+        },
+    ))
+}
+
+fn usage_set(
+    species: &str,
+    format: &str,
+    usage: SpeciesUsage,
+    report: &mut ImportReport,
+) -> Option<PokemonBundleSet> {
+    let mut hidden_power_types = BTreeSet::new();
+    let mut moves: BTreeMap<String, f64> = BTreeMap::new();
+    for (mut r#move, share) in usage.moves {
+        if r#move.is_empty() || r#move.eq_ignore_ascii_case("nothing") {
+            continue;
+        }
+        hidden_power_types.extend(strip_hidden_power(&mut r#move));
+        *moves.entry(r#move).or_default() += share;
+    }
+    let moves: Vec<_> = by_usage(moves).into_iter().take(USAGE_MOVES).collect();
+    if moves.is_empty() {
+        report.without_moves.push(species.to_owned());
+        return None;
+    }
+    let slots = (0..4)
+        .map(|slot| weighted(moves.iter().skip(slot).step_by(4).cloned()))
+        .filter(|slot| !slot.is_empty())
+        .collect();
+
+    let mut natures: BTreeMap<String, f64> = BTreeMap::new();
+    // Spreads only differing by their nature share their EVs
+    let mut spreads: Vec<(PokemonEVs, f64)> = Vec::new();
+    for (spread, share) in by_usage(usage.spreads) {
+        let Some((nature, evs)) = parse_spread(&spread) else {
+            continue;
+        };
+        *natures.entry(nature).or_default() += share;
+        match spreads.iter_mut().find(|(known, _)| *known == evs) {
+            Some((_, total)) => *total += share,
+            None => spreads.push((evs, share)),
+        }
+    }
+    spreads.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    spreads.truncate(USAGE_SPREADS);
+
+    let items = usage
+        .items
+        .into_iter()
+        .filter(|(item, _)| !is_no_item(item));
+    let mut set = PokemonBundleSet {
+        format: format.to_owned(),
+        name: "Usage".to_owned(),
+        weight: 1,
+        moves: slots,
+        item: weighted(items),
+        nature: non_empty(weighted(natures)),
+        ability: non_empty(weighted(usage.abilities)),
+        evs: non_empty(weighted(spreads)),
+        ivs: None,
+        tera_types: non_empty(weighted(usage.tera_types)),
+        gigantamax: false,
+    };
+    let entity = format!("{species} \"Usage\"");
+    apply_hidden_power(&mut set, hidden_power_types, entity, report);
+
+    Some(set)
+}
+
+fn load<T: DeserializeOwned>(path: &Path) -> eyre::Result<T> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|error| eyre::eyre!("{}: {error}", path.display()))?;
+    serde_json::from_str(&content).map_err(|error| eyre::eyre!("{}: {error}", path.display()))
+}
+
+/// Loads a sets or analyses file by species then format, the format being
+/// `format` for single format files.
+fn load_by_format<T: DeserializeOwned>(
+    path: &Path,
+    format: Option<&str>,
+) -> eyre::Result<BTreeMap<String, BTreeMap<String, T>>> {
+    Ok(match format {
+        Some(format) => load::<BTreeMap<String, T>>(path)?
+            .into_iter()
+            .map(|(species, data)| (species, BTreeMap::from([(format.to_owned(), data)])))
+            .collect(),
+        None => load(path)?,
+    })
+}
+
+/// Set names of an analyses file.
+#[derive(Deserialize)]
+struct Analysis {
+    #[serde(default)]
+    sets: BTreeMap<String, serde_json::Value>,
+}
+
+impl ImportOptions {
+    fn includes(&self, format: &str) -> bool {
+        (self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|pattern| matches_pattern(pattern, format)))
+            && !self
+                .exclude
+                .iter()
+                .any(|pattern| matches_pattern(pattern, format))
+    }
+}
+
+/// Converts the pkmn/smogon file at `input` into a bundle, keyed by pokedex
+/// species name.
+pub fn import(
+    input: &Path,
+    pokedex: &Pokedex,
+    options: &ImportOptions,
+) -> eyre::Result<(BTreeMap<String, Vec<PokemonBundleSet>>, ImportReport)> {
+    let mut report = ImportReport::default();
+    let mut bundle: BTreeMap<String, Vec<PokemonBundleSet>> = BTreeMap::new();
+
+    let analyses = options
+        .analyses
+        .as_ref()
+        .map(|path| load_by_format::<Analysis>(path, options.format.as_deref()))
+        .transpose()?;
+    let has_analysis = |species: &str, format: &str, name: &str| {
+        analyses.as_ref().is_none_or(|analyses| {
+            analyses
+                .get(species)
+                .and_then(|formats| formats.get(format))
+                .is_some_and(|analysis| analysis.sets.contains_key(name))
+        })
+    };
+
+    match options.data {
+        SmogonData::Sets => {
+            let sets = load_by_format::<FormatSets<SmogonSet>>(input, options.format.as_deref())?;
+            for (species, formats) in sets {
+                let Ok(entry) = pokedex.entry(&species) else {
+                    report.unknown_species.insert(species);
+                    continue;
+                };
+                for (format, sets) in formats {
+                    if !options.includes(&format) {
+                        *report.excluded_formats.entry(format).or_default() += sets.len();
+                        continue;
+                    }
+                    for (name, set) in sets {
+                        if !has_analysis(&species, &format, &name) {
+                            report.without_analysis += 1;
+                            continue;
+                        }
+                        let set = convert_set(&entry.name, &format, name, set, &mut report);
+                        bundle.entry(entry.name.clone()).or_default().push(set);
+                    }
+                }
+            }
+        }
+        SmogonData::Stats => {
+            let Some(ref format) = options.format else {
+                eyre::bail!("Usage stats hold a single format, which must be given");
+            };
+            let stats: UsageStats = load(input)?;
+            if !options.includes(format) {
+                // A set per species of the file
+                report
+                    .excluded_formats
+                    .insert(format.clone(), stats.pokemon.len());
+                return Ok((bundle, report));
+            }
+            for (species, usage) in stats.pokemon {
+                let Ok(entry) = pokedex.entry(&species) else {
+                    report.unknown_species.insert(species);
+                    continue;
+                };
+                if let Some(set) = usage_set(&entry.name, format, usage, &mut report) {
+                    bundle.entry(entry.name.clone()).or_default().push(set);
+                }
+            }
+        }
+    }
+
+    report.species = bundle.len();
+    report.sets = bundle.values().map(Vec::len).sum();
+
+    Ok((bundle, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_usage_spreads() {
+        let (nature, evs) = parse_spread("Adamant:4/252/0/0/0/252").unwrap();
+        assert_eq!(nature, "Adamant");
+        assert_eq!(
+            evs,
+            PokemonEVs {
+                health: Some(4),
+                attack: Some(252),
+                speed: Some(252),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_spreads() {
+        assert_eq!(parse_spread("Adamant"), None);
+        assert_eq!(parse_spread("Adamant:4/252/0/0/0"), None);
+        assert_eq!(parse_spread("Adamant:4/252/0/0/0/252/0"), None);
+        assert_eq!(parse_spread("Adamant:4/252/0/0/0/256"), None);
+        assert_eq!(parse_spread("Adamant:4/252/x/0/0/252"), None);
+    }
+
+    #[test]
+    fn filters_formats_by_pattern() {
+        let options = |include: &[&str], exclude: &[&str]| ImportOptions {
+            data: SmogonData::Sets,
+            format: None,
+            include: include.iter().map(|format| format.to_string()).collect(),
+            exclude: exclude.iter().map(|format| format.to_string()).collect(),
+            analyses: None,
+        };
+
+        assert!(options(&[], &[]).includes("ou"));
+        assert!(options(&["*ou"], &[]).includes("doublesou"));
+        assert!(!options(&["*ou"], &[]).includes("ubers"));
+        assert!(!options(&["*ou"], &["doubles*"]).includes("doublesou"));
+        assert!(!options(&[], &["ou"]).includes("ou"));
+    }
+}
@@ -0,0 +1,44 @@
+//! Stable content hashing, used to tell files apart across runs.
+//!
+//! `std`'s hashers are not guaranteed to be stable across Rust releases, so a
+//! plain 64 bits FNV-1a is used instead. This is not a cryptographic hash.
+
+use std::path::Path;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+pub fn content_hash(bytes: &[u8]) -> String {
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+
+    format!("{:016x}", hash)
+}
+
+pub fn file_hash(path: &Path) -> std::io::Result<String> {
+    Ok(content_hash(&std::fs::read(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_fnv1a_reference_values() {
+        assert_eq!(content_hash(b""), "cbf29ce484222325");
+        assert_eq!(content_hash(b"a"), "af63dc4c8601ec8c");
+        assert_eq!(content_hash(b"foobar"), "85944171f73967e8");
+    }
+
+    #[test]
+    fn hashes_files_by_content() {
+        let path = std::env::temp_dir().join(format!("hash-test-{}", std::process::id()));
+        std::fs::write(&path, b"foobar").unwrap();
+        let hash = file_hash(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(hash.unwrap(), content_hash(b"foobar"));
+    }
+}
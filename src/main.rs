@@ -5,17 +5,27 @@ use rand::{SeedableRng, rngs::SmallRng};
 use tracing_subscriber::{Layer, filter, layer::SubscriberExt};
 
 use crate::{
-    database::pokedex,
-    engine::{Engine, trainer_order},
+    database::{learnsets, moves, pokedex},
+    engine::{Engine, formes, legality::Legality, pool, validation::ValidationReport},
+    formats::{showdown, smogon},
+    manifest::RunManifest,
+    pristine::Pristine,
+    profile::{LegalityMode, Profile},
 };
 
+mod backend;
 mod bundles;
 mod cli;
 mod database;
 mod doc;
 mod encounters;
 mod engine;
+mod formats;
+mod hash;
+mod manifest;
 mod parties;
+mod pristine;
+mod profile;
 
 fn main() -> eyre::Result<()> {
     let cli = cli::Cli::parse();
@@ -35,40 +45,313 @@ fn main() -> eyre::Result<()> {
         );
     tracing::subscriber::set_global_default(registry)?;
 
-    ////
-    let pokedex = pokedex::load_pokedex(Path::new(&cli.pokedex))?;
+    match cli.command {
+        cli::Command::Generate(ref project) => {
+            let run_manifest = generate(project.clone(), Profile::resolve(&cli)?, cli.force)?;
+            run_manifest.save(&run_manifest.profile.output_directory)
+        }
+        cli::Command::Restore { ref project } => restore(project),
+        cli::Command::Status { ref project } => status(project),
+        cli::Command::Reproduce { ref manifest } => reproduce(manifest, cli.force),
+        cli::Command::Paste {
+            ref split,
+            ref output,
+            ref project,
+        } => paste(
+            project,
+            split.as_deref(),
+            output.as_deref(),
+            Profile::resolve(&cli)?,
+        ),
+        cli::Command::Profile {
+            command: cli::ProfileCommand::Show,
+        } => {
+            print!("{}", Profile::resolve(&cli)?.to_toml()?);
+            Ok(())
+        }
+        cli::Command::Bundle {
+            command: cli::BundleCommand::Import { .. },
+        } => import_bundle(&cli),
+    }
+}
+
+/// Runs a generation, returning its manifest for the caller to save. Managed
+/// files edited by hand are only overwritten when `force` is set.
+fn generate(
+    project: cli::ProjectOption,
+    mut profile: Profile,
+    force: bool,
+) -> eyre::Result<RunManifest> {
+    let mut backend = project.backend();
+    let mut pristine = Pristine::open(backend.project_path(), &backend.managed_files())?;
+    pristine.set_force(force);
+    // Checked before generating anything rather than between two writes
+    for file in backend.managed_files().iter() {
+        pristine.check_writable(file)?;
+    }
+
+    // The seed is always resolved so that it can be recorded in the manifest
+    let seed = *profile.seed.get_or_insert_with(rand::random);
+    let mut pokedex = pokedex::load_pokedex(Path::new(&profile.pokedex))?;
+    if let Some(ref aliases) = profile.species_aliases {
+        pokedex.load_aliases(aliases)?;
+    }
+
+    let pool = pool::resolve_pool(&profile.pool, &profile.formes, &pokedex, backend.as_ref())?;
+    let species_pool = pool.len();
+    tracing::info!("Species pool of {} species", species_pool);
+    pokedex.set_pool(pool);
+
+    // Hashed after the pool so that missing pool inputs report their own error
+    let inputs = manifest::hash_inputs(&profile, backend.as_ref(), &pristine)?;
+
+    let movedex = profile
+        .moves
+        .as_ref()
+        .map(|path| moves::load_moves(path))
+        .transpose()?;
+    let learnsets = profile
+        .learnsets
+        .as_ref()
+        .map(|path| learnsets::load_learnsets(path, &pokedex))
+        .transpose()?;
+
+    let mut report = ValidationReport::default();
 
-    let set_bundle = bundles::load_bundles(&cli.bundles)?;
+    let items = backend.project_items()?;
+    let checks = bundles::BundleChecks::new(
+        &profile.bundle_checks,
+        &pokedex,
+        movedex.as_ref(),
+        items.as_ref(),
+    );
+    let mut set_bundle = bundles::load_bundles(&profile.bundles, &checks, &mut report)?;
+    formes::apply_to_bundle(&mut set_bundle, &pokedex, &profile.formes);
 
-    let parties = parties::load_parties(&cli.project)?;
+    let parties = backend.load_parties(&pristine, &pokedex, &mut report)?;
 
-    let trainer_order = trainer_order::load_trainer_order(&cli.project)?;
+    let trainer_order = backend.load_trainer_order()?;
 
-    let encounters = encounters::load_encounter(&cli.project)?;
-    let rng: SmallRng = if let Some(seed) = cli.seed {
-        SmallRng::seed_from_u64(seed)
-    } else {
-        rand::make_rng()
+    let legality = match (profile.trainers.legality.mode, learnsets) {
+        (LegalityMode::Off, _) => None,
+        (_, Some(learnsets)) if movedex.is_some() => Some(Legality::new(
+            &profile.trainers.legality,
+            learnsets,
+            trainer_order.as_ref(),
+        )),
+        _ => eyre::bail!("The move legality policy needs both a moves and a learnsets file"),
     };
 
+    let pinned = showdown::load_pinned_teams(&profile.trainers.pinned, &pokedex)?;
+
+    let encounters = backend.load_encounters(&pristine)?;
+    let rng = SmallRng::seed_from_u64(seed);
+
     let mut engine = Engine {
         parties,
         encounters,
         pokedex,
+        movedex,
+        legality,
         set_bundle,
-        cli_options: cli,
+        pinned,
+        profile,
+        backend,
         rng: Box::new(rng),
         trainer_order,
     };
 
-    engine.randomize_parties();
-    engine.randomize_encounters();
+    engine.validate(&mut report);
+    report.check()?;
+
+    engine.randomize_parties()?;
+    engine.randomize_encounters()?;
 
-    parties::save_parties(&engine.cli_options.project, &engine.parties)?;
+    // Checked again so that no set is written with a move the game lacks
+    let mut report = ValidationReport::default();
+    engine.validate_moves(&mut report);
+    report.check()?;
 
-    encounters::save_encounter(&engine.cli_options.project, &engine.encounters)?;
+    engine
+        .backend
+        .save_parties(&engine.parties, &engine.pokedex, &mut pristine)?;
+
+    engine
+        .backend
+        .save_encounters(engine.encounters.as_ref(), &mut pristine)?;
 
     engine.generate_documentation()?;
 
+    let run_manifest = RunManifest {
+        engine_version: manifest::ENGINE_VERSION.to_owned(),
+        seed,
+        project,
+        outputs: manifest::hash_outputs(&engine.profile, engine.backend.as_ref())?,
+        profile: engine.profile,
+        inputs,
+        species_pool,
+    };
+    println!("Seed: {}", run_manifest.seed);
+    println!("Species pool: {} species", run_manifest.species_pool);
+    println!(
+        "Documentation: {}",
+        run_manifest
+            .profile
+            .output_directory
+            .join("trainers.html")
+            .display()
+    );
+
+    Ok(run_manifest)
+}
+
+fn paste(
+    project: &cli::ProjectOption,
+    split: Option<&str>,
+    output: Option<&Path>,
+    profile: Profile,
+) -> eyre::Result<()> {
+    let backend = project.backend();
+    let mut pokedex = pokedex::load_pokedex(Path::new(&profile.pokedex))?;
+    if let Some(ref aliases) = profile.species_aliases {
+        pokedex.load_aliases(aliases)?;
+    }
+
+    let mut report = ValidationReport::default();
+    let parties = backend.load_project_parties(&pokedex, &mut report)?;
+    report.check()?;
+
+    let trainers: Vec<_> = match split {
+        None => parties.iter().collect(),
+        Some(split) => {
+            let Some(trainer_order) = backend.load_trainer_order()? else {
+                eyre::bail!("Splits come from the trainer order, which the project lacks");
+            };
+            let mut ids: Vec<&str> = Vec::new();
+            for entry in trainer_order.trainers.iter() {
+                if entry.split == split && !ids.contains(&entry.id.as_str()) {
+                    ids.push(&entry.id);
+                }
+            }
+            if ids.is_empty() {
+                eyre::bail!("No trainer of the trainer order is in split {split}");
+            }
+            ids.into_iter().filter_map(|id| parties.get(id)).collect()
+        }
+    };
+
+    let mut pastes = Vec::new();
+    for trainer in trainers {
+        pastes.push(showdown::write_trainer(trainer, &pokedex)?);
+    }
+    let pastes = pastes.join("\n");
+
+    match output {
+        Some(output) => std::fs::write(output, pastes)?,
+        None => print!("{pastes}"),
+    }
+
+    Ok(())
+}
+
+fn import_bundle(cli: &cli::Cli) -> eyre::Result<()> {
+    let cli::Command::Bundle {
+        command:
+            cli::BundleCommand::Import {
+                ref input,
+                ref output,
+                data,
+                ref format,
+                ref include,
+                ref exclude,
+                ref analyses,
+            },
+    } = cli.command
+    else {
+        unreachable!()
+    };
+
+    let profile = Profile::resolve(cli)?;
+    let mut pokedex = pokedex::load_pokedex(Path::new(&profile.pokedex))?;
+    if let Some(ref aliases) = profile.species_aliases {
+        pokedex.load_aliases(aliases)?;
+    }
+
+    let options = smogon::ImportOptions {
+        data,
+        format: format.clone(),
+        include: include.clone(),
+        exclude: exclude.clone(),
+        analyses: analyses.clone(),
+    };
+    let (bundle, report) = smogon::import(input, &pokedex, &options)?;
+    std::fs::write(output, serde_json::to_string_pretty(&bundle)?)?;
+
+    print!("{report}");
+    println!("Bundle: {}", output.display());
+
+    Ok(())
+}
+
+fn reproduce(manifest_path: &Path, force: bool) -> eyre::Result<()> {
+    let run_manifest = RunManifest::load(manifest_path)?;
+    let profile = run_manifest.profile.clone();
+    let project = run_manifest.project.clone();
+
+    let backend = project.backend();
+    let pristine = Pristine::open(backend.project_path(), &backend.managed_files())?;
+    let input_drift = run_manifest.input_drift(&manifest::hash_inputs(
+        &profile,
+        backend.as_ref(),
+        &pristine,
+    )?);
+    if !input_drift.is_empty() {
+        for drift in input_drift.iter() {
+            println!("input drifted: {}", drift);
+        }
+        eyre::bail!(
+            "Cannot reproduce the run, {} input(s) drifted",
+            input_drift.len()
+        );
+    }
+
+    // The manifest being reproduced is left as is, it usually lives in the
+    // output directory and holds the only record of the original outputs
+    generate(project, profile.clone(), force)?;
+
+    let output_drift =
+        run_manifest.output_drift(&manifest::hash_outputs(&profile, backend.as_ref())?);
+    if !output_drift.is_empty() {
+        for drift in output_drift.iter() {
+            println!("output differs: {}", drift);
+        }
+        eyre::bail!("The run was not reproduced identically");
+    }
+
+    println!("Reproduced seed {} identically", run_manifest.seed);
+
+    Ok(())
+}
+
+fn restore(project: &cli::ProjectOption) -> eyre::Result<()> {
+    let backend = project.backend();
+    let mut pristine = Pristine::open(backend.project_path(), &backend.managed_files())?;
+
+    for file in backend.managed_files().iter() {
+        pristine.restore(file)?;
+        println!("{}: restored", file.display());
+    }
+
+    Ok(())
+}
+
+fn status(project: &cli::ProjectOption) -> eyre::Result<()> {
+    let backend = project.backend();
+    let pristine = Pristine::open(backend.project_path(), &backend.managed_files())?;
+
+    for file in pristine.files() {
+        println!("{}: {}", file.display(), pristine.status(file)?);
+    }
+
     Ok(())
 }
@@ -0,0 +1,231 @@
+//! Run manifests.
+//!
+//! A seed alone cannot reproduce a run once the inputs change, so every run
+//! writes a manifest in the output directory recording the resolved options
+//! along with a content hash of every input and output. The manifest is then
+//! enough to regenerate the exact same output, or to tell which input drifted.
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::GameBackend,
+    cli::ProjectOption,
+    hash::file_hash,
+    pristine::{Pristine, write_atomic},
+    profile::Profile,
+};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+pub const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunManifest {
+    pub engine_version: String,
+    pub seed: u64,
+    pub project: ProjectOption,
+    /// Profile the run was made with, seed included.
+    pub profile: Profile,
+    /// Content hash of every input, keyed by a description of the input.
+    pub inputs: BTreeMap<String, String>,
+    /// Content hash of every generated file.
+    pub outputs: BTreeMap<String, String>,
+    /// Number of species in the resolved species pool.
+    #[serde(default)]
+    pub species_pool: usize,
+}
+
+/// A difference between a manifest and the current state of the files.
+#[derive(Debug)]
+pub struct Drift {
+    pub name: String,
+    pub recorded: Option<String>,
+    pub current: Option<String>,
+}
+
+impl std::fmt::Display for Drift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (recorded {}, now {})",
+            self.name,
+            self.recorded.as_deref().unwrap_or("nothing"),
+            self.current.as_deref().unwrap_or("nothing")
+        )
+    }
+}
+
+fn compare(recorded: &BTreeMap<String, String>, current: &BTreeMap<String, String>) -> Vec<Drift> {
+    let mut keys: Vec<&String> = recorded.keys().chain(current.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter(|key| recorded.get(*key) != current.get(*key))
+        .map(|key| Drift {
+            name: key.clone(),
+            recorded: recorded.get(key).cloned(),
+            current: current.get(key).cloned(),
+        })
+        .collect()
+}
+
+/// Hashes every file the generation depends on.
+pub fn hash_inputs(
+    profile: &Profile,
+    backend: &dyn GameBackend,
+    pristine: &Pristine,
+) -> eyre::Result<BTreeMap<String, String>> {
+    let mut inputs = BTreeMap::new();
+
+    inputs.insert("pokedex".to_owned(), file_hash(&profile.pokedex)?);
+    if let Some(ref aliases) = profile.species_aliases {
+        inputs.insert("species aliases".to_owned(), file_hash(aliases)?);
+    }
+    if let Some(ref moves) = profile.moves {
+        inputs.insert("moves".to_owned(), file_hash(moves)?);
+    }
+    if let Some(ref learnsets) = profile.learnsets {
+        inputs.insert("learnsets".to_owned(), file_hash(learnsets)?);
+    }
+    for bundle in profile.bundles.iter() {
+        inputs.insert(format!("bundle {}", bundle.display()), file_hash(bundle)?);
+    }
+    for paste in profile.trainers.pinned.iter() {
+        inputs.insert(format!("pinned {}", paste.display()), file_hash(paste)?);
+    }
+
+    if let Some(trainer_order) = backend.trainer_order_path() {
+        inputs.insert("trainer order".to_owned(), file_hash(&trainer_order)?);
+    }
+    if let Some(ref regional_dex) = profile.pool.regional_dex {
+        inputs.insert("regional dex".to_owned(), file_hash(regional_dex)?);
+    }
+    if profile.pool.project_species_only
+        && let Some(species) = backend.species_path()
+    {
+        inputs.insert("project species".to_owned(), file_hash(&species)?);
+    }
+
+    for file in pristine.files() {
+        inputs.insert(
+            format!("pristine {}", file.display()),
+            pristine.pristine_hash(file)?,
+        );
+    }
+
+    Ok(inputs)
+}
+
+/// Hashes every file written by a run.
+pub fn hash_outputs(
+    profile: &Profile,
+    backend: &dyn GameBackend,
+) -> eyre::Result<BTreeMap<String, String>> {
+    let mut outputs = BTreeMap::new();
+
+    let documentation = profile.output_directory.join("trainers.html");
+    let files = backend
+        .managed_files()
+        .into_iter()
+        .map(|file| backend.project_path().join(file))
+        .chain([documentation]);
+    for path in files {
+        outputs.insert(path.display().to_string(), file_hash(&path)?);
+    }
+
+    Ok(outputs)
+}
+
+impl RunManifest {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, output_directory: &Path) -> eyre::Result<()> {
+        std::fs::create_dir_all(output_directory)?;
+        write_atomic(
+            &output_directory.join(MANIFEST_FILE),
+            serde_json::to_string_pretty(self)?.as_bytes(),
+        )?;
+
+        Ok(())
+    }
+
+    /// Inputs that changed since the manifest was written, engine included.
+    pub fn input_drift(&self, current: &BTreeMap<String, String>) -> Vec<Drift> {
+        let mut drift = compare(&self.inputs, current);
+        if self.engine_version != ENGINE_VERSION {
+            drift.insert(
+                0,
+                Drift {
+                    name: "engine version".to_owned(),
+                    recorded: Some(self.engine_version.clone()),
+                    current: Some(ENGINE_VERSION.to_owned()),
+                },
+            );
+        }
+
+        drift
+    }
+
+    pub fn output_drift(&self, current: &BTreeMap<String, String>) -> Vec<Drift> {
+        compare(&self.outputs, current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::EmeraldExpansionOption;
+
+    use super::*;
+
+    /// Project holding the trainer and encounter fixtures.
+    fn project(name: &str) -> ProjectOption {
+        let project_path =
+            std::env::temp_dir().join(format!("nge-manifest-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&project_path);
+        std::fs::create_dir_all(project_path.join("src/data")).unwrap();
+        for file in ["trainers.party", "wild_encounters.json"] {
+            std::fs::copy(
+                Path::new("tests/fixtures").join(file),
+                project_path.join("src/data").join(file),
+            )
+            .unwrap();
+        }
+
+        ProjectOption::EmeraldExpansion(EmeraldExpansionOption {
+            project_path,
+            trainers_party_file_path: "src/data/trainers.party".into(),
+            party_dialect: Default::default(),
+            encounters_file_path: "src/data/wild_encounters.json".into(),
+            trainer_order_path: "trainer_order.toml".into(),
+            no_trainer_order: true,
+        })
+    }
+
+    fn outputs(project: &ProjectOption, seed: u64) -> BTreeMap<String, String> {
+        let profile = Profile {
+            seed: Some(seed),
+            output_directory: project.backend().project_path().join("output"),
+            ..Default::default()
+        };
+
+        crate::generate(project.clone(), profile, false)
+            .unwrap()
+            .outputs
+    }
+
+    #[test]
+    fn hashes_the_same_outputs_for_the_same_seed_and_profile() {
+        let project = project("outputs");
+
+        let outputs_of_seed = outputs(&project, 7);
+        assert_eq!(outputs(&project, 7), outputs_of_seed);
+        assert_ne!(outputs(&project, 8), outputs_of_seed);
+
+        std::fs::remove_dir_all(project.backend().project_path()).unwrap();
+    }
+}
@@ -1,233 +1,647 @@
 //! This file contains functions to parse and write from/into the .parties
 //! file format used in the pokemon emerald decomp expansion project.
+//!
+//! The lines every trainer was read from are kept apart from the parsed data,
+//! in [`PartySources`] held by the backend, so that comments, blank lines and
+//! keys unknown to the engine survive a write, and that trainers and Pokemon
+//! left untouched are written back verbatim.
+//!
+//! Parsing is done in two steps: every line is first turned into a [`Token`]
+//! remembering where it comes from, then tokens are grouped into trainers and
+//! Pokemon. An error skips the rest of the trainer it is found in, so that
+//! every broken trainer of the file is reported at once.
+
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{pokedex::Pokedex, species::to_id},
+    engine::validation::FileReport,
+    parties::party::{PokemonGender, PokemonIVs},
+};
+
+use super::{
+    Parties, Trainer,
+    error::{PartyError, Span},
+    party::PokemonSet,
+};
 
-use regex::{Captures, Regex};
+//
+// ------ Source lines
+//
+
+/// A line of the trainer fields section, as read.
+#[derive(Clone, Debug)]
+enum FieldLine {
+    /// A field known to the engine, only rewritten when its value changes.
+    Known {
+        key: String,
+        value: Option<String>,
+        line: String,
+    },
+    /// Comments and keys unknown to the engine.
+    Verbatim(String),
+}
+
+#[derive(Clone, Debug)]
+struct MonSource {
+    /// Blank lines and comments before the Pokemon.
+    leading: Vec<String>,
+    lines: Vec<String>,
+    /// Lines the set does not hold, unknown keys and comments, written back after
+    /// its fields when the Pokemon changed.
+    kept: Vec<String>,
+    /// The set as parsed, None if its species could not be resolved.
+    set: Option<PokemonSet>,
+}
+
+/// Lines a trainer was read from.
+#[derive(Clone, Debug, Default)]
+pub struct TrainerSource {
+    /// Blank lines and comments before the header.
+    leading: Vec<String>,
+    header: String,
+    fields: Vec<FieldLine>,
+    mons: Vec<MonSource>,
+    /// Blank lines and comments at the end of the file.
+    trailing: Vec<String>,
+}
+
+/// Lines every trainer was read from, by trainer id. Trainers created by the
+/// engine have none.
+pub type PartySources = HashMap<String, TrainerSource>;
+
+/// Flavours of the `.party` format.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum Dialect {
+    /// Double battles are set with `Double Battle: Yes`
+    #[default]
+    EmeraldExpansion,
+    /// Double battles are set with `Battle Type: Doubles`
+    BattleType,
+}
+
+impl Dialect {
+    /// Key setting the battle type of a trainer.
+    fn battle_type_key(self) -> &'static str {
+        match self {
+            Dialect::EmeraldExpansion => "Double Battle",
+            Dialect::BattleType => "Battle Type",
+        }
+    }
 
-use crate::parties::party::{PokemonGender, PokemonIVs};
+    /// Fields of a trainer, in the order they are written for new trainers.
+    fn trainer_fields(self) -> [&'static str; 10] {
+        [
+            "Name",
+            "Class",
+            "Pic",
+            "Gender",
+            "Music",
+            "Items",
+            self.battle_type_key(),
+            "AI",
+            "Mugshot",
+            "Starting Status",
+        ]
+    }
 
-use super::{Parties, Trainer, error::PartyError, party::PokemonSet};
+    /// Sets a trainer field from its value in the file, returns false if the
+    /// key is unknown.
+    fn set_trainer_field(
+        self,
+        trainer: &mut Trainer,
+        key: &str,
+        value: &str,
+    ) -> Result<bool, String> {
+        let owned = value.to_owned();
+        match key {
+            "Name" => trainer.name = owned,
+            "Class" => trainer.class = Some(owned),
+            "Pic" => trainer.pic = owned,
+            "Gender" => trainer.gender = Some(owned),
+            "Music" => trainer.music = Some(owned),
+            "Items" => trainer.items = Some(owned),
+            "AI" => trainer.ai = Some(owned),
+            "Mugshot" => trainer.mugshot = Some(owned),
+            "Starting Status" => trainer.starting_status = Some(owned),
+            key if key == self.battle_type_key() => {
+                trainer.double_battle = match (self, value) {
+                    (Dialect::EmeraldExpansion, "Yes") | (Dialect::BattleType, "Doubles") => true,
+                    (Dialect::EmeraldExpansion, "No") | (Dialect::BattleType, "Singles") => false,
+                    _ => return Err(format!("invalid {} `{}`", key, value)),
+                }
+            }
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// Value of a trainer field as written in the file, None if it is not set.
+    fn trainer_field(self, trainer: &Trainer, key: &str) -> Option<String> {
+        match key {
+            "Name" => Some(trainer.name.clone()),
+            "Class" => trainer.class.clone(),
+            "Pic" => Some(trainer.pic.clone()),
+            "Gender" => trainer.gender.clone(),
+            "Music" => trainer.music.clone(),
+            "Items" => trainer.items.clone(),
+            "AI" => trainer.ai.clone(),
+            "Mugshot" => trainer.mugshot.clone(),
+            "Starting Status" => trainer.starting_status.clone(),
+            key if key == self.battle_type_key() => Some(
+                match (self, trainer.double_battle) {
+                    (Dialect::EmeraldExpansion, true) => "Yes",
+                    (Dialect::EmeraldExpansion, false) => "No",
+                    (Dialect::BattleType, true) => "Doubles",
+                    (Dialect::BattleType, false) => "Singles",
+                }
+                .to_owned(),
+            ),
+            _ => None,
+        }
+    }
+}
 
 //
-// ------ Parsing stuff
+// ------ Tokenizing stuff
 //
 
-static DEFAULT_TRAINER_REGEX_DELIMITER: &str =
-    r"=== (?<name>[A-Z0-9_]+) ===\n(?<details>(?:[\w: &/]+\n)+)\n+(?<mons>(?:[\w: /@\-\n]+)*)";
+#[derive(Clone, Copy, Debug)]
+struct Spanned<'a> {
+    text: &'a str,
+    span: Span,
+}
 
-// TODO: Improve so that trailing spaces before \n are ignored
-/// https://regex101.com/r/tuREWJ
-static DEFAULT_TRAINER_FIELDS_REGEX: &str = r"(?:(?:Name: ?(?<name>[\w &]+)?\n?)|(?:Class: (?<class>[\w ]+)\n?)|(?:Pic: (?<pic>[\w ]+)\n?)|(?:^Gender: (?<gender>[\w ]+)\n?)|(?:Music: (?<music>[\w ]+)\n?)|(?:Items: (?<items>[\w /]+)\n?)|(?:Double Battle: (?<double_battle>[\w ]+)\n?)|(?:AI: (?<ai>[\w ]+)\n?)|(?:Mugshot: (?<mugshot>[\w ]+)\n?)|(?:Starting Status: (?<starting_status>[\w ]+)\n?))+";
+#[derive(Clone, Copy, Debug)]
+enum TokenKind<'a> {
+    Blank,
+    Comment,
+    /// `=== TRAINER_ID ===`, holding what follows the opening `===`
+    Header(Spanned<'a>),
+    /// `Key: Value`
+    Field {
+        key: Spanned<'a>,
+        value: Spanned<'a>,
+    },
+    /// `- Move`
+    Move(Spanned<'a>),
+    /// `Adamant Nature`
+    Nature(Spanned<'a>),
+    /// Anything else, such as a species line.
+    Text,
+}
 
-// TODO: Improve to handle Nicknames and Happiness
-/// https://regex101.com/r/oC2CeP
-/// https://regex101.com/r/2v9kpN/1
-static DEFAULT_POKEMON_FIELDS_REGEX: &str = r"(?<species>[\w :-]+)(?: (?:\((?<gender>[MF])\))? ?(?:@ (?<item>[\w\- ]+)))?\n(?:(?:Level+: (?<level>[0-9]+\s*))\n|(?:Happiness+: (?<happiness>[0-9]+\s*))\n|(?:Ability: (?<ability>[\w -]+\s*))\n|(?:Tera Type: (?<tera_type>[\w]+\s*))\n|(?:EVs: (?<effort_values>[\w/ ]+\s*))\n|(?:IVs: (?<individual_values>[\w/ ]+\s*))\n|(?:Shiny: (?<Shiny>[\w]+\s*))\n|(?:Ball: (?<Ball>[\w]+\s*))\n|(?:(?<nature>[\w]+) Nature[\s]*\n))+(?:- (?<move_1>[\w\- ]+)\n?)?(?:- (?<move_2>[\w\- ]+)\n?)?(?:- (?<move_3>[\w\- ]+)\n?)?(?:- (?<move_4>[\w\- ]+)\n?)?";
+/// A line of a `.party` file.
+#[derive(Clone, Copy, Debug)]
+struct Token<'a> {
+    kind: TokenKind<'a>,
+    /// The whole line, trailing whitespace and newline excluded.
+    text: Spanned<'a>,
+    /// The whole line as read, to be written back verbatim.
+    raw: &'a str,
+}
 
-/// https://regex101.com/r/GMoBaW/1
-static DEFAULT_POKEMON_IVS_EVS_FIELD_REGEX: &str = r"(?:(?<hp>[0-9]+) HP(?: / )?)?(?:(?<atk>[0-9]+) Atk(?: / )?)?(?:(?<def>[0-9]+) Def(?: / )?)?(?:(?<spa>[0-9]+) SpA(?: / )?)?(?:(?<spd>[0-9]+) SpD(?: / )?)?(?:(?<spe>[0-9]+) Spe(?: / )?)?";
+/// Spans `part`, which must be a slice of `line`.
+fn spanned<'a>(line: &str, number: usize, part: &'a str) -> Spanned<'a> {
+    let offset = part.as_ptr() as usize - line.as_ptr() as usize;
+    Spanned {
+        text: part,
+        span: Span {
+            line: number,
+            column: line[..offset].chars().count() + 1,
+        },
+    }
+}
 
-pub struct ParsingConfig<'a> {
-    trainer_regex: &'a str,
-    trainer_fields_regex: &'a str,
-    pokemon_fields_regex: &'a str,
-    pokemon_ivs_evs_field_regex: &'a str,
+fn syntax_error(span: Span, message: impl Into<String>) -> PartyError {
+    PartyError::Syntax {
+        span,
+        message: message.into(),
+    }
 }
 
-impl<'a> Default for ParsingConfig<'a> {
-    fn default() -> Self {
-        Self {
-            trainer_regex: DEFAULT_TRAINER_REGEX_DELIMITER,
-            trainer_fields_regex: DEFAULT_TRAINER_FIELDS_REGEX,
-            pokemon_fields_regex: DEFAULT_POKEMON_FIELDS_REGEX,
-            pokemon_ivs_evs_field_regex: DEFAULT_POKEMON_IVS_EVS_FIELD_REGEX,
-        }
+/// Turns a line into a token, keeping track of block comments spanning several
+/// lines.
+fn tokenize<'a>(raw: &'a str, number: usize, in_comment: &mut bool) -> Token<'a> {
+    let line = raw.trim_end();
+    let trimmed = line.trim_start();
+    let text = spanned(line, number, trimmed);
+    let token = |kind| Token { kind, text, raw };
+
+    if *in_comment {
+        *in_comment = !trimmed.contains("*/");
+        return token(TokenKind::Comment);
+    }
+    if let Some(comment) = trimmed.strip_prefix("/*") {
+        *in_comment = !comment.contains("*/");
+        return token(TokenKind::Comment);
+    }
+    if trimmed.starts_with("//") {
+        return token(TokenKind::Comment);
+    }
+    if trimmed.is_empty() {
+        return token(TokenKind::Blank);
     }
+    if let Some(header) = trimmed.strip_prefix("===") {
+        return token(TokenKind::Header(spanned(line, number, header)));
+    }
+    if let Some(r#move) = trimmed.strip_prefix('-') {
+        return token(TokenKind::Move(spanned(line, number, r#move.trim())));
+    }
+    if let Some((key, value)) = trimmed.split_once(':') {
+        return token(TokenKind::Field {
+            key: spanned(line, number, key.trim()),
+            value: spanned(line, number, value.trim()),
+        });
+    }
+    if let Some(nature) = trimmed.strip_suffix(" Nature") {
+        return token(TokenKind::Nature(spanned(line, number, nature.trim())));
+    }
+
+    token(TokenKind::Text)
 }
 
-fn parse_ivs_evs(content: &str, re: &Regex) -> eyre::Result<PokemonIVs> {
-    let Some(cap) = re.captures(content) else {
-        let error = "Could not parse IVs or EVs fields, regex did not match";
-        tracing::error!(error);
-        return Err(PartyError::ParsingError(error.to_owned()))?;
-    };
+//
+// ------ Parsing stuff
+//
+
+/// Parses a `12 HP / 31 Atk` line, which may list any subset of the stats.
+fn parse_ivs_evs(line: &str, value: Spanned) -> Result<PokemonIVs, PartyError> {
+    let mut result = PokemonIVs::default();
+
+    for part in value.text.split('/') {
+        let part = spanned(line, value.span.line, part.trim());
+        let Some((amount, stat)) = part.text.split_once(' ') else {
+            return Err(syntax_error(
+                part.span,
+                format!("expected `<value> <stat>`, found `{}`", part.text),
+            ));
+        };
+        let amount = amount
+            .parse()
+            .map_err(|_| syntax_error(part.span, format!("invalid stat value `{}`", amount)))?;
+        let stat = stat.trim();
+        let Some(stat) = result.stat_mut(stat) else {
+            return Err(syntax_error(part.span, format!("unknown stat `{}`", stat)));
+        };
+        *stat = Some(amount);
+    }
 
-    let health = cap_get_or_none(&cap, "hp")
-        .and_then(|v| Some(v.parse()))
-        .transpose()?;
-    let attack = cap_get_or_none(&cap, "atk")
-        .and_then(|v| Some(v.parse()))
-        .transpose()?;
-    let defense = cap_get_or_none(&cap, "def")
-        .and_then(|v| Some(v.parse()))
-        .transpose()?;
-    let sp_attack = cap_get_or_none(&cap, "spa")
-        .and_then(|v| Some(v.parse()))
-        .transpose()?;
-    let sp_defense = cap_get_or_none(&cap, "spd")
-        .and_then(|v| Some(v.parse()))
-        .transpose()?;
-    let speed = cap_get_or_none(&cap, "spe")
-        .and_then(|v| Some(v.parse()))
-        .transpose()?;
-
-    Ok(PokemonIVs {
-        health,
-        attack,
-        defense,
-        sp_attack,
-        sp_defense,
-        speed,
-    })
+    Ok(result)
 }
 
-fn cap_get_or_none(cap: &Captures, field: &str) -> Option<String> {
-    cap.name(field)
-        .map_or(None, |c| Some(c.as_str().trim_end().to_owned()))
+fn parse_number<T: std::str::FromStr>(value: Spanned, what: &str) -> Result<T, PartyError> {
+    value
+        .text
+        .parse()
+        .map_err(|_| syntax_error(value.span, format!("invalid {} `{}`", what, value.text)))
 }
 
-fn parse_mons_fields(
-    content: &str,
-    re: &Regex,
-    ivs_evs_re: &Regex,
-) -> eyre::Result<[Option<PokemonSet>; 6]> {
-    let mut mons: [Option<PokemonSet>; 6] = Default::default();
-
-    for (i, cap) in re.captures_iter(content).enumerate() {
-        if i > 5 {
-            let error = "More than 6 pokemon found in party";
-            tracing::debug!(?mons);
-            tracing::error!(error);
-            return Err(PartyError::ParsingError(error.to_owned()))?;
-        }
-        let species = cap_get_or_none(&cap, "species").ok_or(PartyError::ParsingError(
-            "Error parsing Pokemon species.".to_owned(),
-        ))?;
-        let species_normalized = unidecode::unidecode(&species)
-            .to_lowercase()
-            .replace('_', "")
-            .replace('\'', "")
-            .replace(". ", "_")
-            .replace('-', "_")
-            .replace(' ', "_")
-            .replace('.', "")
-            .replace(':', "");
-        // This is weird, maybe rework the gender field into an option and remove
-        // the None variant in PokemonGender
-        let gender = cap_get_or_none(&cap, "gender")
-            .unwrap_or("".to_owned())
-            .as_str()
-            .try_into()?;
-        let held_item = cap_get_or_none(&cap, "held_item");
-        let level = cap_get_or_none(&cap, "level")
-            .and_then(|lv| Some(lv.parse()))
-            .transpose()?;
-        let ivs = cap_get_or_none(&cap, "individual_values")
-            .and_then(|ivs| Some(parse_ivs_evs(&ivs, ivs_evs_re)))
-            .transpose()?;
-        let evs = cap_get_or_none(&cap, "effort_values")
-            .and_then(|evs| Some(parse_ivs_evs(&evs, ivs_evs_re)))
-            .transpose()?;
-        let ball = cap_get_or_none(&cap, "ball");
-        let ability = cap_get_or_none(&cap, "ability");
-        let happiness = cap_get_or_none(&cap, "happiness")
-            .and_then(|h| Some(h.parse()))
-            .transpose()?;
-        let nature = cap_get_or_none(&cap, "nature");
-        let shiny = if cap_get_or_none(&cap, "shiny").unwrap_or("No".to_owned()) == "Yes" {
-            true
-        } else {
-            false
-        };
-        // TODO: dynamax and gigantamax
-        let tera_type = cap_get_or_none(&cap, "tera_type");
+fn parse_yes_no(value: Spanned, key: Spanned) -> Result<bool, PartyError> {
+    match value.text {
+        "Yes" => Ok(true),
+        "No" => Ok(false),
+        _ => Err(syntax_error(
+            value.span,
+            format!("invalid {} `{}`, expected Yes or No", key.text, value.text),
+        )),
+    }
+}
 
-        let move_1 = cap_get_or_none(&cap, "move_1");
-        let move_2 = cap_get_or_none(&cap, "move_2");
-        let move_3 = cap_get_or_none(&cap, "move_3");
-        let move_4 = cap_get_or_none(&cap, "move_4");
+/// Splits a `Nickname (Species) (F) @ Item` line into the nickname, species,
+/// gender and held item.
+pub fn parse_species_line(
+    line: &str,
+) -> Result<(Option<String>, String, PokemonGender, Option<String>), PartyError> {
+    let (rest, held_item) = match line.split_once(" @ ") {
+        Some((rest, item)) => (rest, Some(item.trim().to_owned())),
+        None => (line, None),
+    };
+
+    let mut rest = rest.trim();
+    let mut gender = PokemonGender::None;
+    if let Some((name, suffix)) = rest.rsplit_once(" (")
+        && let Some(value @ ("M" | "F")) = suffix.strip_suffix(')')
+    {
+        gender = value.try_into()?;
+        rest = name.trim_end();
+    }
 
-        let mon = PokemonSet {
-            species,
-            species_normalized,
+    match rest
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once(" ("))
+    {
+        Some((nickname, species)) => Ok((
+            Some(nickname.to_owned()),
+            species.to_owned(),
             gender,
             held_item,
-            level,
-            ivs,
-            evs,
-            ball,
-            ability,
-            happiness,
-            nature,
-            shiny,
-            dynamax_level: None,
-            gigantamax: false,
-            tera_type,
-            move_1,
-            move_2,
-            move_3,
-            move_4,
+        )),
+        None => Ok((None, rest.to_owned(), gender, held_item)),
+    }
+}
+
+fn parse_mon(
+    tokens: &[Token],
+    pokedex: &Pokedex,
+    entity: String,
+    kept: &mut Vec<String>,
+    report: &mut FileReport,
+) -> Result<Option<PokemonSet>, PartyError> {
+    let (nickname, name, gender, held_item) = parse_species_line(tokens[0].text.text)?;
+    let species = match pokedex.resolve(&name) {
+        Ok(species) => species,
+        Err(error) => {
+            report.push(entity, error);
+            return Ok(None);
+        }
+    };
+    // Cosmetic formes resolve to their base species, the name keeps the look
+    let cosmetic_forme = pokedex.get(&species).and_then(|entry| {
+        entry
+            .cosmetic_formes
+            .iter()
+            .find(|forme| to_id(forme) == to_id(&name))
+            .cloned()
+    });
+
+    let mut mon = PokemonSet {
+        nickname,
+        species,
+        cosmetic_forme,
+        gender,
+        held_item,
+        ..Default::default()
+    };
+    let mut moves = vec![];
+
+    for token in tokens[1..].iter() {
+        let (key, value) = match token.kind {
+            TokenKind::Move(r#move) => {
+                if moves.len() == 4 {
+                    return Err(syntax_error(r#move.span, "more than 4 moves"));
+                }
+                moves.push(r#move.text.to_owned());
+                continue;
+            }
+            TokenKind::Nature(nature) => {
+                mon.nature = Some(nature.text.to_owned());
+                continue;
+            }
+            TokenKind::Field { key, value } => (key, value),
+            TokenKind::Comment => {
+                kept.push(token.raw.to_owned());
+                continue;
+            }
+            _ => {
+                return Err(syntax_error(
+                    token.text.span,
+                    format!("expected a Pokemon field, found `{}`", token.text.text),
+                ));
+            }
         };
 
-        mons[i] = Some(mon);
+        let line = token.raw.trim_end();
+        match key.text {
+            "Level" => mon.level = Some(parse_number(value, "level")?),
+            "Ability" => mon.ability = Some(value.text.to_owned()),
+            "IVs" => mon.ivs = Some(parse_ivs_evs(line, value)?),
+            "EVs" => mon.evs = Some(parse_ivs_evs(line, value)?),
+            "Happiness" => mon.happiness = Some(parse_number(value, "happiness")?),
+            "Shiny" => mon.shiny = parse_yes_no(value, key)?,
+            "Ball" => mon.ball = Some(value.text.to_owned()),
+            "Dynamax Level" => mon.dynamax_level = Some(parse_number(value, "dynamax level")?),
+            "Gigantamax" => mon.gigantamax = parse_yes_no(value, key)?,
+            "Tera Type" => mon.tera_type = Some(value.text.to_owned()),
+            _ => {
+                tracing::warn!(
+                    "{}:{}:{}: unknown Pokemon key `{}`, kept as is",
+                    report.path().display(),
+                    key.span.line,
+                    key.span.column,
+                    key.text
+                );
+                kept.push(token.raw.to_owned());
+            }
+        }
     }
 
-    Ok(mons)
+    let mut moves = moves.into_iter();
+    mon.move_1 = moves.next();
+    mon.move_2 = moves.next();
+    mon.move_3 = moves.next();
+    mon.move_4 = moves.next();
+
+    Ok(Some(mon))
 }
 
-fn parse_trainer_fields(content: &str, id: &str, re: &Regex) -> eyre::Result<Trainer> {
-    let mut trainer = Trainer::default();
-    trainer.id = id.to_owned();
+/// Groups the tokens following a trainer header into fields and Pokemon.
+/// Returns the trivia left after the last Pokemon, which belongs to whatever
+/// comes next.
+fn parse_trainer<'a>(
+    trainer: &mut Trainer,
+    source: &mut TrainerSource,
+    tokens: &[Token<'a>],
+    dialect: Dialect,
+    pokedex: &Pokedex,
+    report: &mut FileReport,
+) -> Result<Vec<String>, PartyError> {
+    let mut pending = vec![];
+    let mut mons: Vec<(Vec<String>, Vec<Token<'a>>)> = vec![];
+    let mut in_fields = true;
+    let mut in_mon = false;
+
+    for token in tokens {
+        let raw = token.raw.to_owned();
+        match token.kind {
+            TokenKind::Blank if in_fields => {
+                in_fields = false;
+                pending.push(raw);
+            }
+            TokenKind::Comment if in_fields => source.fields.push(FieldLine::Verbatim(raw)),
+            TokenKind::Field { key, value } if in_fields => {
+                let known = dialect
+                    .set_trainer_field(trainer, key.text, value.text)
+                    .map_err(|message| syntax_error(value.span, message))?;
+                if known {
+                    source.fields.push(FieldLine::Known {
+                        key: key.text.to_owned(),
+                        value: dialect.trainer_field(trainer, key.text),
+                        line: raw,
+                    });
+                } else {
+                    tracing::warn!(
+                        "{}:{}:{}: unknown trainer key `{}`, kept as is",
+                        report.path().display(),
+                        key.span.line,
+                        key.span.column,
+                        key.text
+                    );
+                    source.fields.push(FieldLine::Verbatim(raw));
+                }
+            }
+            _ if in_fields => {
+                return Err(syntax_error(
+                    token.text.span,
+                    format!("expected `Key: Value`, found `{}`", token.text.text),
+                ));
+            }
+            // Only a blank line ends a Pokemon, comments within it are its own
+            TokenKind::Blank => {
+                in_mon = false;
+                pending.push(raw);
+            }
+            _ if in_mon => {
+                let (_, mon_tokens) = mons.last_mut().expect("in a Pokemon");
+                mon_tokens.push(*token);
+            }
+            TokenKind::Comment => pending.push(raw),
+            _ => {
+                if mons.len() == 6 {
+                    return Err(syntax_error(
+                        token.text.span,
+                        "more than 6 Pokemon in party",
+                    ));
+                }
+                mons.push((std::mem::take(&mut pending), vec![*token]));
+                in_mon = true;
+            }
+        }
+    }
+
+    for (i, (leading, tokens)) in mons.into_iter().enumerate() {
+        let entity = format!("{} slot {}", trainer.id, i + 1);
+        let mut kept = vec![];
+        let set = parse_mon(&tokens, pokedex, entity, &mut kept, report)?;
+        trainer.party[i] = set.clone();
+        source.mons.push(MonSource {
+            leading,
+            lines: tokens.iter().map(|token| token.raw.to_owned()).collect(),
+            kept,
+            set,
+        });
+    }
 
-    let Some(cap) = re.captures(content) else {
-        let error = "Could not parse trainer fields, regex did not match";
-        tracing::error!(error);
-        return Err(PartyError::ParsingError(error.to_owned()).into());
+    Ok(pending)
+}
+
+/// Checks a `=== TRAINER_ID ===` header, returning the trainer id.
+fn parse_header(line: &str, header: Spanned) -> Result<String, PartyError> {
+    let Some(id) = header.text.strip_suffix("===") else {
+        let mut span = header.span;
+        span.column += header.text.chars().count();
+        return Err(syntax_error(
+            span,
+            "unterminated trainer header, expected `===`",
+        ));
     };
 
-    trainer.name = cap_get_or_none(&cap, "name").unwrap_or_default();
-    trainer.pic = cap_get_or_none(&cap, "pic").unwrap_or_default();
-    trainer.class = cap_get_or_none(&cap, "class");
-    trainer.gender = cap_get_or_none(&cap, "gender");
-    trainer.music = cap_get_or_none(&cap, "music");
-    trainer.items = cap_get_or_none(&cap, "items");
-    trainer.double_battle =
-        if cap_get_or_none(&cap, "double_battle").unwrap_or("No".to_owned()) == "Yes" {
-            true
-        } else {
-            false
-        };
-    trainer.ai = cap_get_or_none(&cap, "ai");
-    trainer.mugshot = cap_get_or_none(&cap, "mugshot");
-    trainer.starting_status = cap_get_or_none(&cap, "starting_status");
+    let id = spanned(line, header.span.line, id.trim());
+    if id.text.is_empty() {
+        return Err(syntax_error(id.span, "empty trainer id"));
+    }
+    if let Some(c) = id
+        .text
+        .chars()
+        .find(|c| !matches!(c, 'A'..='Z' | '0'..='9' | '_'))
+    {
+        return Err(syntax_error(
+            id.span,
+            format!("unexpected `{}` in trainer id", c),
+        ));
+    }
 
-    Ok(trainer)
+    Ok(id.text.to_owned())
 }
 
-pub fn from_emerald_expansion_format_config(
+pub fn from_emerald_expansion_format(
     file_content: &str,
-    config: &ParsingConfig,
-) -> eyre::Result<Parties> {
-    let trainer_re = Regex::new(config.trainer_regex).expect("regex is valid");
-    let trainer_fields_re = Regex::new(config.trainer_fields_regex).expect("regex is valid");
-    let pokemon_fields_re = Regex::new(config.pokemon_fields_regex).expect("regex is valid");
-    let pokemon_iv_ev_re = Regex::new(config.pokemon_ivs_evs_field_regex).expect("regex is valid");
+    dialect: Dialect,
+    pokedex: &Pokedex,
+    report: &mut FileReport,
+) -> eyre::Result<(Parties, PartySources)> {
+    let mut in_comment = false;
+    let tokens: Vec<Token> = file_content
+        .split_inclusive('\n')
+        .enumerate()
+        .map(|(i, raw)| tokenize(raw, i + 1, &mut in_comment))
+        .collect();
+
+    let headers: Vec<usize> = tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| matches!(token.kind, TokenKind::Header(_)))
+        .map(|(i, _)| i)
+        .collect();
+
+    // Trivia waiting for the next trainer
+    let mut pending = vec![];
+    let preamble_end = headers.first().copied().unwrap_or(tokens.len());
+    for token in tokens[..preamble_end].iter() {
+        match token.kind {
+            TokenKind::Blank | TokenKind::Comment => pending.push(token.raw.to_owned()),
+            _ => report.push(
+                "before the first trainer",
+                syntax_error(token.text.span, "expected a trainer header"),
+            ),
+        }
+    }
 
     let mut trainers = vec![];
+    let mut sources = PartySources::new();
+    let mut last_id = None;
+    for (n, &start) in headers.iter().enumerate() {
+        let end = headers.get(n + 1).copied().unwrap_or(tokens.len());
+        let header = &tokens[start];
+        let TokenKind::Header(header_text) = header.kind else {
+            unreachable!("headers are header tokens");
+        };
 
-    for (_, [id, fields, mons]) in trainer_re.captures_iter(file_content).map(|c| c.extract()) {
-        let mut trainer = parse_trainer_fields(fields, id, &trainer_fields_re)?;
-        let mons = parse_mons_fields(mons, &pokemon_fields_re, &pokemon_iv_ev_re)?;
-        trainer.party = mons;
+        // Any error skips the trainer, parsing resumes at the next header
+        let id = match parse_header(header.raw.trim_end(), header_text) {
+            Ok(id) => id,
+            Err(error) => {
+                report.push("trainer header", error);
+                continue;
+            }
+        };
+        let mut trainer = Trainer {
+            id,
+            ..Default::default()
+        };
+        let mut source = TrainerSource {
+            leading: std::mem::take(&mut pending),
+            header: header.raw.to_owned(),
+            ..Default::default()
+        };
 
-        trainers.push(trainer);
+        match parse_trainer(
+            &mut trainer,
+            &mut source,
+            &tokens[start + 1..end],
+            dialect,
+            pokedex,
+            report,
+        ) {
+            Ok(trailing) => {
+                pending = trailing;
+                last_id = Some(trainer.id.clone());
+                sources.insert(trainer.id.clone(), source);
+                trainers.push(trainer);
+            }
+            Err(error) => report.push(trainer.id, error),
+        }
     }
 
-    Ok(Parties::new(trainers))
-}
+    if let Some(source) = last_id.and_then(|id| sources.get_mut(&id)) {
+        source.trailing = pending;
+    }
 
-pub fn from_emerald_expansion_format(file_content: &str) -> eyre::Result<Parties> {
-    let parsing_config = ParsingConfig::default();
-    from_emerald_expansion_format_config(file_content, &parsing_config)
+    Ok((Parties::new(trainers), sources))
 }
 
 //
@@ -246,8 +660,28 @@ impl PushLn for String {
     }
 }
 
-fn write_mons_field(mons: &[Option<PokemonSet>; 6]) -> Result<String, PartyError> {
-    let mut result = String::new();
+fn push_lines(lines: &[String], result: &mut String) {
+    for line in lines {
+        result.push_str(line);
+    }
+}
+
+/// Makes sure generated lines do not end up on the last line read, when the
+/// file did not end with a newline.
+fn ensure_newline(result: &mut String) {
+    if !result.is_empty() && !result.ends_with('\n') {
+        result.push('\n');
+    }
+}
+
+/// Writes the fields of `pokemon`, followed by the `kept` lines of its source
+/// and its moves.
+fn write_mon(
+    pokemon: &PokemonSet,
+    kept: &[String],
+    pokedex: &Pokedex,
+) -> Result<String, PartyError> {
+    let mut mon_fields = String::new();
     let push_field_if_some = |field: &Option<String>, name: &str, res: &mut String| {
         if let Some(value) = field {
             let line = format!("{}: {}", name.trim_end(), value.trim_end());
@@ -255,144 +689,645 @@ fn write_mons_field(mons: &[Option<PokemonSet>; 6]) -> Result<String, PartyError
         }
     };
 
-    for mon in mons {
-        let Some(pokemon) = mon else {
-            continue;
-        };
+    let species = pokedex.entry(pokemon.species.as_str())?;
+    let species = unidecode::unidecode(pokemon.cosmetic_forme.as_ref().unwrap_or(&species.name));
+    match pokemon.nickname {
+        Some(ref nickname) => mon_fields.push_str(&format!("{} ({})", nickname, species)),
+        None => mon_fields.push_str(&species),
+    }
 
-        let mut mon_fields = String::new();
+    if pokemon.gender != PokemonGender::None {
+        let gender = format!(" ({})", String::from(pokemon.gender));
+        mon_fields.push_str(&gender);
+    }
 
-        let species = format!("{}", pokemon.species);
-        mon_fields.push_str(&species);
+    if let Some(ref value) = pokemon.held_item {
+        let held_item = format!(" @ {}", value);
+        mon_fields.push_str(&held_item);
+    }
 
-        if pokemon.gender != PokemonGender::None {
-            let gender = format!(" ({})", String::from(pokemon.gender));
-            mon_fields.push_str(&gender);
-        }
+    mon_fields.push('\n');
 
-        if let Some(ref value) = pokemon.held_item {
-            let held_item = format!(" @ {}", value);
-            mon_fields.push_str(&held_item);
+    if let Some(value) = pokemon.level {
+        let level = format!("Level: {}", value);
+        mon_fields.push_ln(&level);
+    }
+
+    let push_ivs_if_some = |ivs: &Option<PokemonIVs>, prepend: &str, res: &mut String| {
+        if let Some(value) = ivs {
+            let push_stat =
+                |stat: &Option<u8>, stat_str: &str, first: &mut bool, res: &mut String| {
+                    if let Some(stat) = stat {
+                        if *first {
+                            *first = false;
+                            *res = format!("{} {} {}", res, stat, stat_str);
+                        } else {
+                            *res = format!("{} / {} {}", res, stat, stat_str);
+                        }
+                    }
+                };
+
+            let mut iv_result = format!("{}:", prepend);
+            let mut first = true;
+            push_stat(&value.health, "HP", &mut first, &mut iv_result);
+            push_stat(&value.attack, "Atk", &mut first, &mut iv_result);
+            push_stat(&value.defense, "Def", &mut first, &mut iv_result);
+            push_stat(&value.sp_attack, "SpA", &mut first, &mut iv_result);
+            push_stat(&value.sp_defense, "SpD", &mut first, &mut iv_result);
+            push_stat(&value.speed, "Spe", &mut first, &mut iv_result);
+            res.push_ln(&iv_result);
         }
+    };
+
+    push_ivs_if_some(&pokemon.ivs, "IVs", &mut mon_fields);
+    push_ivs_if_some(&pokemon.evs, "EVs", &mut mon_fields);
+
+    push_field_if_some(&pokemon.ball, "Ball", &mut mon_fields);
+
+    push_field_if_some(&pokemon.ability, "Ability", &mut mon_fields);
+
+    if let Some(value) = pokemon.happiness {
+        let happiness = format!("Happiness: {}", value);
+        mon_fields.push_ln(&happiness);
+    }
+
+    if let Some(ref value) = pokemon.nature {
+        let nature = format!("{} Nature", value);
+        mon_fields.push_ln(&nature);
+    }
+
+    if pokemon.shiny {
+        mon_fields.push_ln("Shiny: Yes");
+    }
+
+    if let Some(value) = pokemon.dynamax_level {
+        let dynamax_level = format!("Dynamax Level: {}", value);
+        mon_fields.push_ln(&dynamax_level);
+    }
+
+    if pokemon.gigantamax {
+        mon_fields.push_ln("Gigantamax: Yes");
+    }
 
-        mon_fields.push_str("\n");
+    push_field_if_some(&pokemon.tera_type, "Tera Type", &mut mon_fields);
 
-        if let Some(value) = pokemon.level {
-            let level = format!("Level: {}", value);
-            mon_fields.push_ln(&level);
+    push_lines(kept, &mut mon_fields);
+    ensure_newline(&mut mon_fields);
+
+    let push_move_if_some = |r#move: &Option<String>, res: &mut String| {
+        if let Some(value) = r#move {
+            let line = format!("- {}", value);
+            res.push_ln(&line);
         }
+    };
 
-        let push_ivs_if_some = |ivs: &Option<PokemonIVs>, prepend: &str, res: &mut String| {
-            if let Some(value) = ivs {
-                let push_stat =
-                    |stat: &Option<u8>, stat_str: &str, first: &mut bool, res: &mut String| {
-                        if let Some(stat) = stat {
-                            if *first {
-                                *first = false;
-                                *res = format!("{} {} {}", res, stat, stat_str);
-                            } else {
-                                *res = format!("{} / {} {}", res, stat, stat_str);
-                            }
-                        }
-                    };
-
-                let mut iv_result = format!("{}:", prepend);
-                let mut first = true;
-                push_stat(&value.health, "HP", &mut first, &mut iv_result);
-                push_stat(&value.attack, "Atk", &mut first, &mut iv_result);
-                push_stat(&value.defense, "Def", &mut first, &mut iv_result);
-                push_stat(&value.sp_attack, "SpA", &mut first, &mut iv_result);
-                push_stat(&value.sp_defense, "SpD", &mut first, &mut iv_result);
-                push_stat(&value.speed, "Spe", &mut first, &mut iv_result);
-                res.push_ln(&iv_result);
-            }
-        };
+    push_move_if_some(&pokemon.move_1, &mut mon_fields);
+    push_move_if_some(&pokemon.move_2, &mut mon_fields);
+    push_move_if_some(&pokemon.move_3, &mut mon_fields);
+    push_move_if_some(&pokemon.move_4, &mut mon_fields);
 
-        // Temporary
-        push_ivs_if_some(&pokemon.ivs, "IVs", &mut mon_fields);
-        push_ivs_if_some(&pokemon.evs, "EVs", &mut mon_fields);
+    Ok(mon_fields)
+}
 
-        push_field_if_some(&pokemon.ball, "Ball", &mut mon_fields);
+fn write_mons_field(
+    trainer: &Trainer,
+    source: Option<&TrainerSource>,
+    pokedex: &Pokedex,
+    result: &mut String,
+) -> Result<(), PartyError> {
+    let sources = source
+        .map(|source| source.mons.as_slice())
+        .unwrap_or_default();
+
+    for (i, mon) in trainer.party.iter().enumerate() {
+        match (mon, sources.get(i)) {
+            (Some(pokemon), Some(source)) if source.set.as_ref() == Some(pokemon) => {
+                push_lines(&source.leading, result);
+                push_lines(&source.lines, result);
+            }
+            (Some(pokemon), Some(source)) => {
+                push_lines(&source.leading, result);
+                ensure_newline(result);
+                result.push_str(&write_mon(pokemon, &source.kept, pokedex)?);
+            }
+            (Some(pokemon), None) => {
+                ensure_newline(result);
+                result.push('\n');
+                result.push_str(&write_mon(pokemon, &[], pokedex)?);
+            }
+            // Could not be parsed, keep it as it was
+            (None, Some(source)) if source.set.is_none() => {
+                push_lines(&source.leading, result);
+                push_lines(&source.lines, result);
+            }
+            (None, _) => (),
+        }
+    }
 
-        push_field_if_some(&pokemon.ability, "Ability", &mut mon_fields);
+    Ok(())
+}
 
-        if let Some(value) = pokemon.happiness {
-            let happiness = format!("Happiness: {}", value);
-            mon_fields.push_ln(&happiness);
+fn write_trainer_fields(
+    trainer: &Trainer,
+    source: Option<&TrainerSource>,
+    dialect: Dialect,
+    result: &mut String,
+) {
+    let push_field_if_some = |key: &str, res: &mut String| {
+        if let Some(value) = dialect.trainer_field(trainer, key) {
+            let line = format!("{}: {}", key, value.trim_end());
+            res.push_ln(line.trim_end());
         }
+    };
 
-        if let Some(ref value) = pokemon.nature {
-            let nature = format!("{} Nature", value);
-            mon_fields.push_ln(&nature);
+    let mut written = vec![];
+    if let Some(source) = source {
+        for field in source.fields.iter() {
+            match field {
+                FieldLine::Known { key, value, line } => {
+                    written.push(key.as_str());
+                    if *value == dialect.trainer_field(trainer, key) {
+                        result.push_str(line);
+                    } else {
+                        ensure_newline(result);
+                        push_field_if_some(key, result);
+                    }
+                }
+                FieldLine::Verbatim(line) => result.push_str(line),
+            }
         }
+    }
 
-        // TODO: dynamax & gigantamax
-        push_field_if_some(&pokemon.tera_type, "Tera Type", &mut mon_fields);
+    // A field left out of the file reads as its default, which must not be
+    // written back unless it changed
+    let default = Trainer::default();
+    for key in dialect.trainer_fields().iter() {
+        let unset = source.is_some()
+            && dialect.trainer_field(trainer, key) == dialect.trainer_field(&default, key);
+        if !written.contains(key) && !unset {
+            ensure_newline(result);
+            push_field_if_some(key, result);
+        }
+    }
+}
 
-        let push_move_if_some = |r#move: &Option<String>, res: &mut String| {
-            if let Some(value) = r#move {
-                let line = format!("- {}", value);
-                res.push_ln(&line);
+pub fn to_emerald_expansion_format(
+    parties: &Parties,
+    sources: &PartySources,
+    dialect: Dialect,
+    pokedex: &Pokedex,
+) -> Result<String, PartyError> {
+    let mut result = String::new();
+    for trainer in parties.iter() {
+        let source = sources.get(&trainer.id);
+        match source {
+            Some(source) => {
+                push_lines(&source.leading, &mut result);
+                result.push_str(&source.header);
             }
-        };
+            None => {
+                ensure_newline(&mut result);
+                if !result.is_empty() {
+                    result.push('\n');
+                }
+                let line = format!("=== {} ===", trainer.id);
+                result.push_ln(&line);
+            }
+        }
 
-        push_move_if_some(&pokemon.move_1, &mut mon_fields);
-        push_move_if_some(&pokemon.move_2, &mut mon_fields);
-        push_move_if_some(&pokemon.move_3, &mut mon_fields);
-        push_move_if_some(&pokemon.move_4, &mut mon_fields);
+        write_trainer_fields(trainer, source, dialect, &mut result);
+        write_mons_field(trainer, source, pokedex, &mut result)?;
 
-        result.push_ln(&mon_fields);
+        if let Some(source) = source {
+            push_lines(&source.trailing, &mut result);
+        }
     }
 
     Ok(result)
 }
 
-fn write_trainer_fields(trainer: &Trainer) -> Result<String, PartyError> {
-    let mut result = String::new();
-    let push_field_if_some = |field: &Option<String>, name: &str, res: &mut String| {
-        if let Some(value) = field {
-            let line = format!("{}: {}", name.trim_end(), value.trim_end());
-            res.push_ln(&line);
-        }
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        path::Path,
+        sync::{Arc, Mutex},
     };
 
-    let line = format!("Name: {}", trainer.name);
-    result.push_ln(&line);
+    use super::*;
+    use crate::{
+        database::pokedex::{Pokedex, load_pokedex},
+        engine::validation::ValidationReport,
+    };
 
-    let line = format!("Pic: {}", trainer.pic);
-    result.push_ln(&line);
+    const FIXTURE: &str = include_str!("../../tests/fixtures/trainers.party");
 
-    push_field_if_some(&trainer.class, "Class", &mut result);
-    push_field_if_some(&trainer.gender, "Gender", &mut result);
-    push_field_if_some(&trainer.music, "Music", &mut result);
-    push_field_if_some(&trainer.items, "Items", &mut result);
+    fn pokedex() -> Pokedex {
+        load_pokedex(Path::new("pokedex.json")).unwrap()
+    }
 
-    result.push_ln("AI: Smart Trainer");
+    fn parse(
+        content: &str,
+        dialect: Dialect,
+        pokedex: &Pokedex,
+    ) -> (Parties, PartySources, ValidationReport) {
+        let mut report = ValidationReport::default();
+        let (parties, sources) = from_emerald_expansion_format(
+            content,
+            dialect,
+            pokedex,
+            &mut report.file(Path::new("trainers.party")),
+        )
+        .unwrap();
+        (parties, sources, report)
+    }
 
-    let line = format!(
-        "Double Battle: {}",
-        if trainer.double_battle { "Yes" } else { "No" }
-    );
-    result.push_ln(&line);
+    fn round_trip(content: &str) -> String {
+        let pokedex = pokedex();
+        let (parties, sources, report) = parse(content, Dialect::EmeraldExpansion, &pokedex);
+        assert!(report.problems().is_empty(), "{:?}", report.problems());
+        to_emerald_expansion_format(&parties, &sources, Dialect::EmeraldExpansion, &pokedex)
+            .unwrap()
+    }
 
-    // push_field_if_some(&trainer.ai, "AI", &mut result);
-    push_field_if_some(&trainer.mugshot, "Mugshot", &mut result);
-    push_field_if_some(&trainer.starting_status, "Starting Status", &mut result);
+    #[test]
+    fn parses_fixture() {
+        let pokedex = pokedex();
+        let (parties, _, report) = parse(FIXTURE, Dialect::EmeraldExpansion, &pokedex);
+        assert!(report.problems().is_empty(), "{:?}", report.problems());
+        assert_eq!(parties.len(), 4);
+
+        let roxanne = parties.get("TRAINER_ROXANNE_1").unwrap();
+        assert_eq!(roxanne.name, "Roxanne");
+        assert!(!roxanne.double_battle);
+        let nosepass = roxanne.party[1].as_ref().unwrap();
+        assert_eq!(nosepass.nickname.as_deref(), Some("Rocky"));
+        assert_eq!(nosepass.species.as_str(), "nosepass");
+        assert_eq!(nosepass.gender, PokemonGender::Male);
+        assert_eq!(nosepass.held_item.as_deref(), Some("Oran Berry"));
+        assert_eq!(nosepass.ball.as_deref(), Some("Great Ball"));
+        assert_eq!(nosepass.happiness, Some(200));
+        assert_eq!(nosepass.nature.as_deref(), Some("Bold"));
+        assert_eq!(nosepass.move_4.as_deref(), Some("Rock Tomb"));
+
+        let calvin = parties.get("TRAINER_CALVIN_1").unwrap();
+        assert!(calvin.double_battle);
+        assert!(calvin.party[0].as_ref().unwrap().shiny);
+        assert_eq!(calvin.party[1].as_ref().unwrap().dynamax_level, Some(5));
+
+        let charizard = parties.get("TRAINER_RED").unwrap().party[0]
+            .as_ref()
+            .unwrap();
+        assert!(charizard.gigantamax);
+        assert_eq!(charizard.dynamax_level, Some(10));
+        assert_eq!(charizard.tera_type.as_deref(), Some("Fire"));
+    }
 
-    Ok(result)
-}
+    #[test]
+    fn writes_back_unchanged_file_verbatim() {
+        assert_eq!(round_trip(FIXTURE), FIXTURE);
+    }
 
-pub fn to_emerald_expansion_format(parties: &Parties) -> Result<String, PartyError> {
-    let mut result = String::new();
-    for trainer in parties.iter() {
-        let line = format!("=== {} ===", trainer.id);
-        result.push_ln(&line);
-        let fields = write_trainer_fields(trainer)?;
-        result.push_ln(&fields);
+    #[test]
+    fn writes_back_file_without_final_newline_verbatim() {
+        let content = FIXTURE.trim_end();
+        assert_eq!(round_trip(content), content);
+    }
 
-        let mons = write_mons_field(&trainer.party)?;
-        result.push_str(&mons);
+    #[test]
+    fn does_not_add_fields_left_out_of_the_file() {
+        let content = "=== TRAINER_SHORT ===\nName: Short\n\nGeodude\nLevel: 5\n";
+        assert_eq!(round_trip(content), content);
     }
 
-    Ok(result)
+    #[test]
+    fn rewrites_only_the_changed_mon() {
+        let pokedex = pokedex();
+        let (mut parties, sources, _) = parse(FIXTURE, Dialect::EmeraldExpansion, &pokedex);
+        let roxanne = parties.get_mut("TRAINER_ROXANNE_1").unwrap();
+        roxanne.party[0].as_mut().unwrap().level = Some(13);
+
+        let written =
+            to_emerald_expansion_format(&parties, &sources, Dialect::EmeraldExpansion, &pokedex)
+                .unwrap();
+        let changed: Vec<(&str, &str)> = FIXTURE
+            .lines()
+            .zip(written.lines())
+            .filter(|(before, after)| before != after)
+            .collect();
+        assert_eq!(changed, vec![("Level: 12", "Level: 13")]);
+        assert_eq!(FIXTURE.lines().count(), written.lines().count());
+    }
+
+    #[test]
+    fn keeps_unknown_keys_of_a_rewritten_mon() {
+        let pokedex = pokedex();
+        let (mut parties, sources, _) = parse(FIXTURE, Dialect::EmeraldExpansion, &pokedex);
+        let roxanne = parties.get_mut("TRAINER_ROXANNE_1").unwrap();
+        let rocky = roxanne.party[1].as_mut().unwrap();
+        rocky.held_item = Some("Sitrus Berry".to_owned());
+        rocky.level = Some(16);
+
+        let written =
+            to_emerald_expansion_format(&parties, &sources, Dialect::EmeraldExpansion, &pokedex)
+                .unwrap();
+        let rocky: Vec<&str> = written
+            .lines()
+            .skip_while(|line| !line.starts_with("Rocky"))
+            .take_while(|line| !line.is_empty())
+            .collect();
+        assert_eq!(
+            rocky,
+            vec![
+                "Rocky (Nosepass) (M) @ Sitrus Berry",
+                "Level: 16",
+                "Ball: Great Ball",
+                "Happiness: 200",
+                "Bold Nature",
+                "Tags: Lead",
+                "- Block",
+                "- Harden",
+                "- Tackle",
+                "- Rock Tomb",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_comments_within_a_mon_on_that_mon() {
+        let content = "\
+=== TRAINER_SHORT ===
+Name: Short
+
+Geodude
+// Sturdy on purpose
+Level: 5
+- Tackle
+
+Onix
+Level: 6
+";
+        let pokedex = pokedex();
+        let (mut parties, sources, report) = parse(content, Dialect::EmeraldExpansion, &pokedex);
+        assert!(report.problems().is_empty(), "{:?}", report.problems());
+        let party = &mut parties.get_mut("TRAINER_SHORT").unwrap().party;
+        assert_eq!(party[0].as_ref().unwrap().level, Some(5));
+        assert_eq!(party[1].as_ref().unwrap().level, Some(6));
+        assert!(party[2].is_none());
+
+        party[0].as_mut().unwrap().level = Some(7);
+        let written =
+            to_emerald_expansion_format(&parties, &sources, Dialect::EmeraldExpansion, &pokedex)
+                .unwrap();
+        assert_eq!(
+            written,
+            content.replace(
+                "// Sturdy on purpose\nLevel: 5\n",
+                "Level: 7\n// Sturdy on purpose\n"
+            )
+        );
+    }
+
+    #[test]
+    fn rewritten_file_parses_to_the_same_parties() {
+        let pokedex = pokedex();
+        let (mut parties, sources, _) = parse(FIXTURE, Dialect::EmeraldExpansion, &pokedex);
+        let calvin = parties.get_mut("TRAINER_CALVIN_1").unwrap();
+        calvin.double_battle = false;
+        calvin.party[1].as_mut().unwrap().held_item = Some("Leftovers".to_owned());
+
+        let written =
+            to_emerald_expansion_format(&parties, &sources, Dialect::EmeraldExpansion, &pokedex)
+                .unwrap();
+        let (reparsed, _, report) = parse(&written, Dialect::EmeraldExpansion, &pokedex);
+        assert!(report.problems().is_empty(), "{:?}", report.problems());
+        for (before, after) in parties.iter().zip(reparsed.iter()) {
+            assert_eq!(before.id, after.id);
+            assert_eq!(before.double_battle, after.double_battle);
+            assert_eq!(before.party, after.party);
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Runs `f`, returning what it logged.
+    fn logs(f: impl FnOnce()) -> String {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+        String::from_utf8(captured.0.lock().unwrap().clone()).unwrap()
+    }
+
+    const LEVEL_TYPO: &str = "\
+=== TRAINER_BROKEN ===
+Name: Broken
+Pic: Hiker
+
+Geodude
+Level: abc
+
+=== TRAINER_FINE ===
+Name: Fine
+Pic: Hiker
+
+Geodude
+Level: 5
+";
+
+    #[test]
+    fn spans_are_one_based_and_skip_whitespace() {
+        let mut in_comment = false;
+        let token = tokenize("  Level:   12  \n", 7, &mut in_comment);
+        assert_eq!(token.text.text, "Level:   12");
+        assert_eq!(token.text.span, Span { line: 7, column: 3 });
+        let TokenKind::Field { key, value } = token.kind else {
+            panic!("expected a field, found {:?}", token.kind);
+        };
+        assert_eq!(key.text, "Level");
+        assert_eq!(key.span, Span { line: 7, column: 3 });
+        assert_eq!(value.text, "12");
+        assert_eq!(
+            value.span,
+            Span {
+                line: 7,
+                column: 12
+            }
+        );
+    }
+
+    #[test]
+    fn tokenizes_block_comments_over_several_lines() {
+        let mut in_comment = false;
+        let kinds: Vec<_> = ["/* Level: 5", "- Tackle", "*/ Geodude", "Geodude"]
+            .into_iter()
+            .map(|line| tokenize(line, 1, &mut in_comment).kind)
+            .collect();
+        assert!(matches!(
+            kinds.as_slice(),
+            [
+                TokenKind::Comment,
+                TokenKind::Comment,
+                TokenKind::Comment,
+                TokenKind::Text
+            ]
+        ));
+    }
+
+    #[test]
+    fn ignores_trailing_spaces_and_keeps_them_on_write() {
+        let content = "=== TRAINER_SPACES ===  \nName: Spaces \nPic: Hiker\t\n\nGeodude  \nLevel: 12   \n- Tackle \n";
+        let pokedex = pokedex();
+        let (parties, _, report) = parse(content, Dialect::EmeraldExpansion, &pokedex);
+        assert!(report.problems().is_empty(), "{:?}", report.problems());
+
+        let trainer = parties.get("TRAINER_SPACES").unwrap();
+        assert_eq!(trainer.name, "Spaces");
+        assert_eq!(trainer.pic, "Hiker");
+        let geodude = trainer.party[0].as_ref().unwrap();
+        assert_eq!(geodude.level, Some(12));
+        assert_eq!(geodude.move_1.as_deref(), Some("Tackle"));
+        assert_eq!(round_trip(content), content);
+    }
+
+    #[test]
+    fn reports_errors_at_their_line_and_column() {
+        let pokedex = pokedex();
+        let (_, _, report) = parse(LEVEL_TYPO, Dialect::EmeraldExpansion, &pokedex);
+        let [problem] = report.problems() else {
+            panic!("expected one problem, found {:?}", report.problems());
+        };
+        assert_eq!(problem.entity, "TRAINER_BROKEN");
+        assert_eq!(
+            problem.error.to_string(),
+            "line 6, column 8: invalid level `abc`"
+        );
+    }
+
+    #[test]
+    fn resumes_parsing_at_the_next_header() {
+        let pokedex = pokedex();
+        let (parties, _, _) = parse(LEVEL_TYPO, Dialect::EmeraldExpansion, &pokedex);
+        assert!(parties.get("TRAINER_BROKEN").is_none());
+        let fine = parties.get("TRAINER_FINE").unwrap();
+        assert_eq!(fine.party[0].as_ref().unwrap().level, Some(5));
+
+        let content = "=== TRAINER_bad ===\nName: Bad\n\n=== TRAINER_GOOD\n\n=== TRAINER_FINE ===\nName: Fine\n";
+        let (parties, _, report) = parse(content, Dialect::EmeraldExpansion, &pokedex);
+        let errors: Vec<String> = report
+            .problems()
+            .iter()
+            .map(|problem| problem.error.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "line 1, column 5: unexpected `b` in trainer id",
+                "line 4, column 17: unterminated trainer header, expected `===`",
+            ]
+        );
+        assert_eq!(parties.len(), 1);
+        assert_eq!(parties.get("TRAINER_FINE").unwrap().name, "Fine");
+    }
+
+    #[test]
+    fn warns_about_unknown_keys_with_their_position() {
+        let pokedex = pokedex();
+        let logs = logs(|| {
+            let (_, _, report) = parse(FIXTURE, Dialect::EmeraldExpansion, &pokedex);
+            assert!(report.problems().is_empty(), "{:?}", report.problems());
+        });
+        assert!(
+            logs.contains("trainers.party:27:1: unknown trainer key `Party Size`, kept as is"),
+            "{}",
+            logs
+        );
+        assert!(
+            logs.contains("trainers.party:44:1: unknown Pokemon key `Tags`, kept as is"),
+            "{}",
+            logs
+        );
+    }
+
+    #[test]
+    fn reads_the_battle_type_of_each_dialect() {
+        let pokedex = pokedex();
+        let double = |content: &str, dialect| {
+            let (parties, _, report) = parse(content, dialect, &pokedex);
+            assert!(report.problems().is_empty(), "{:?}", report.problems());
+            parties.get("TRAINER_X").unwrap().double_battle
+        };
+
+        assert!(double(
+            "=== TRAINER_X ===\nDouble Battle: Yes\n",
+            Dialect::EmeraldExpansion
+        ));
+        assert!(!double(
+            "=== TRAINER_X ===\nDouble Battle: No\n",
+            Dialect::EmeraldExpansion
+        ));
+        assert!(double(
+            "=== TRAINER_X ===\nBattle Type: Doubles\n",
+            Dialect::BattleType
+        ));
+        assert!(!double(
+            "=== TRAINER_X ===\nBattle Type: Singles\n",
+            Dialect::BattleType
+        ));
+        // The other dialect's key is unknown, and kept as is
+        assert!(!double(
+            "=== TRAINER_X ===\nBattle Type: Doubles\n",
+            Dialect::EmeraldExpansion
+        ));
+
+        let (_, _, report) = parse(
+            "=== TRAINER_X ===\nBattle Type: Yes\n",
+            Dialect::BattleType,
+            &pokedex,
+        );
+        assert_eq!(
+            report.problems()[0].error.to_string(),
+            "line 2, column 14: invalid Battle Type `Yes`"
+        );
+    }
+
+    #[test]
+    fn writes_the_battle_type_of_each_dialect() {
+        let pokedex = pokedex();
+        for (dialect, key, content) in [
+            (
+                Dialect::EmeraldExpansion,
+                "Double Battle",
+                "Double Battle: Yes",
+            ),
+            (Dialect::BattleType, "Battle Type", "Battle Type: Doubles"),
+        ] {
+            let input = format!("=== TRAINER_X ===\nName: X\n{}\n", content);
+            let (mut parties, sources, _) = parse(&input, dialect, &pokedex);
+            let written =
+                to_emerald_expansion_format(&parties, &sources, dialect, &pokedex).unwrap();
+            assert_eq!(written, input);
+
+            parties.get_mut("TRAINER_X").unwrap().double_battle = false;
+            let written =
+                to_emerald_expansion_format(&parties, &sources, dialect, &pokedex).unwrap();
+            assert!(written.contains(&format!("{}: ", key)));
+            assert!(!written.contains(content), "{}", written);
+        }
+    }
 }
@@ -1,11 +1,20 @@
-use std::num::ParseIntError;
-
 use thiserror::Error;
 
+use crate::database::error::DatabaseError;
+
+/// Position in a parsed file, 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
 #[derive(Error, Debug)]
 pub enum PartyError {
     #[error("Parsing error: {0}")]
     ParsingError(String),
-    #[error("{0}")]
-    ParseIntError(#[from] ParseIntError),
+    #[error("line {}, column {}: {message}", span.line, span.column)]
+    Syntax { span: Span, message: String },
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
 }
@@ -2,17 +2,16 @@
 //! their Pokemon parties. This is the intermediate representation that stands
 //! between the NGE config files and the game specific representation.
 
-use std::{collections::HashMap, io::Write};
-
-use crate::cli::ProjectOption;
+use std::collections::HashMap;
 
 pub mod emerald_expansion;
 pub mod error;
 
 pub mod party {
-    use serde::Deserialize;
+    use serde::{Deserialize, Serialize};
 
     use super::error::PartyError;
+    use crate::database::species::SpeciesId;
 
     #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
     pub enum PokemonGender {
@@ -47,30 +46,57 @@ pub mod party {
         }
     }
 
-    #[derive(Clone, Default, Debug, Deserialize)]
+    #[derive(Clone, Default, Debug, PartialEq, Deserialize, Serialize)]
     pub struct PokemonIVs {
-        #[serde(rename = "hp")]
+        #[serde(rename = "hp", skip_serializing_if = "Option::is_none")]
         pub health: Option<u8>,
-        #[serde(rename = "atk")]
+        #[serde(rename = "atk", skip_serializing_if = "Option::is_none")]
         pub attack: Option<u8>,
-        #[serde(rename = "def")]
+        #[serde(rename = "def", skip_serializing_if = "Option::is_none")]
         pub defense: Option<u8>,
-        #[serde(rename = "spa")]
+        #[serde(rename = "spa", skip_serializing_if = "Option::is_none")]
         pub sp_attack: Option<u8>,
-        #[serde(rename = "spd")]
+        #[serde(rename = "spd", skip_serializing_if = "Option::is_none")]
         pub sp_defense: Option<u8>,
-        #[serde(rename = "spe")]
+        #[serde(rename = "spe", skip_serializing_if = "Option::is_none")]
         pub speed: Option<u8>,
     }
 
     pub type PokemonEVs = PokemonIVs;
 
-    #[derive(Clone, Default, Debug)]
-    #[expect(dead_code)]
+    impl PokemonIVs {
+        /// Value of every stat, labelled as in Showdown sets.
+        pub fn stats(&self) -> [(&'static str, Option<u8>); 6] {
+            [
+                ("HP", self.health),
+                ("Atk", self.attack),
+                ("Def", self.defense),
+                ("SpA", self.sp_attack),
+                ("SpD", self.sp_defense),
+                ("Spe", self.speed),
+            ]
+        }
+
+        /// Value of the stat labelled as in Showdown sets, None for an unknown
+        /// label.
+        pub fn stat_mut(&mut self, label: &str) -> Option<&mut Option<u8>> {
+            match label {
+                "HP" => Some(&mut self.health),
+                "Atk" => Some(&mut self.attack),
+                "Def" => Some(&mut self.defense),
+                "SpA" => Some(&mut self.sp_attack),
+                "SpD" => Some(&mut self.sp_defense),
+                "Spe" => Some(&mut self.speed),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Clone, Default, Debug, PartialEq)]
     pub struct PokemonSet {
-        // nickname: String,
-        pub species: String,
-        pub species_normalized: String,
+        pub nickname: Option<String>,
+        pub species: SpeciesId,
+        pub cosmetic_forme: Option<String>, // Defaults to the look of the species
         pub gender: PokemonGender,
         pub held_item: Option<String>,
         pub level: Option<u8>,       // Defaults to 100
@@ -92,10 +118,16 @@ pub mod party {
         pub move_3: Option<String>,
         pub move_4: Option<String>,
     }
+
+    impl PokemonSet {
+        pub fn moves(&self) -> impl Iterator<Item = &String> {
+            [&self.move_1, &self.move_2, &self.move_3, &self.move_4]
+                .into_iter()
+                .flatten()
+        }
+    }
 }
 
-// TODO: These two structs should be emerald-expansion specific
-// and parties should be a trait
 #[derive(Clone, Default, Debug)]
 pub struct Trainer {
     pub id: String,
@@ -132,34 +164,16 @@ impl Parties {
         res
     }
 
-    #[expect(dead_code)]
     pub fn get(&self, key: &str) -> Option<&Trainer> {
         self.parties.get(*self.name_map.get(key)?)
     }
 
-    #[expect(dead_code)]
+    #[cfg_attr(not(test), expect(dead_code))]
     pub fn get_mut(&mut self, key: &str) -> Option<&mut Trainer> {
         self.parties.get_mut(*self.name_map.get(key)?)
     }
 }
 
-impl std::ops::Index<&str> for Parties {
-    type Output = Trainer;
-    fn index(&self, index: &str) -> &Self::Output {
-        self.parties
-            .get(*self.name_map.get(index).unwrap())
-            .unwrap()
-    }
-}
-
-impl std::ops::IndexMut<&str> for Parties {
-    fn index_mut(&mut self, index: &str) -> &mut Self::Output {
-        self.parties
-            .get_mut(*self.name_map.get(index).unwrap())
-            .unwrap()
-    }
-}
-
 impl std::ops::Deref for Parties {
     type Target = Vec<Trainer>;
 
@@ -173,34 +187,3 @@ impl std::ops::DerefMut for Parties {
         &mut self.parties
     }
 }
-
-pub fn load_parties(project_options: &ProjectOption) -> eyre::Result<Parties> {
-    match project_options {
-        ProjectOption::EmeraldExpansion(ee_options) => {
-            let parties_file_path = ee_options
-                .project_path
-                .join(&ee_options.trainers_party_file_path);
-
-            let content = std::fs::read_to_string(parties_file_path)?;
-
-            Ok(emerald_expansion::from_emerald_expansion_format(&content)?)
-        }
-    }
-}
-
-pub fn save_parties(project_options: &ProjectOption, parties: &Parties) -> eyre::Result<()> {
-    match project_options {
-        ProjectOption::EmeraldExpansion(ee_options) => {
-            let parties_file_path = ee_options
-                .project_path
-                .join(&ee_options.trainers_party_file_path);
-
-            let result = crate::parties::emerald_expansion::to_emerald_expansion_format(&parties)?;
-
-            let mut file = std::fs::File::create(parties_file_path)?;
-            file.write_all(result.as_bytes())?;
-
-            Ok(())
-        }
-    }
-}
@@ -0,0 +1,295 @@
+//! Snapshots of the decomp project files managed by the engine.
+//!
+//! The first time the engine runs on a project, every managed file is copied
+//! into `.nge/pristine` inside the project. Generation always reads from those
+//! copies, so that running the engine twice does not re-randomize already
+//! randomized data. Files are written atomically and their hashes recorded, to
+//! be able to tell whether a file is pristine, generated by the engine or
+//! edited by hand since.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::hash::{content_hash, file_hash};
+
+const CACHE_DIRECTORY: &str = ".nge";
+const PRISTINE_DIRECTORY: &str = "pristine";
+const STATE_FILE: &str = "state.toml";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ManagedFileState {
+    pristine: String,
+    generated: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PristineState {
+    #[serde(default)]
+    files: BTreeMap<PathBuf, ManagedFileState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    /// Identical to the snapshot taken on first run.
+    Pristine,
+    /// Identical to the last file written by the engine.
+    Generated,
+    /// Changed by something else than the engine since.
+    HandEdited,
+    Missing,
+}
+
+impl Display for FileStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileStatus::Pristine => write!(f, "pristine"),
+            FileStatus::Generated => write!(f, "generated"),
+            FileStatus::HandEdited => write!(f, "hand-edited"),
+            FileStatus::Missing => write!(f, "missing"),
+        }
+    }
+}
+
+/// Writes `content` to a temporary file next to `path`, then renames it over
+/// `path` so that readers never see a half written file.
+pub fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".nge-tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    let mut file = std::fs::File::create(&temp_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    drop(file);
+
+    std::fs::rename(&temp_path, path)
+}
+
+pub struct Pristine {
+    project_path: PathBuf,
+    state: PristineState,
+    /// Overwrite the managed files edited by hand
+    force: bool,
+}
+
+impl Pristine {
+    /// Opens the snapshot of a project, taking it for every managed file
+    /// (relative to the project path) that has not been snapshotted yet.
+    pub fn open(project_path: &Path, managed_files: &[PathBuf]) -> eyre::Result<Self> {
+        let state_path = project_path.join(CACHE_DIRECTORY).join(STATE_FILE);
+        let state = if std::fs::exists(&state_path)? {
+            toml::from_str(&std::fs::read_to_string(&state_path)?)?
+        } else {
+            PristineState::default()
+        };
+
+        let mut pristine = Self {
+            project_path: project_path.to_owned(),
+            state,
+            force: false,
+        };
+
+        for file in managed_files.iter() {
+            if pristine.state.files.contains_key(file) {
+                continue;
+            }
+
+            let content = std::fs::read(project_path.join(file))?;
+            let snapshot_path = pristine.snapshot_path(file);
+            std::fs::create_dir_all(snapshot_path.parent().expect("snapshot is in a directory"))?;
+            write_atomic(&snapshot_path, &content)?;
+
+            tracing::info!("Took pristine snapshot of {}", file.display());
+            pristine.state.files.insert(
+                file.clone(),
+                ManagedFileState {
+                    pristine: content_hash(&content),
+                    generated: None,
+                },
+            );
+        }
+
+        pristine.save_state()?;
+
+        Ok(pristine)
+    }
+
+    fn snapshot_path(&self, file: &Path) -> PathBuf {
+        self.project_path
+            .join(CACHE_DIRECTORY)
+            .join(PRISTINE_DIRECTORY)
+            .join(file)
+    }
+
+    fn save_state(&self) -> eyre::Result<()> {
+        let cache_directory = self.project_path.join(CACHE_DIRECTORY);
+        std::fs::create_dir_all(&cache_directory)?;
+        write_atomic(
+            &cache_directory.join(STATE_FILE),
+            toml::to_string(&self.state)?.as_bytes(),
+        )?;
+
+        Ok(())
+    }
+
+    /// Managed files, relative to the project path.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.state.files.keys().map(PathBuf::as_path)
+    }
+
+    /// Hash of a managed file as it was before the engine ever touched it.
+    pub fn pristine_hash(&self, file: &Path) -> eyre::Result<String> {
+        match self.state.files.get(file) {
+            Some(state) => Ok(state.pristine.clone()),
+            None => eyre::bail!("{} is not managed by the engine", file.display()),
+        }
+    }
+
+    /// Lets [`Self::write`] overwrite the managed files edited by hand.
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    /// Snapshot of a managed file, checked against the hash recorded when it
+    /// was taken.
+    fn read_snapshot(&self, file: &Path) -> eyre::Result<Vec<u8>> {
+        let content = std::fs::read(self.snapshot_path(file))?;
+        if content_hash(&content) != self.pristine_hash(file)? {
+            eyre::bail!(
+                "The pristine snapshot of {} changed since it was taken, delete {} to take it again",
+                file.display(),
+                self.project_path.join(CACHE_DIRECTORY).display()
+            );
+        }
+
+        Ok(content)
+    }
+
+    /// Content of a managed file as it was before the engine ever touched it.
+    pub fn read_to_string(&self, file: &Path) -> eyre::Result<String> {
+        Ok(String::from_utf8(self.read_snapshot(file)?)?)
+    }
+
+    pub fn status(&self, file: &Path) -> eyre::Result<FileStatus> {
+        let Some(state) = self.state.files.get(file) else {
+            eyre::bail!("{} is not managed by the engine", file.display());
+        };
+        let path = self.project_path.join(file);
+        if !std::fs::exists(&path)? {
+            return Ok(FileStatus::Missing);
+        }
+
+        let hash = file_hash(&path)?;
+        Ok(if state.generated.as_ref() == Some(&hash) {
+            FileStatus::Generated
+        } else if state.pristine == hash {
+            FileStatus::Pristine
+        } else {
+            FileStatus::HandEdited
+        })
+    }
+
+    /// Fails when a managed file was edited by hand since the last run,
+    /// unless forced to overwrite it.
+    pub fn check_writable(&self, file: &Path) -> eyre::Result<()> {
+        if self.status(file)? != FileStatus::HandEdited {
+            return Ok(());
+        }
+
+        if !self.force {
+            eyre::bail!(
+                "{} was edited by hand since the last run, restore it or pass --force to overwrite it",
+                file.display()
+            );
+        }
+        tracing::warn!(
+            "{} was edited by hand since the last run, overwriting it",
+            file.display()
+        );
+
+        Ok(())
+    }
+
+    /// Atomically writes generated content to a managed file.
+    pub fn write(&mut self, file: &Path, content: &[u8]) -> eyre::Result<()> {
+        self.check_writable(file)?;
+
+        write_atomic(&self.project_path.join(file), content)?;
+        if let Some(state) = self.state.files.get_mut(file) {
+            state.generated = Some(content_hash(content));
+        }
+
+        self.save_state()
+    }
+
+    /// Puts back the pristine snapshot of a managed file.
+    pub fn restore(&mut self, file: &Path) -> eyre::Result<()> {
+        let content = self.read_snapshot(file)?;
+        write_atomic(&self.project_path.join(file), &content)?;
+        if let Some(state) = self.state.files.get_mut(file) {
+            state.generated = None;
+        }
+
+        self.save_state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Project holding a single managed file.
+    fn project(name: &str) -> (PathBuf, PathBuf) {
+        let project_path =
+            std::env::temp_dir().join(format!("nge-pristine-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&project_path);
+        std::fs::create_dir_all(&project_path).unwrap();
+        let file = PathBuf::from("trainers.party");
+        std::fs::write(project_path.join(&file), "original").unwrap();
+
+        (project_path, file)
+    }
+
+    #[test]
+    fn refuses_a_snapshot_changed_since_it_was_taken() {
+        let (project_path, file) = project("snapshot");
+        let pristine = Pristine::open(&project_path, std::slice::from_ref(&file)).unwrap();
+        assert_eq!(pristine.read_to_string(&file).unwrap(), "original");
+
+        std::fs::write(pristine.snapshot_path(&file), "tampered").unwrap();
+        assert!(pristine.read_to_string(&file).is_err());
+
+        std::fs::remove_dir_all(project_path).unwrap();
+    }
+
+    #[test]
+    fn overwrites_a_file_edited_by_hand_only_when_forced() {
+        let (project_path, file) = project("hand-edited");
+        let mut pristine = Pristine::open(&project_path, std::slice::from_ref(&file)).unwrap();
+        pristine.write(&file, b"generated").unwrap();
+        pristine.write(&file, b"regenerated").unwrap();
+
+        std::fs::write(project_path.join(&file), "edited").unwrap();
+        assert_eq!(pristine.status(&file).unwrap(), FileStatus::HandEdited);
+        assert!(pristine.write(&file, b"regenerated").is_err());
+        assert_eq!(
+            std::fs::read_to_string(project_path.join(&file)).unwrap(),
+            "edited"
+        );
+
+        pristine.set_force(true);
+        pristine.write(&file, b"regenerated").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(project_path.join(&file)).unwrap(),
+            "regenerated"
+        );
+
+        std::fs::remove_dir_all(project_path).unwrap();
+    }
+}
@@ -0,0 +1,599 @@
+//! Run profiles.
+//!
+//! Every generation setting lives in a TOML profile, so that a run is described
+//! by a file that can be read and reviewed instead of a long command line.
+//! Command line flags are applied on top of the profile, as overrides. Paths
+//! are relative to the directory the engine is run from, like flags.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::Cli;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Path to pokedex file
+    pub pokedex: PathBuf,
+    /// Path to a TOML table of additional species spellings (alias = "species")
+    pub species_aliases: Option<PathBuf>,
+    /// Path to a Showdown moves file, moves are not checked when missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moves: Option<PathBuf>,
+    /// Path to a Showdown learnsets file, needed by the move legality policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub learnsets: Option<PathBuf>,
+    /// List of bundles to use
+    pub bundles: Vec<PathBuf>,
+    pub bundle_checks: BundleCheckPolicy,
+    /// Seed used for the random number generator, random if missing
+    pub seed: Option<u64>,
+    /// Directory to output documentation to
+    pub output_directory: PathBuf,
+    pub trainers: TrainerProfile,
+    pub encounters: EncounterProfile,
+    pub evolutions: EvolutionPolicy,
+    pub pool: PoolPolicy,
+    pub formes: FormePolicy,
+    /// When species with a pokedex tag may appear
+    pub tags: Vec<TagRule>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            pokedex: "pokedex.json".into(),
+            species_aliases: None,
+            moves: None,
+            learnsets: None,
+            bundles: vec![
+                "bundles/default/gen6.bundle.json".into(),
+                "bundles/default/gen7.bundle.json".into(),
+                "bundles/default/gen8.bundle.json".into(),
+                "bundles/default/gen9.bundle.json".into(),
+            ],
+            bundle_checks: BundleCheckPolicy::default(),
+            seed: None,
+            output_directory: "output".into(),
+            trainers: TrainerProfile::default(),
+            encounters: EncounterProfile::default(),
+            evolutions: EvolutionPolicy::default(),
+            pool: PoolPolicy::default(),
+            formes: FormePolicy::default(),
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// How strictly bundle sets are checked when loaded, see [`crate::bundles`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BundleCheckPolicy {
+    /// Sets with more than four move slots, the extra slots being ignored
+    pub move_slots: CheckLevel,
+    /// Empty option lists, the slot or field being left out
+    pub empty_options: CheckLevel,
+    /// EV spreads above 252 in a stat or 510 in total
+    pub evs: CheckLevel,
+    /// IV spreads above 31 in a stat
+    pub ivs: CheckLevel,
+    /// Species missing from the pokedex, whose sets are never used
+    pub unknown_species: CheckLevel,
+    /// Abilities no species of the pokedex has
+    pub unknown_abilities: CheckLevel,
+    /// Items the project does not define, when the backend can tell
+    pub unknown_items: CheckLevel,
+}
+
+impl Default for BundleCheckPolicy {
+    fn default() -> Self {
+        Self {
+            move_slots: CheckLevel::Deny,
+            empty_options: CheckLevel::Deny,
+            evs: CheckLevel::Warn,
+            ivs: CheckLevel::Deny,
+            unknown_species: CheckLevel::Warn,
+            unknown_abilities: CheckLevel::Deny,
+            unknown_items: CheckLevel::Warn,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CheckLevel {
+    /// The problem is ignored
+    Allow,
+    /// The problem is logged
+    Warn,
+    /// The problem fails the run
+    Deny,
+}
+
+/// Policies applied to trainer parties.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrainerProfile {
+    pub evs: EvPolicy,
+    /// Showdown pastes of teams replacing the party of the trainer they are
+    /// named after, see [`crate::formats::showdown`]
+    pub pinned: Vec<PathBuf>,
+    pub levels: LevelPolicy,
+    pub similarity: SimilarityPolicy,
+    pub themes: Vec<ThemeRule>,
+    pub clauses: ClausePolicy,
+    pub legality: LegalityPolicy,
+    pub tiers: TierPolicy,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EvPolicy {
+    /// Keep the EVs of the bundle sets
+    #[default]
+    Bundle,
+    /// Give no EVs to trainer Pokemon
+    None,
+}
+
+/// Clauses parties are generated under, see [`crate::engine::clauses`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClausePolicy {
+    /// How many times a slot breaking a clause is rerolled before giving up
+    pub rerolls: u32,
+    /// Clauses of single battles
+    pub singles: ClauseSet,
+    /// Clauses of double battles
+    pub doubles: ClauseSet,
+}
+
+impl Default for ClausePolicy {
+    fn default() -> Self {
+        Self {
+            rerolls: 20,
+            singles: ClauseSet::default(),
+            doubles: ClauseSet::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClauseSet {
+    /// No two Pokemon of the same species
+    pub species: bool,
+    /// No two Pokemon holding the same item
+    pub item: bool,
+    /// No two Pokemon of the same evolution family
+    pub family: bool,
+    /// No more than this many Pokemon of the same type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_per_type: Option<usize>,
+}
+
+/// Which moves generated sets may know, see [`crate::engine::legality`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LegalityPolicy {
+    pub mode: LegalityMode,
+    /// TMs obtained by each trainer order split, on top of the ones of the
+    /// previous splits
+    pub tms: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LegalityMode {
+    /// Keep the moves of the bundle sets
+    #[default]
+    Off,
+    /// Only keep moves the species can learn one way or another
+    Learnable,
+    /// Only keep moves learned by level up at or below the level of the
+    /// Pokemon, or from a TM obtained by the split of the trainer
+    Level,
+}
+
+/// Which bundle set formats are picked as the game goes, see
+/// [`crate::engine::tiers`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TierPolicy {
+    /// Stages of the game, in order, the last one a Pokemon reached applying
+    pub stages: Vec<TierStage>,
+    /// Formats tried right after the ones of the stage for the Pokemon of
+    /// double battles
+    pub doubles: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TierStage {
+    /// Trainer order split the stage starts at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split: Option<String>,
+    /// Level the stage starts at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<u8>,
+    /// Formats sets are picked from, in order of preference, in which `*`
+    /// stands for any text
+    pub formats: Vec<String>,
+    /// Formats sets are picked from when neither the stage formats nor the
+    /// earlier ones have any, any format when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed: Vec<String>,
+}
+
+/// Type theme of the trainers matching every given criteria, see
+/// [`crate::engine::themes`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeRule {
+    /// Trainer class, such as "Leader"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    /// Trainer id pattern, in which `*` stands for any text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Trainer order split
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split: Option<String>,
+    /// Types the theme is picked from, any type when empty
+    #[serde(default)]
+    pub types: Vec<String>,
+    /// How many Pokemon have the theme type, the whole party when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at_least: Option<usize>,
+}
+
+/// Which species can replace a species, see [`crate::engine::similarity`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimilarityPolicy {
+    pub band: BandKind,
+    /// How far above the original BST the asymmetric band goes
+    pub plus: u32,
+    /// How far below the original BST the asymmetric band goes
+    pub minus: u32,
+    /// Width of the percent band on each side of the original BST
+    pub percent: u32,
+    /// Only keep species attacking on the same side and as fast
+    pub stat_distribution: bool,
+    /// Prefer species sharing a type with the original
+    pub prefer_shared_type: bool,
+    /// Widen the band until it gives at least this many candidates, 0 to
+    /// never widen it
+    pub min_candidates: usize,
+    /// How much the band is widened at a time
+    pub widen_step: u32,
+}
+
+impl Default for SimilarityPolicy {
+    fn default() -> Self {
+        Self {
+            band: BandKind::default(),
+            plus: 30,
+            minus: 30,
+            percent: 10,
+            stat_distribution: false,
+            prefer_shared_type: false,
+            min_candidates: 1,
+            widen_step: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BandKind {
+    /// From `minus` below to `plus` above the original BST
+    #[default]
+    Asymmetric,
+    /// Within `percent` percent of the original BST
+    Percent,
+}
+
+/// How party levels are set, see [`crate::engine::levels`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LevelPolicy {
+    pub mode: LevelMode,
+    /// Boss Pokemon other than the ace are between 1 and this many levels
+    /// below the cap
+    pub boss_spread: u8,
+    /// The highest level Pokemon of other trainers is this many levels below
+    /// the cap
+    pub route_margin: u8,
+}
+
+impl Default for LevelPolicy {
+    fn default() -> Self {
+        Self {
+            mode: LevelMode::default(),
+            boss_spread: 3,
+            route_margin: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LevelMode {
+    /// Keep the levels of the original parties
+    #[default]
+    Original,
+    /// Rescale the levels against the level cap of each trainer, which needs
+    /// a trainer order
+    LevelCap,
+}
+
+/// Species the engine may pick, for trainers and wild encounters alike, see
+/// [`crate::engine::pool`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolPolicy {
+    /// Generations species are taken from, every generation when empty
+    pub generations: Vec<u8>,
+    /// Path to a regional dex, only its species being taken when given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regional_dex: Option<PathBuf>,
+    /// Only take the species the project defines
+    pub project_species_only: bool,
+    /// Species added to the pool whatever the above
+    pub include: Vec<String>,
+    /// Species removed from the pool whatever the above
+    pub exclude: Vec<String>,
+}
+
+/// How every class of alternate formes may appear, see
+/// [`crate::engine::formes`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormePolicy {
+    pub regional: SpeciesFormes,
+    pub cosmetic: CosmeticFormes,
+    pub battle_only: BattleFormes,
+    pub mega: BattleFormes,
+    pub gigantamax: BattleFormes,
+    pub other: SpeciesFormes,
+}
+
+impl Default for FormePolicy {
+    fn default() -> Self {
+        Self {
+            regional: SpeciesFormes::Species,
+            cosmetic: CosmeticFormes::Variant,
+            battle_only: BattleFormes::Never,
+            mega: BattleFormes::ThroughBase,
+            gigantamax: BattleFormes::Never,
+            other: SpeciesFormes::Never,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpeciesFormes {
+    /// The forme is a species of its own, with its own stats and types
+    Species,
+    /// The forme never appears
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CosmeticFormes {
+    /// The base species is shown as any of its formes
+    Variant,
+    /// The base species is always shown as itself
+    Base,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BattleFormes {
+    /// The sets of the forme go to its base species, holding the item or
+    /// having the Gigantamax factor the forme needs
+    ThroughBase,
+    /// The forme never appears
+    Never,
+}
+
+/// When the species with a pokedex tag may appear, see
+/// [`crate::engine::tags`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TagRule {
+    /// Pokedex tag, such as "Sub-Legendary"
+    pub tag: String,
+    #[serde(default)]
+    pub trainers: Availability,
+    #[serde(default)]
+    pub wild: Availability,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Availability {
+    #[default]
+    Always,
+    Never,
+    /// From the given trainer order split onwards
+    From(String),
+}
+
+impl Display for Availability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Availability::Always => write!(f, "always"),
+            Availability::Never => write!(f, "never"),
+            Availability::From(split) => write!(f, "from {split}"),
+        }
+    }
+}
+
+/// How the evolution stage of a replacement is chosen, for trainers and wild
+/// encounters alike, see [`crate::engine::evolution`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvolutionPolicy {
+    pub mode: EvolutionMode,
+    /// Level from which evolutions by item are considered reached
+    pub item_level: u8,
+    /// Level from which evolutions by trade are considered reached
+    pub trade_level: u8,
+    /// Level from which evolutions by friendship are considered reached
+    pub friendship_level: u8,
+    /// Level from which other evolutions without a level are considered
+    /// reached
+    pub other_level: u8,
+}
+
+impl Default for EvolutionPolicy {
+    fn default() -> Self {
+        Self {
+            mode: EvolutionMode::default(),
+            item_level: 30,
+            trade_level: 36,
+            friendship_level: 25,
+            other_level: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EvolutionMode {
+    /// Pick any species with a close BST, whatever its evolution stage
+    #[default]
+    Off,
+    /// Pick a family with a close BST, then its stage fitting the level
+    Stage,
+}
+
+/// Policies applied to wild encounters.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncounterProfile {
+    pub mode: EncounterMode,
+    pub similarity: SimilarityPolicy,
+    /// Trainer order split of the maps, by map constant
+    pub map_splits: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EncounterMode {
+    /// Each map replaces its species independently
+    #[default]
+    Local,
+    /// A species is replaced by the same species on every map
+    Global,
+}
+
+impl Profile {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    /// Loads the profile given on the command line, the default one otherwise,
+    /// and applies the command line flags on top of it.
+    pub fn resolve(cli: &Cli) -> eyre::Result<Self> {
+        let mut profile = match cli.profile {
+            Some(ref path) => Self::load(path)?,
+            None => Self::default(),
+        };
+
+        if let Some(ref pokedex) = cli.pokedex {
+            profile.pokedex = pokedex.clone();
+        }
+        if let Some(ref species_aliases) = cli.species_aliases {
+            profile.species_aliases = Some(species_aliases.clone());
+        }
+        if let Some(ref moves) = cli.moves {
+            profile.moves = Some(moves.clone());
+        }
+        if let Some(ref learnsets) = cli.learnsets {
+            profile.learnsets = Some(learnsets.clone());
+        }
+        if !cli.bundles.is_empty() {
+            profile.bundles = cli.bundles.clone();
+        }
+        if cli.seed.is_some() {
+            profile.seed = cli.seed;
+        }
+        if let Some(ref output_directory) = cli.output_directory {
+            profile.output_directory = output_directory.clone();
+        }
+        if cli.disable_evs {
+            profile.trainers.evs = EvPolicy::None;
+        }
+        if let Some(mode) = cli.encounter_mode {
+            profile.encounters.mode = mode;
+        }
+
+        Ok(profile)
+    }
+
+    pub fn to_toml(&self) -> eyre::Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn cli(args: &[&str]) -> Cli {
+        let args = ["nuzlocke-generator-engine"].iter().chain(args);
+        Cli::try_parse_from(args.chain(&["emerald-expansion"])).unwrap()
+    }
+
+    #[test]
+    fn loads_the_default_profile() {
+        let profile = Profile::load(Path::new("profiles/default.toml")).unwrap();
+        assert_eq!(
+            profile.to_toml().unwrap(),
+            Profile::default().to_toml().unwrap()
+        );
+    }
+
+    #[test]
+    fn lets_the_command_line_override_the_profile() {
+        let profile = Profile::resolve(&cli(&["--profile", "profiles/default.toml"])).unwrap();
+        assert_eq!(profile.seed, None);
+        assert_eq!(profile.output_directory, Path::new("output"));
+        assert_eq!(profile.trainers.evs, EvPolicy::Bundle);
+        assert_eq!(profile.encounters.mode, EncounterMode::Local);
+
+        let profile = Profile::resolve(&cli(&[
+            "--profile",
+            "profiles/default.toml",
+            "--seed",
+            "42",
+            "--output-directory",
+            "elsewhere",
+            "--disable-evs",
+            "--encounter-mode",
+            "global",
+            "--bundles",
+            "mine.bundle.json",
+        ]))
+        .unwrap();
+        assert_eq!(profile.seed, Some(42));
+        assert_eq!(profile.output_directory, Path::new("elsewhere"));
+        assert_eq!(profile.trainers.evs, EvPolicy::None);
+        assert_eq!(profile.encounters.mode, EncounterMode::Global);
+        assert_eq!(profile.bundles, [Path::new("mine.bundle.json")]);
+    }
+}